@@ -0,0 +1,101 @@
+// Dimensionless Developments Rust Ai
+// # Activity Panel Component
+// Shows what the agent is doing for an answer - model rounds, searches,
+// result counts, pages read - as the server reports it. Open while the
+// answer is being worked on, collapsed (but expandable) once it is in.
+
+use std::collections::HashMap;
+
+use leptos::*;
+
+use crate::protocol::ActivityStep;
+
+const PANEL_CLASS: &str = "mb-2 rounded bg-white bg-opacity-60 px-2 py-1 text-xs text-gray-700";
+const SUMMARY_CLASS: &str = "cursor-pointer select-none font-semibold text-gray-600";
+
+/// What the agent did for one assistant message.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MessageActivity {
+    pub steps: Vec<ActivityStep>,
+
+    /// The answer (or an error) has arrived
+    pub done: bool,
+}
+
+/// Activity per assistant message, keyed by the message's index in the
+/// conversation.
+pub type ActivityLog = HashMap<usize, MessageActivity>;
+
+#[component]
+pub fn ActivityPanel(activity: MessageActivity) -> impl IntoView {
+    let summary = if activity.done {
+        summarize(&activity.steps)
+    } else {
+        let current = activity.steps.last().map(label).unwrap_or_default();
+        format!("Working… {}", current)
+    };
+
+    // A native <details> keeps the open/closed state without any signals
+    view! {
+        <details class=PANEL_CLASS open=!activity.done>
+            <summary class=SUMMARY_CLASS>{summary}</summary>
+            <ul class="mt-1 space-y-0.5">
+                {activity.steps.iter().map(|step| view! {
+                    <li class="truncate" title=label(step)>{label(step)}</li>
+                }).collect_view()}
+            </ul>
+        </details>
+    }
+}
+
+/// One line of the panel.
+fn label(step: &ActivityStep) -> String {
+    match step {
+        ActivityStep::Thinking { round, max_rounds } => {
+            format!("🧠 Thinking (round {} of {})", round, max_rounds)
+        }
+        ActivityStep::Searching { query } => format!("🔎 Searching: {}", query),
+        ActivityStep::Searched { error: Some(error), .. } => format!("⚠️ Search failed: {}", error),
+        ActivityStep::Searched { results, .. } => format!("↳ {} results", results),
+        ActivityStep::SearchedDocuments { query, results } => {
+            format!("📎 Searched attachments: {} ({} passages)", query, results)
+        }
+        ActivityStep::Fetching { url } => format!("📄 Reading {}", url),
+        ActivityStep::Fetched { error: Some(error), .. } => format!("⚠️ Could not read page: {}", error),
+        ActivityStep::Fetched { chars, .. } => format!("↳ {} characters read", chars),
+    }
+}
+
+/// The collapsed panel's title, e.g. "2 searches · 9 results · 1 page read · 3 rounds".
+fn summarize(steps: &[ActivityStep]) -> String {
+    let mut searches = 0;
+    let mut results = 0;
+    let mut pages = 0;
+    let mut rounds = 0;
+    for step in steps {
+        match step {
+            ActivityStep::Searching { .. } => searches += 1,
+            ActivityStep::Searched { results: n, .. } => results += n,
+            ActivityStep::SearchedDocuments { results: n, .. } => {
+                searches += 1;
+                results += n;
+            }
+            ActivityStep::Fetched { error: None, .. } => pages += 1,
+            ActivityStep::Thinking { round, .. } => rounds = rounds.max(*round),
+            _ => {}
+        }
+    }
+
+    let plural = |n: usize, one: &str, many: &str| format!("{} {}", n, if n == 1 { one } else { many });
+    let mut parts = vec![
+        plural(searches, "search", "searches"),
+        plural(results, "result", "results"),
+    ];
+    if pages > 0 {
+        parts.push(plural(pages, "page read", "pages read"));
+    }
+    if rounds > 0 {
+        parts.push(plural(rounds, "round", "rounds"));
+    }
+    parts.join(" · ")
+}
@@ -1,22 +1,168 @@
 // Dimensionless Developments Rust Ai
 
+use std::collections::HashMap;
+
+use gloo_timers::callback::Interval;
 use leptos::{*, html::Div};
 
+use crate::components::activity_panel::{ActivityLog, ActivityPanel};
+use crate::components::markdown::{Citations, Markdown};
+use crate::components::preferences::Preferences;
+use crate::components::source_cards::{SourceCards, SourceLog};
 use crate::model::conversation::Conversation;
+use crate::protocol::AnswerMeta;
+
+const USER_MESSAGE_STYLE: &str = "px-3 bg-orange-500 rounded text-white";
+const MODEL_MESSAGE_STYLE: &str = "px-3.5 bg-blue-100 rounded text-gray-900";
 
-const USER_MESSAGE_STYLE: &str = "px-3 py-2 bg-orange-500 rounded text-white";
-const MODEL_MESSAGE_STYLE: &str = "px-3.5 py-2 bg-blue-100 rounded text-gray-900";
+const ACTION_CLASS: &str = "text-gray-500 text-xs hover:text-orange-500";
+const EDIT_BOX_CLASS: &str = "w-72 max-w-full px-2 py-1 rounded border border-orange-500 text-sm text-gray-900 focus:outline-none";
 
-const CHAT_AREA_CLASS: &str = "h-full w-full flex flex-col overflow-y-auto p-5";
+const CHAT_AREA_CLASS: &str = "h-full w-full flex flex-col overflow-y-auto";
 const CHAT_AREA_LIGHT_MODE_COLORS: &str = "bg-gray-50";
 const CHAT_AREA_DARK_MODE_COLORS: &str = "bg-gradient-to-br from-gray-900 via-gray-800 to-gray-900";
 
+/// How often relative times ("5 min ago") are brought up to date.
+const CLOCK_TICK_MS: u32 = 30_000;
+
+/// How each assistant message was made, keyed by the message's index in
+/// the conversation.
+pub type MetaLog = HashMap<usize, AnswerMeta>;
+
+// =============================================================================
+// TIMES
+// =============================================================================
+/// Message timestamp for `ms` milliseconds since the Unix epoch, as an
+/// ISO 8601 string (e.g. `2024-05-01T09:30:00.000Z`).
+pub fn timestamp_at(ms: f64) -> String {
+    String::from(js_sys::Date::new(&ms.into()).to_iso_string())
+}
+
+/// Message timestamp for the present moment.
+pub fn now_timestamp() -> String {
+    timestamp_at(js_sys::Date::now())
+}
+
+/// Milliseconds since the epoch of a timestamp; `None` for ones saved
+/// before timestamps were recorded (e.g. "now").
+fn timestamp_ms(timestamp: &str) -> Option<f64> {
+    let ms = js_sys::Date::parse(timestamp);
+    (!ms.is_nan()).then_some(ms)
+}
+
+/// "just now", "5 min ago", "3 h ago", "2 d ago", then the date.
+fn relative_time(timestamp: &str, now: f64) -> String {
+    let Some(ms) = timestamp_ms(timestamp) else {
+        return timestamp.to_string();
+    };
+    let minutes = ((now - ms) / 60_000.0).max(0.0) as u64;
+    match minutes {
+        0 => "just now".to_string(),
+        1..=59 => format!("{} min ago", minutes),
+        60..=1_439 => format!("{} h ago", minutes / 60),
+        1_440..=10_079 => format!("{} d ago", minutes / 1_440),
+        _ => String::from(
+            js_sys::Date::new(&ms.into()).to_locale_date_string("default", &wasm_bindgen::JsValue::UNDEFINED),
+        ),
+    }
+}
+
+/// Date and time in the browser's locale, for the tooltip.
+fn absolute_time(timestamp: &str) -> String {
+    match timestamp_ms(timestamp) {
+        Some(ms) => String::from(
+            js_sys::Date::new(&ms.into()).to_locale_string("default", &wasm_bindgen::JsValue::UNDEFINED),
+        ),
+        None => timestamp.to_string(),
+    }
+}
+
+/// One line about how an answer was made, e.g.
+/// "llama3.2 · 12.4 s · 3 searches · 1,850 tokens".
+fn meta_summary(meta: &AnswerMeta) -> String {
+    let mut parts = vec![meta.model.clone().unwrap_or_else(|| "Quick search".to_string())];
+    parts.push(format!("{:.1} s", meta.duration_ms as f64 / 1000.0));
+    parts.push(match meta.searches {
+        1 => "1 search".to_string(),
+        n => format!("{} searches", n),
+    });
+    if meta.model.is_some() {
+        parts.push(format!("{} tokens", group_digits(meta.input_tokens + meta.output_tokens)));
+    }
+    parts.join(" · ")
+}
+
+/// The tooltip of `meta_summary`, with the token counts split up.
+fn meta_details(meta: &AnswerMeta) -> String {
+    let mut details = format!(
+        "Model: {}\nTime: {:.1} s\nSearches: {}",
+        meta.model.as_deref().unwrap_or("none (quick search)"),
+        meta.duration_ms as f64 / 1000.0,
+        meta.searches,
+    );
+    if meta.model.is_some() {
+        details.push_str(&format!(
+            "\nTokens: {} in, {} out",
+            group_digits(meta.input_tokens),
+            group_digits(meta.output_tokens),
+        ));
+    }
+    details
+}
+
+/// 1850 -> "1,850".
+fn group_digits(n: u64) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+// =============================================================================
+// COMPONENT
+// =============================================================================
+
 #[component]
-pub fn ChatArea(conversation: ReadSignal<Conversation>) -> impl IntoView {
+pub fn ChatArea(
+    conversation: ReadSignal<Conversation>,
+    activity: ReadSignal<ActivityLog>,
+    sources: ReadSignal<SourceLog>,
+    meta: ReadSignal<MetaLog>,
+    #[prop(into)] busy: Signal<bool>,
+    /// Ask the question before this assistant message again
+    on_regenerate: Callback<usize>,
+    /// Ask an edited version of this user message in a new thread
+    on_edit: Callback<(usize, String)>,
+) -> impl IntoView {
     let dark_mode = use_context::<ReadSignal<bool>>()
         .expect("should be able to get dark mode state");
+    let preferences = use_context::<ReadSignal<Preferences>>()
+        .expect("should be able to get the preferences");
+    let compact = move || preferences.with(|p| p.compact);
 
     let chat_div_ref = create_node_ref::<Div>();
+
+    // Keeps "5 min ago" current
+    let (now, set_now) = create_signal(js_sys::Date::now());
+    let clock = Interval::new(CLOCK_TICK_MS, move || set_now.set(js_sys::Date::now()));
+    on_cleanup(move || clock.cancel());
+    let time = move |timestamp: String| {
+        let title = absolute_time(&timestamp);
+        view! {
+            <time datetime=timestamp.clone() title=title>
+                {move || relative_time(&timestamp, now.get())}
+            </time>
+        }
+    };
+
+    // The user message being edited, and its new text
+    let (editing, set_editing) = create_signal(None::<usize>);
+    let (draft, set_draft) = create_signal(String::new());
     
     // Track message count to trigger scroll without reading full conversation
     let message_count = create_memo(move |_| conversation.get().messages.len());
@@ -29,73 +175,130 @@ pub fn ChatArea(conversation: ReadSignal<Conversation>) -> impl IntoView {
     });
 
     let chat_area_class = Signal::derive(move || {
+        let padding = if compact() { "p-2" } else { "p-5" };
         if dark_mode.get() {
-            format!("{CHAT_AREA_CLASS} {CHAT_AREA_DARK_MODE_COLORS}")
+            format!("{CHAT_AREA_CLASS} {padding} {CHAT_AREA_DARK_MODE_COLORS}")
         } else {
-            format!("{CHAT_AREA_CLASS} {CHAT_AREA_LIGHT_MODE_COLORS}")
+            format!("{CHAT_AREA_CLASS} {padding} {CHAT_AREA_LIGHT_MODE_COLORS}")
         }
     });
 
     view! {
-        <div class={chat_area_class.get()} node_ref=chat_div_ref>
+        <div class=move || chat_area_class.get() node_ref=chat_div_ref>
             <div class="w-full pb-40">
                 {move || {
                     let messages = conversation.get().messages;
-                    messages.iter().map(|message| {
-                        if message.user {
+                    let activity = activity.get();
+                    let sources = sources.get();
+                    let meta = meta.get();
+                    let busy = busy.get();
+                    let last = messages.len().saturating_sub(1);
+
+                    // Compact mode: less space around and between messages
+                    let (row, bubble, avatar) = if compact() {
+                        ("mb-1.5", "py-1", "w-6 h-6 rounded-full")
+                    } else {
+                        ("mb-4", "py-2", "w-10 h-10 rounded-full")
+                    };
+                    messages.iter().enumerate().map(|(index, message)| {
+                        if message.user && editing.get() == Some(index) {
+                            let save = move || {
+                                let text = draft.get_untracked().trim().to_string();
+                                if !text.is_empty() {
+                                    set_editing.set(None);
+                                    on_edit.call((index, text));
+                                }
+                            };
                             view! {
-                                <div class="flex gap-2.5 justify-end mb-4">
+                                <div class=format!("flex gap-2.5 justify-end {}", row)>
+                                    <div class="grid">
+                                        <textarea
+                                            class=EDIT_BOX_CLASS
+                                            rows="3"
+                                            prop:value=draft.get_untracked()
+                                            on:input=move |ev| set_draft.set(event_target_value(&ev))
+                                        ></textarea>
+                                        <div class="flex justify-end gap-3 py-1">
+                                            <button class=ACTION_CLASS on:click=move |_| set_editing.set(None)>"Cancel"</button>
+                                            <button
+                                                class=ACTION_CLASS
+                                                title="Ask this in a new thread; this one stays as it is"
+                                                disabled=busy
+                                                on:click=move |_| save()
+                                            >
+                                                "Save & send"
+                                            </button>
+                                        </div>
+                                    </div>
+                                </div>
+                            }
+                        } else if message.user {
+                            let text = message.text.clone();
+                            let start_editing = move |_| {
+                                set_draft.set(text.clone());
+                                set_editing.set(Some(index));
+                            };
+                            view! {
+                                <div class=format!("flex gap-2.5 justify-end {}", row)>
                                     <div class="grid">
                                         <div class="text-right text-gray-400 text-sm font-semibold leading-snug pb-1">
                                             {&message.sender_name}
                                         </div>
-                                        <div class={USER_MESSAGE_STYLE}>
+                                        <div class=format!("{USER_MESSAGE_STYLE} {bubble}")>
                                             <h2 class="text-white text-sm font-normal leading-snug">
                                                 {&message.text}
                                             </h2>
                                         </div>
-                                        <div class="text-right text-gray-500 text-xs font-normal leading-4 py-1">
-                                            {&message.timestamp}
+                                        <div class="flex justify-end gap-3 text-gray-500 text-xs font-normal leading-4 py-1">
+                                            <Show when=move || !busy>
+                                                <button class=ACTION_CLASS title="Edit and ask again" on:click=start_editing.clone()>
+                                                    "✎ Edit"
+                                                </button>
+                                            </Show>
+                                            {time(message.timestamp.clone())}
                                         </div>
                                     </div>
                                 </div>
                             }
                         } else {
-                            // Split message text into paragraphs for better formatting
-                            let text = message.text.clone(); // Clone to own the string
-                            let paragraphs: Vec<String> = text.split("\n\n").map(|s| s.to_string()).collect();
-                            
+                            let panel = activity.get(&index).cloned().map(|activity| view! {
+                                <ActivityPanel activity/>
+                            });
+                            let message_sources = sources.get(&index).cloned().unwrap_or_default();
+                            let citations = (!message_sources.is_empty()).then_some(Citations {
+                                message: index,
+                                count: message_sources.len(),
+                            });
+                            let cards = citations.map(|_| view! {
+                                <SourceCards sources=message_sources message=index/>
+                            });
+                            // How the answer was made, shown on hover
+                            let details = meta.get(&index).map(|meta| view! {
+                                <span class="hidden group-hover:inline" title=meta_details(meta)>
+                                    {meta_summary(meta)}
+                                </span>
+                            });
+
                             view! {
-                                <div class="flex gap-2.5 mb-4">
-                                    <img src="https://cdn-icons-png.freepik.com/512/1404/1404288.png" alt="assistant" class="w-10 h-10 rounded-full" />
+                                <div class=format!("group flex gap-2.5 {}", row)>
+                                    <img src="https://cdn-icons-png.freepik.com/512/1404/1404288.png" alt="assistant" class=avatar />
                                     <div class="grid">
                                         <h5 class="text-gray-400 text-sm font-semibold leading-snug pb-1">
                                             {&message.sender_name}
                                         </h5>
-                                        <div class={MODEL_MESSAGE_STYLE}>
-                                            {paragraphs.iter().map(|para| {
-                                                // Split by single newlines for line breaks within paragraphs
-                                                let lines: Vec<String> = para.split('\n').map(|s| s.to_string()).collect();
-                                                view! {
-                                                    <div class="mb-3 last:mb-0">
-                                                        {lines.iter().enumerate().map(|(i, line)| {
-                                                            view! {
-                                                                <>
-                                                                    <span class="text-sm font-normal leading-snug">{line.clone()}</span>
-                                                                    {if i < lines.len() - 1 {
-                                                                        view! { <br /> }.into_view()
-                                                                    } else {
-                                                                        view! { <></> }.into_view()
-                                                                    }}
-                                                                </>
-                                                            }
-                                                        }).collect_view()}
-                                                    </div>
-                                                }
-                                            }).collect_view()}
+                                        <div class=format!("{MODEL_MESSAGE_STYLE} {bubble}")>
+                                            {panel}
+                                            <Markdown text=message.text.clone() citations/>
+                                            {cards}
                                         </div>
-                                        <div class="text-gray-500 text-xs font-normal leading-4 py-1">
-                                            {&message.timestamp}
+                                        <div class="flex gap-3 text-gray-500 text-xs font-normal leading-4 py-1">
+                                            {time(message.timestamp.clone())}
+                                            {details}
+                                            <Show when=move || !busy && index == last>
+                                                <button class=ACTION_CLASS title="Ask again for a new answer" on:click=move |_| on_regenerate.call(index)>
+                                                    "↻ Regenerate"
+                                                </button>
+                                            </Show>
                                         </div>
                                     </div>
                                 </div>
@@ -0,0 +1,62 @@
+// Dimensionless Developments Rust Ai
+// # Connection Status Component
+// A small pill in the corner of the chat showing whether the WebSocket to
+// the research server is up, and when the next reconnect attempt is.
+
+use leptos::*;
+
+const PILL_CLASS: &str = "fixed top-3 right-3 z-50 flex items-center gap-2 px-3 py-1 rounded-full text-xs font-semibold shadow";
+
+/// State of the chat's WebSocket connection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionStatus {
+    /// First connection attempt in progress
+    Connecting,
+
+    /// Connected and resumed (or started) a session
+    Connected,
+
+    /// Connection lost; `attempt` starts at 1 and the next try is in
+    /// `retry_in_secs` seconds
+    Reconnecting { attempt: u32, retry_in_secs: u32 },
+
+    /// The page is leaving the chat; no more attempts
+    Closed,
+}
+
+impl ConnectionStatus {
+    fn label(&self) -> String {
+        match self {
+            ConnectionStatus::Connecting => "Connecting…".to_string(),
+            ConnectionStatus::Connected => "Connected".to_string(),
+            ConnectionStatus::Reconnecting { attempt, retry_in_secs } => {
+                format!("Offline - retrying in {}s (attempt {})", retry_in_secs, attempt)
+            }
+            ConnectionStatus::Closed => "Disconnected".to_string(),
+        }
+    }
+
+    fn colors(&self) -> (&'static str, &'static str) {
+        // (pill, dot)
+        match self {
+            ConnectionStatus::Connected => ("bg-green-100 text-green-800", "bg-green-500"),
+            ConnectionStatus::Connecting => ("bg-yellow-100 text-yellow-800", "bg-yellow-500"),
+            ConnectionStatus::Reconnecting { .. } | ConnectionStatus::Closed => {
+                ("bg-red-100 text-red-800", "bg-red-500")
+            }
+        }
+    }
+}
+
+#[component]
+pub fn ConnectionIndicator(status: ReadSignal<ConnectionStatus>) -> impl IntoView {
+    view! {
+        <div
+            class=move || format!("{PILL_CLASS} {}", status.get().colors().0)
+            title="Connection to the research server"
+        >
+            <span class=move || format!("inline-block w-2 h-2 rounded-full {}", status.get().colors().1)></span>
+            <span>{move || status.get().label()}</span>
+        </div>
+    }
+}
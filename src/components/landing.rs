@@ -3,27 +3,44 @@
 use leptos::*;
 use leptos_router::*;
 
+use crate::components::preferences::{themed, ThemeToggle};
+
 #[component]
 pub fn Landing() -> impl IntoView {
+    let dark_mode = use_context::<ReadSignal<bool>>()
+        .expect("should be able to get dark mode state");
+
+    let page = themed(
+        dark_mode,
+        "bg-gradient-to-br from-gray-900 via-gray-800 to-gray-900",
+        "bg-gradient-to-br from-gray-50 via-white to-gray-100",
+    );
+    let bar = themed(dark_mode, "border-gray-700 bg-gray-900/80", "border-gray-200 bg-white/80");
+    let card = themed(dark_mode, "bg-gray-800/50 border-gray-700", "bg-white border-gray-200 shadow");
+    let heading = themed(dark_mode, "text-white", "text-gray-900");
+    let body = themed(dark_mode, "text-gray-300", "text-gray-700");
+    let muted = themed(dark_mode, "text-gray-400", "text-gray-500");
+
     view! {
-        <div class="min-h-screen bg-gradient-to-br from-gray-900 via-gray-800 to-gray-900">
+        <div class=move || format!("min-h-screen {}", page())>
             {/* Navigation */}
-            <nav class="border-b border-gray-700 bg-gray-900/80 backdrop-blur">
+            <nav class=move || format!("border-b backdrop-blur {}", bar())>
                 <div class="max-w-7xl mx-auto px-4 sm:px-6 lg:px-8">
                     <div class="flex justify-between items-center h-16">
                         <div class="text-2xl font-bold text-orange-500">
                             "Dimensionless Rust AI 🚀"
                         </div>
-                        <div class="flex space-x-4">
-                            <a href="#about" class="text-gray-300 hover:text-orange-500 transition">
+                        <div class="flex items-center space-x-4">
+                            <a href="#about" class=move || format!("hover:text-orange-500 transition {}", body())>
                                 "About"
                             </a>
-                            <a href="#features" class="text-gray-300 hover:text-orange-500 transition">
+                            <a href="#features" class=move || format!("hover:text-orange-500 transition {}", body())>
                                 "Features"
                             </a>
-                            <a href="/chat" class="text-gray-300 hover:text-orange-500 transition">
+                            <a href="/chat" class=move || format!("hover:text-orange-500 transition {}", body())>
                                 "Chat Now!"
                             </a>
+                            <ThemeToggle/>
                         </div>
                     </div>
                 </div>
@@ -32,10 +49,10 @@ pub fn Landing() -> impl IntoView {
             {/* Hero Section */}
             <section class="max-w-7xl mx-auto px-4 sm:px-6 lg:px-8 py-20">
                 <div class="text-center">
-                    <h1 class="text-5xl sm:text-7xl font-bold text-white mb-6">
+                    <h1 class=move || format!("text-5xl sm:text-7xl font-bold mb-6 {}", heading())>
                         "Welcome to Dimensionless Rust Ai 🚀"
                     </h1>
-                    <p class="text-xl text-gray-400 mb-12 max-w-2xl mx-auto">
+                    <p class=move || format!("text-xl mb-12 max-w-2xl mx-auto {}", muted())>
                         "An advanced AI research agent built with Rust and cutting-edge technology. Experience fast, reliable, and powerful AI-powered research at your fingertips."
                     </p>
                 </div>
@@ -43,17 +60,17 @@ pub fn Landing() -> impl IntoView {
 
             {/* About Section */}
             <section id="about" class="max-w-7xl mx-auto px-4 sm:px-6 lg:px-8 py-20">
-                <div class="bg-gray-800/50 backdrop-blur border hover:bg-orange-500 border-gray-700 rounded-lg p-12 transition">
-                    <h2 class="text-4xl font-bold text-white mb-6 text-center">
+                <div class=move || format!("backdrop-blur border hover:bg-orange-500 rounded-lg p-12 transition {}", card())>
+                    <h2 class=move || format!("text-4xl font-bold mb-6 text-center {}", heading())>
                         "About Dimensionless Rust AI:"
                     </h2>
-                    <p class="text-lg text-gray-300 mb-4">
+                    <p class=move || format!("text-lg mb-4 {}", body())>
                         "Dimensionless Rust AI (DRA) is a state-of-the-art AI research agent built with Rust, Leptos, and WebAssembly. We combine the performance of Rust with modern web frameworks to deliver seamless AI-powered research."
                     </p>
-                    <p class="text-lg text-gray-300 mb-4">
+                    <p class=move || format!("text-lg mb-4 {}", body())>
                         "Powered by advanced language models through Ollama, our agent can search the web, understand context, and provide comprehensive research summaries. Whether you need information, analysis, or insights, Dimensionless Rust AI is here to help."
                     </p>
-                    <p class="text-lg text-gray-300">
+                    <p class=move || format!("text-lg {}", body())>
                         "Experience the future of AI-powered research with an agent that's as fast and reliable as Rust itself."
                     </p>
                 </div>
@@ -61,20 +78,20 @@ pub fn Landing() -> impl IntoView {
 
             {/* Tech-stack Section */}
             <section id="about" class="max-w-7xl mx-auto px-4 sm:px-6 lg:px-8 py-20">
-                <div class="bg-gray-800/50 backdrop-blur border hover:bg-orange-500 border-gray-700 rounded-lg p-12 transition">
-                    <h2 class="text-4xl font-bold text-white mb-6 text-center">
+                <div class=move || format!("backdrop-blur border hover:bg-orange-500 rounded-lg p-12 transition {}", card())>
+                    <h2 class=move || format!("text-4xl font-bold mb-6 text-center {}", heading())>
                         "Technical Stack of Dimensionless Rust AI:"
                     </h2>
-                    <p class="text-lg text-gray-300 mb-4">
+                    <p class=move || format!("text-lg mb-4 {}", body())>
                         "- A full-stack AI research agent built with Rust backend and Leptos WebAssembly frontend. This project combines a CLI tool for AI research with a modern web interface, demonstrating the complete AI agent architecture from LLM integration to real-time web communication."
                     </p>
-                    <p class="text-lg text-gray-300 mb-4">
+                    <p class=move || format!("text-lg mb-4 {}", body())>
                         "- Uses Ollama for privacy-friendly, free AI inference DuckDuckGo integration (no API key required!)"
                     </p>
-                    <p class="text-lg text-gray-300">
+                    <p class=move || format!("text-lg {}", body())>
                         "- Demonstrates agentic AI patterns with function calling Modern Leptos + TailwindCSS frontend with real-time WebSocket chat."
                     </p>
-                    <p class="text-lg text-gray-300">
+                    <p class=move || format!("text-lg {}", body())>
                         "- Tokio-based backend with concurrent request handling    "
                     </p>
                 </div>
@@ -82,42 +99,42 @@ pub fn Landing() -> impl IntoView {
 
             {/* Features Section */}
             <section id="features" class="max-w-7xl mx-auto px-4 sm:px-6 lg:px-8 py-20">
-                <h2 class="text-4xl font-bold text-white mb-12 text-center">
+                <h2 class=move || format!("text-4xl font-bold mb-12 text-center {}", heading())>
                     "Why Choose Dimensionless Rust AI?"
                 </h2>
                 <div class="grid md:grid-cols-3 gap-8">
-                    <div class="bg-gray-800/50 backdrop-blur border border-gray-700 rounded-lg p-8 hover:bg-orange-500 hover:border-orange-500 transition">
+                    <div class=move || format!("backdrop-blur border rounded-lg p-8 hover:bg-orange-500 hover:border-orange-500 transition {}", card())>
                         <div class="text-4xl mb-4">
                             "⚡"
                         </div>
-                        <h3 class="text-2xl font-bold text-white mb-4">
+                        <h3 class=move || format!("text-2xl font-bold mb-4 {}", heading())>
                             "Lightning Fast"
                         </h3>
-                        <p class="text-gray-200">
+                        <p class=body>
                             "Built with Rust for exceptional performance. Experience instant responses with local AI models via Ollama."
                         </p>
                     </div>
 
-                    <div class="bg-gray-800/50 backdrop-blur border border-gray-700 rounded-lg p-8 hover:bg-orange-500 hover:border-orange-500 transition">
+                    <div class=move || format!("backdrop-blur border rounded-lg p-8 hover:bg-orange-500 hover:border-orange-500 transition {}", card())>
                         <div class="text-4xl mb-4">
                             "🔒"
                         </div>
-                        <h3 class="text-2xl font-bold text-white mb-4">
+                        <h3 class=move || format!("text-2xl font-bold mb-4 {}", heading())>
                             "Private & Secure"
                         </h3>
-                        <p class="text-gray-200">
+                        <p class=body>
                             "Your data stays on your machine. All AI processing happens locally with no external API calls."
                         </p>
                     </div>
 
-                    <div class="bg-gray-800/50 backdrop-blur border border-gray-700 rounded-lg p-8 hover:bg-orange-500 hover:border-orange-500 transition">
+                    <div class=move || format!("backdrop-blur border rounded-lg p-8 hover:bg-orange-500 hover:border-orange-500 transition {}", card())>
                         <div class="text-4xl mb-4">
                             "🔍"
                         </div>
-                        <h3 class="text-2xl font-bold text-white mb-4">
+                        <h3 class=move || format!("text-2xl font-bold mb-4 {}", heading())>
                             "Web Search Integration"
                         </h3>
-                        <p class="text-gray-200">
+                        <p class=body>
                             "Automatically searches the web and synthesizes information from multiple sources for comprehensive answers."
                         </p>
                     </div>
@@ -127,10 +144,10 @@ pub fn Landing() -> impl IntoView {
             {/* CTA Section */}
             <section class="max-w-7xl mx-auto px-4 sm:px-6 lg:px-8 py-20">
                 <div class="text-center">
-                    <h2 class="text-4xl font-bold text-white mb-8">
+                    <h2 class=move || format!("text-4xl font-bold mb-8 {}", heading())>
                         "Ready to Research?"
                     </h2>
-                    <p class="text-xl text-gray-400 mb-12">
+                    <p class=move || format!("text-xl mb-12 {}", muted())>
                         "Start your AI-powered research journey today"
                     </p>
                     <A href="/chat" class="inline-block bg-orange-500 hover:bg-orange-700 text-white font-bold py-4 px-8 rounded-lg text-lg transition transform hover:scale-105">
@@ -140,11 +157,11 @@ pub fn Landing() -> impl IntoView {
             </section>
 
             {/* Footer */}
-            <footer class="border-t border-gray-700 bg-gray-900/80 mt-20">
-                <div class="max-w-7xl mx-auto px-4 sm:px-6 lg:px-8 py-12 text-center text-gray-400">
+            <footer class=move || format!("border-t mt-20 {}", bar())>
+                <div class=move || format!("max-w-7xl mx-auto px-4 sm:px-6 lg:px-8 py-12 text-center {}", muted())>
                     <p>
                         "© 2026 Dimensionless Rust AI. Built with Rust, Leptos, and ❤️"
-                        <a href="https://www.dimensionlessdevelopments.com" class="text-gray-300 hover:text-orange-500 transition">
+                        <a href="https://www.dimensionlessdevelopments.com" class=move || format!("hover:text-orange-500 transition {}", body())>
                                 "Made by Dimensionless Developments"
                         </a>
                     </p>
@@ -0,0 +1,673 @@
+// Dimensionless Developments Rust Ai
+// # Markdown Component
+// Renders assistant answers as CommonMark: headings, bold and italics,
+// lists, block quotes, tables, links and code blocks with highlighting.
+//
+// The text is parsed with pulldown-cmark and turned into Leptos elements
+// one by one - never into an HTML string set with `inner_html`. Text nodes
+// are escaped by the DOM, raw HTML in an answer shows up as literal text,
+// and only http(s) and mailto links become clickable, so nothing a model
+// (or a web page it quoted) writes can run script in the page.
+//
+// Citations like [2] become links to the message's source cards when the
+// message has sources.
+
+use leptos::*;
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+use crate::components::source_cards::{highlight_source, source_id};
+
+const PARAGRAPH_CLASS: &str = "text-sm font-normal leading-snug mb-3 last:mb-0";
+const H1_CLASS: &str = "text-lg font-bold mt-4 mb-2 first:mt-0";
+const H2_CLASS: &str = "text-base font-bold mt-4 mb-2 first:mt-0";
+const H3_CLASS: &str = "text-sm font-bold mt-3 mb-1 first:mt-0";
+const LIST_CLASS: &str = "text-sm leading-snug pl-5 mb-3 last:mb-0 space-y-1";
+const QUOTE_CLASS: &str = "border-l-4 border-blue-300 pl-3 italic text-gray-700 mb-3 last:mb-0";
+const LINK_CLASS: &str = "text-blue-700 underline hover:text-orange-500 break-words";
+const INLINE_CODE_CLASS: &str = "px-1 rounded bg-white bg-opacity-60 font-mono text-xs";
+const CODE_BLOCK_CLASS: &str = "relative mb-3 last:mb-0 rounded bg-gray-900 text-gray-100";
+const TABLE_CLASS: &str = "text-sm border-collapse mb-3 last:mb-0";
+const CELL_CLASS: &str = "border border-blue-200 px-2 py-1";
+const CITATION_CLASS: &str = "text-xs font-semibold text-blue-700 no-underline hover:text-orange-500 cursor-pointer";
+
+/// The sources `[n]` markers in a message can point to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Citations {
+    /// Index of the message in the conversation
+    pub message: usize,
+
+    /// Number of sources; `[1]` to `[count]` are citations
+    pub count: usize,
+}
+
+// =============================================================================
+// COMPONENT
+// =============================================================================
+/// An assistant message rendered as markdown.
+#[component]
+pub fn Markdown(
+    #[prop(into)] text: String,
+    #[prop(optional_no_strip)] citations: Option<Citations>,
+) -> impl IntoView {
+    let nodes = parse(&text);
+    view! {
+        <div class="break-words">
+            {render_all(nodes, citations)}
+        </div>
+    }
+}
+
+// =============================================================================
+// PARSING
+// =============================================================================
+/// A markdown element, with its children already parsed.
+///
+/// # Rust Concept: Events to Trees
+///
+/// pulldown-cmark is a streaming parser: it yields `Start(tag)`, content,
+/// then `End(tag)`. Leptos builds elements from their children, so we fold
+/// the stream into a tree first, using a stack of open elements.
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Code(String),
+    CodeBlock { lang: String, code: String },
+    SoftBreak,
+    HardBreak,
+    Rule,
+    TaskMarker(bool),
+    Element(Element, Vec<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Element {
+    Paragraph,
+    Heading(u8),
+    BlockQuote,
+    List(Option<u64>),
+    Item,
+    Table(Vec<Alignment>),
+    TableHead,
+    TableRow,
+    TableCell,
+    Emphasis,
+    Strong,
+    Strikethrough,
+    Link(String),
+    Image(String),
+
+    /// Anything else (footnotes, definition lists, raw HTML blocks):
+    /// its content is kept, the markup is not
+    Other,
+}
+
+fn parse(text: &str) -> Vec<Node> {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS;
+
+    // The bottom of the stack collects the top-level nodes
+    let mut stack: Vec<(Element, Vec<Node>)> = vec![(Element::Other, Vec::new())];
+    let mut code_block: Option<(String, String)> = None;
+
+    for event in Parser::new_ext(text, options) {
+        // Inside a code block everything is code
+        if code_block.is_some() {
+            match event {
+                Event::Text(text) | Event::Html(text) => {
+                    if let Some((_, code)) = code_block.as_mut() {
+                        code.push_str(&text);
+                    }
+                }
+                Event::End(TagEnd::CodeBlock) => {
+                    let (lang, code) = code_block.take().unwrap_or_default();
+                    push(&mut stack, Node::CodeBlock { lang, code });
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let lang = match kind {
+                    // "rust,ignore" and "rust {.class}" name the language first
+                    CodeBlockKind::Fenced(info) => info
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .next()
+                        .unwrap_or_default()
+                        .to_lowercase(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                code_block = Some((lang, String::new()));
+            }
+            Event::Start(tag) => stack.push((element(tag), Vec::new())),
+            Event::End(_) => {
+                if stack.len() > 1 {
+                    let (element, children) = stack.pop().unwrap_or((Element::Other, Vec::new()));
+                    push(&mut stack, Node::Element(element, children));
+                }
+            }
+            // Raw HTML is shown as written, never interpreted
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                push(&mut stack, Node::Text(text.into_string()))
+            }
+            Event::Code(code) | Event::InlineMath(code) | Event::DisplayMath(code) => {
+                push(&mut stack, Node::Code(code.into_string()))
+            }
+            Event::FootnoteReference(name) => push(&mut stack, Node::Text(format!("[{}]", name))),
+            Event::SoftBreak => push(&mut stack, Node::SoftBreak),
+            Event::HardBreak => push(&mut stack, Node::HardBreak),
+            Event::Rule => push(&mut stack, Node::Rule),
+            Event::TaskListMarker(done) => push(&mut stack, Node::TaskMarker(done)),
+        }
+    }
+
+    // A message still streaming in may end inside a code block or list
+    if let Some((lang, code)) = code_block {
+        push(&mut stack, Node::CodeBlock { lang, code });
+    }
+    while stack.len() > 1 {
+        if let Some((element, children)) = stack.pop() {
+            push(&mut stack, Node::Element(element, children));
+        }
+    }
+    stack.pop().map(|(_, nodes)| nodes).unwrap_or_default()
+}
+
+fn push(stack: &mut [(Element, Vec<Node>)], node: Node) {
+    let Some((_, children)) = stack.last_mut() else {
+        return;
+    };
+
+    // "[1]" may arrive as "[", "1", "]"; joined, citations are one text
+    match (children.last_mut(), node) {
+        (Some(Node::Text(last)), Node::Text(text)) => last.push_str(&text),
+        (_, node) => children.push(node),
+    }
+}
+
+fn element(tag: Tag) -> Element {
+    match tag {
+        Tag::Paragraph => Element::Paragraph,
+        Tag::Heading { level, .. } => Element::Heading(level as u8),
+        Tag::BlockQuote(_) => Element::BlockQuote,
+        Tag::List(start) => Element::List(start),
+        Tag::Item => Element::Item,
+        Tag::Table(alignments) => Element::Table(alignments),
+        Tag::TableHead => Element::TableHead,
+        Tag::TableRow => Element::TableRow,
+        Tag::TableCell => Element::TableCell,
+        Tag::Emphasis => Element::Emphasis,
+        Tag::Strong => Element::Strong,
+        Tag::Strikethrough => Element::Strikethrough,
+        Tag::Link { dest_url, .. } => Element::Link(dest_url.into_string()),
+        Tag::Image { dest_url, .. } => Element::Image(dest_url.into_string()),
+        _ => Element::Other,
+    }
+}
+
+/// Only these links are clickable; `javascript:` and friends are not.
+pub fn safe_href(url: &str) -> Option<&str> {
+    let lower = url.trim_start().to_ascii_lowercase();
+    ["http://", "https://", "mailto:"]
+        .iter()
+        .any(|scheme| lower.starts_with(scheme))
+        .then_some(url)
+}
+
+// =============================================================================
+// RENDERING
+// =============================================================================
+// `citations` is `None` where `[n]` must stay text: inside links and
+// messages without sources.
+
+fn render_all(nodes: Vec<Node>, citations: Option<Citations>) -> View {
+    nodes.into_iter().map(|node| render(node, citations)).collect_view()
+}
+
+fn render(node: Node, citations: Option<Citations>) -> View {
+    match node {
+        Node::Text(text) => match citations {
+            Some(citations) => render_text(text, citations),
+            None => text.into_view(),
+        },
+        Node::Code(code) => view! { <code class=INLINE_CODE_CLASS>{code}</code> }.into_view(),
+        Node::CodeBlock { lang, code } => render_code_block(lang, code),
+        Node::SoftBreak => " ".into_view(),
+        Node::HardBreak => view! { <br/> }.into_view(),
+        Node::Rule => view! { <hr class="my-3 border-blue-200"/> }.into_view(),
+        Node::TaskMarker(done) => {
+            view! { <input type="checkbox" class="mr-1" disabled=true checked=done/> }.into_view()
+        }
+        Node::Element(element, children) => render_element(element, children, citations),
+    }
+}
+
+/// A piece of message text: plain, or a citation of source `n`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TextPart<'a> {
+    Plain(&'a str),
+    Citation(usize),
+}
+
+/// Split `text` at its `[n]` citations. Only `[1]` to `[count]` count;
+/// anything else in brackets (`[0]`, `[+1]`, `[note]`) stays text.
+fn split_citations(text: &str, count: usize) -> Vec<TextPart<'_>> {
+    let mut parts = Vec::new();
+    let mut plain_start = 0;
+    let mut search_from = 0;
+    while let Some(offset) = text[search_from..].find('[') {
+        let start = search_from + offset;
+        let number = text[start + 1..]
+            .find(']')
+            .map(|end| (end, &text[start + 1..start + 1 + end]))
+            .filter(|(_, digits)| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|(end, digits)| Some((end, digits.parse::<usize>().ok()?)))
+            .filter(|(_, n)| (1..=count).contains(n));
+        let Some((end, number)) = number else {
+            search_from = start + 1;
+            continue;
+        };
+
+        if plain_start < start {
+            parts.push(TextPart::Plain(&text[plain_start..start]));
+        }
+        parts.push(TextPart::Citation(number));
+        search_from = start + end + 2;
+        plain_start = search_from;
+    }
+    if plain_start < text.len() {
+        parts.push(TextPart::Plain(&text[plain_start..]));
+    }
+    parts
+}
+
+/// Text with its `[n]` citations turned into links to the source cards.
+fn render_text(text: String, citations: Citations) -> View {
+    split_citations(&text, citations.count)
+        .into_iter()
+        .map(|part| match part {
+            TextPart::Plain(plain) => plain.to_string().into_view(),
+            TextPart::Citation(number) => {
+                let id = source_id(citations.message, number);
+                view! {
+                    <a
+                        href=format!("#{}", id)
+                        class=CITATION_CLASS
+                        title=format!("Source {}", number)
+                        on:click=move |ev| {
+                            ev.prevent_default();
+                            highlight_source(&id);
+                        }
+                    >
+                        {format!("[{}]", number)}
+                    </a>
+                }
+                .into_view()
+            }
+        })
+        .collect_view()
+}
+
+fn render_element(element: Element, children: Vec<Node>, citations: Option<Citations>) -> View {
+    match element {
+        Element::Paragraph => view! { <p class=PARAGRAPH_CLASS>{render_all(children, citations)}</p> }.into_view(),
+        Element::Heading(1) => view! { <h1 class=H1_CLASS>{render_all(children, citations)}</h1> }.into_view(),
+        Element::Heading(2) => view! { <h2 class=H2_CLASS>{render_all(children, citations)}</h2> }.into_view(),
+        Element::Heading(3) => view! { <h3 class=H3_CLASS>{render_all(children, citations)}</h3> }.into_view(),
+        Element::Heading(_) => view! { <h4 class=H3_CLASS>{render_all(children, citations)}</h4> }.into_view(),
+        Element::BlockQuote => {
+            view! { <blockquote class=QUOTE_CLASS>{render_all(children, citations)}</blockquote> }.into_view()
+        }
+        Element::List(None) => {
+            view! { <ul class=format!("list-disc {LIST_CLASS}")>{render_all(children, citations)}</ul> }.into_view()
+        }
+        Element::List(Some(start)) => view! {
+            <ol class=format!("list-decimal {LIST_CLASS}") start=start.to_string()>
+                {render_all(children, citations)}
+            </ol>
+        }
+        .into_view(),
+        Element::Item => view! { <li>{render_all(children, citations)}</li> }.into_view(),
+        Element::Table(alignments) => render_table(alignments, children, citations),
+        Element::Emphasis => view! { <em>{render_all(children, citations)}</em> }.into_view(),
+        Element::Strong => view! { <strong class="font-semibold">{render_all(children, citations)}</strong> }.into_view(),
+        Element::Strikethrough => view! { <del>{render_all(children, citations)}</del> }.into_view(),
+        Element::Link(url) => match safe_href(&url) {
+            Some(href) => view! {
+                <a href=href.to_string() class=LINK_CLASS target="_blank" rel="noopener noreferrer">
+                    {render_all(children, None)}
+                </a>
+            }
+            .into_view(),
+            None => render_all(children, citations),
+        },
+        // Remote images are linked, not loaded: answers quote arbitrary pages
+        Element::Image(url) => match safe_href(&url) {
+            Some(href) => view! {
+                <a href=href.to_string() class=LINK_CLASS target="_blank" rel="noopener noreferrer">
+                    "🖼 " {render_all(children, None)}
+                </a>
+            }
+            .into_view(),
+            None => render_all(children, citations),
+        },
+        // Table parts are rendered by render_table
+        Element::TableHead | Element::TableRow | Element::TableCell | Element::Other => {
+            render_all(children, citations)
+        }
+    }
+}
+
+/// Tables need their column alignments while rendering cells, so they
+/// are rendered as a whole.
+fn render_table(alignments: Vec<Alignment>, children: Vec<Node>, citations: Option<Citations>) -> View {
+    let cells = |row: Vec<Node>, header: bool| {
+        row
+            .into_iter()
+            .enumerate()
+            .map(|(column, cell)| {
+                let align = match alignments.get(column) {
+                    Some(Alignment::Center) => "text-center",
+                    Some(Alignment::Right) => "text-right",
+                    _ => "text-left",
+                };
+                let content = match cell {
+                    Node::Element(Element::TableCell, content) => render_all(content, citations),
+                    other => render(other, citations),
+                };
+                if header {
+                    view! { <th class=format!("{CELL_CLASS} {align} font-semibold")>{content}</th> }
+                        .into_view()
+                } else {
+                    view! { <td class=format!("{CELL_CLASS} {align}")>{content}</td> }.into_view()
+                }
+            })
+            .collect_view()
+    };
+
+    let mut head = Vec::new();
+    let mut rows = Vec::new();
+    for child in children {
+        match child {
+            // The header's cells come without a row around them
+            Node::Element(Element::TableHead, content) => head.push(view! { <tr>{cells(content, true)}</tr> }),
+            Node::Element(Element::TableRow, content) => rows.push(view! { <tr>{cells(content, false)}</tr> }),
+            _ => {}
+        }
+    }
+
+    view! {
+        <div class="overflow-x-auto">
+            <table class=TABLE_CLASS>
+                <thead>{head}</thead>
+                <tbody>{rows}</tbody>
+            </table>
+        </div>
+    }
+    .into_view()
+}
+
+fn render_code_block(lang: String, code: String) -> View {
+    let tokens = highlight(&code, &lang)
+        .into_iter()
+        .map(|(kind, text)| match kind.class() {
+            Some(class) => view! { <span class=class>{text}</span> }.into_view(),
+            None => text.into_view(),
+        })
+        .collect_view();
+
+    view! {
+        <div class=CODE_BLOCK_CLASS>
+            {(!lang.is_empty()).then(|| view! {
+                <span class="absolute top-1 right-2 text-xs text-gray-400">{lang.clone()}</span>
+            })}
+            <pre class="p-3 overflow-x-auto text-xs font-mono leading-relaxed"><code>{tokens}</code></pre>
+        </div>
+    }
+    .into_view()
+}
+
+// =============================================================================
+// SYNTAX HIGHLIGHTING
+// =============================================================================
+// A small keyword/string/comment/number highlighter. Full grammars
+// (syntect, tree-sitter) would add megabytes to the WASM bundle; answers
+// mostly contain short snippets, where this is enough to read them.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TokenKind {
+    Plain,
+    Keyword,
+    String,
+    Comment,
+    Number,
+}
+
+impl TokenKind {
+    fn class(self) -> Option<&'static str> {
+        match self {
+            TokenKind::Plain => None,
+            TokenKind::Keyword => Some("text-purple-300"),
+            TokenKind::String => Some("text-green-300"),
+            TokenKind::Comment => Some("text-gray-500 italic"),
+            TokenKind::Number => Some("text-yellow-300"),
+        }
+    }
+}
+
+/// Keywords of the languages answers usually contain (Rust, Python,
+/// JavaScript/TypeScript, Go, shell, SQL).
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "case", "catch", "class", "const", "continue", "crate",
+    "def", "default", "do", "done", "elif", "else", "enum", "export", "extern", "false", "fi",
+    "fn", "for", "from", "func", "function", "go", "if", "impl", "import", "in", "interface",
+    "let", "loop", "match", "mod", "mut", "new", "None", "null", "package", "pass", "pub",
+    "raise", "ref", "return", "self", "Self", "static", "struct", "switch", "then", "throw",
+    "trait", "True", "False", "true", "try", "type", "typeof", "unsafe", "use", "var", "where",
+    "while", "with", "yield", "SELECT", "FROM", "WHERE", "INSERT", "UPDATE", "DELETE", "JOIN",
+    "CREATE", "TABLE",
+];
+
+/// Split `code` into highlighted tokens.
+fn highlight(code: &str, lang: &str) -> Vec<(TokenKind, String)> {
+    if matches!(lang, "" | "text" | "txt" | "plain" | "plaintext" | "console" | "output") {
+        return vec![(TokenKind::Plain, code.to_string())];
+    }
+
+    let hash_comments = matches!(
+        lang,
+        "python" | "py" | "bash" | "sh" | "shell" | "zsh" | "toml" | "yaml" | "yml" | "ruby" | "rb" | "r"
+    );
+    let dash_comments = matches!(lang, "sql" | "lua" | "haskell" | "hs");
+    let slash_comments = !hash_comments && !dash_comments;
+    let rust = matches!(lang, "rust" | "rs");
+
+    let mut tokens: Vec<(TokenKind, String)> = Vec::new();
+    let mut add = |kind: TokenKind, text: &str| match tokens.last_mut() {
+        Some((last, existing)) if *last == kind && kind == TokenKind::Plain => existing.push_str(text),
+        _ => tokens.push((kind, text.to_string())),
+    };
+
+    let mut rest = code;
+    while let Some(c) = rest.chars().next() {
+        let line_end = rest.find('\n').unwrap_or(rest.len());
+
+        let comment = (slash_comments && rest.starts_with("//"))
+            || (hash_comments && c == '#')
+            || (dash_comments && rest.starts_with("--"));
+        let taken = if comment {
+            add(TokenKind::Comment, &rest[..line_end]);
+            line_end
+        } else if slash_comments && rest.starts_with("/*") {
+            let end = rest[2..].find("*/").map(|i| i + 4).unwrap_or(rest.len());
+            add(TokenKind::Comment, &rest[..end]);
+            end
+        } else if matches!(c, '"' | '\'' | '`') {
+            match string_end(rest, c, rust) {
+                Some(end) => {
+                    add(TokenKind::String, &rest[..end]);
+                    end
+                }
+                None => {
+                    add(TokenKind::Plain, &rest[..1]);
+                    1
+                }
+            }
+        } else if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len());
+            add(TokenKind::Number, &rest[..end]);
+            end
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+            let kind = if KEYWORDS.contains(&word) {
+                TokenKind::Keyword
+            } else {
+                TokenKind::Plain
+            };
+            add(kind, word);
+            end
+        } else {
+            add(TokenKind::Plain, &rest[..c.len_utf8()]);
+            c.len_utf8()
+        };
+
+        rest = &rest[taken..];
+    }
+
+    tokens
+}
+
+/// Byte length of the string literal at the start of `text`, if it is one.
+///
+/// Strings end at the matching unescaped quote on the same line (template
+/// literals may span lines). In Rust `'` is mostly a lifetime, so only
+/// short char literals like `'a'` or `'\n'` count.
+fn string_end(text: &str, quote: char, rust: bool) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        if c == '\n' && quote != '`' {
+            return None;
+        }
+        if rust && quote == '\'' && i > 3 {
+            return None;
+        }
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return Some(i + c.len_utf8());
+        }
+    }
+    None
+}
+
+// =============================================================================
+// UNIT TESTS
+// =============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// All text under `nodes`, markup dropped.
+    fn text_of(nodes: &[Node]) -> String {
+        nodes
+            .iter()
+            .map(|node| match node {
+                Node::Text(text) | Node::Code(text) => text.clone(),
+                Node::CodeBlock { code, .. } => code.clone(),
+                Node::Element(_, children) => text_of(children),
+                _ => String::new(),
+            })
+            .collect()
+    }
+
+    fn paragraph(text: &str) -> Vec<Node> {
+        vec![Node::Element(Element::Paragraph, vec![Node::Text(text.to_string())])]
+    }
+
+    #[test]
+    fn test_safe_href_allows_only_web_and_mail_links() {
+        assert_eq!(safe_href("https://docs.rs"), Some("https://docs.rs"));
+        assert_eq!(safe_href("mailto:team@example.com"), Some("mailto:team@example.com"));
+        assert_eq!(safe_href("javascript:alert(1)"), None);
+        assert_eq!(safe_href(" JaVaScript:alert(1)"), None);
+        assert_eq!(safe_href("data:text/html,<script>alert(1)</script>"), None);
+        assert_eq!(safe_href("/relative/path"), None);
+    }
+
+    #[test]
+    fn test_raw_html_stays_text() {
+        let nodes = parse("<script>alert(1)</script>");
+        assert!(text_of(&nodes).contains("<script>alert(1)</script>"));
+        assert!(nodes
+            .iter()
+            .all(|node| matches!(node, Node::Text(_) | Node::Element(Element::Other, _))));
+
+        assert_eq!(
+            parse("Hi <b onclick=\"x()\">there</b>"),
+            paragraph("Hi <b onclick=\"x()\">there</b>")
+        );
+    }
+
+    #[test]
+    fn test_unterminated_code_fence_is_still_a_code_block() {
+        let nodes = parse("```rust\nfn main() {");
+        assert_eq!(nodes.len(), 1);
+        assert!(matches!(
+            &nodes[0],
+            Node::CodeBlock { lang, code } if lang == "rust" && code.starts_with("fn main() {")
+        ));
+    }
+
+    #[test]
+    fn test_citations_split_across_events_are_joined() {
+        // pulldown-cmark yields "[", "1", "]" as separate text events
+        assert_eq!(parse("See [1][2]."), paragraph("See [1][2]."));
+    }
+
+    #[test]
+    fn test_only_known_sources_are_citations() {
+        assert_eq!(
+            split_citations("a [0] b [3] c [1]", 2),
+            vec![TextPart::Plain("a [0] b [3] c "), TextPart::Citation(1)]
+        );
+        assert_eq!(
+            split_citations("[2][+1] [note]", 2),
+            vec![TextPart::Citation(2), TextPart::Plain("[+1] [note]")]
+        );
+        assert_eq!(split_citations("[1", 2), vec![TextPart::Plain("[1")]);
+        assert!(split_citations("", 2).is_empty());
+    }
+
+    #[test]
+    fn test_highlight_handles_non_ascii() {
+        let code = "let café = \"naïve\"; // ünïcode ✓\nlet c = 'é';";
+        let tokens = highlight(code, "rust");
+
+        let joined: String = tokens.iter().map(|(_, text)| text.as_str()).collect();
+        assert_eq!(joined, code);
+        assert!(tokens.contains(&(TokenKind::String, "\"naïve\"".to_string())));
+        assert!(tokens.contains(&(TokenKind::Comment, "// ünïcode ✓".to_string())));
+        assert!(tokens.contains(&(TokenKind::String, "'é'".to_string())));
+    }
+
+    #[test]
+    fn test_highlight_unterminated_block_comment_runs_to_the_end() {
+        assert_eq!(
+            highlight("x /* never closed ü", "rust"),
+            vec![
+                (TokenKind::Plain, "x ".to_string()),
+                (TokenKind::Comment, "/* never closed ü".to_string()),
+            ]
+        );
+        assert_eq!(highlight("/*", "js"), vec![(TokenKind::Comment, "/*".to_string())]);
+    }
+}
@@ -1,5 +1,12 @@
 // Dimensionless Developments Rust Ai
 
+pub mod activity_panel;
 pub mod chat_area;
+pub mod connection_status;
+pub mod markdown;
+pub mod preferences;
+pub mod settings_drawer;
+pub mod sidebar;
+pub mod source_cards;
 pub mod type_area;
 pub mod landing;
\ No newline at end of file
@@ -0,0 +1,249 @@
+// Dimensionless Developments Rust Ai
+// # Preferences Component
+// How the UI looks: light, dark or following the system theme, the font
+// size and a compact layout. Kept in localStorage, so they apply to every
+// page and survive reloads.
+//
+// `App` turns the theme into the `dark_mode` signal every component reads
+// from context; font size and compact mode are read from the
+// `ReadSignal<Preferences>` context.
+
+use leptos::*;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+
+/// localStorage key of the preferences.
+const PREFERENCES_KEY: &str = "research-agent.preferences";
+
+/// Matches while the operating system is in dark mode.
+const SYSTEM_DARK_QUERY: &str = "(prefers-color-scheme: dark)";
+
+const TOGGLE_CLASS: &str = "px-3 py-1 rounded-full text-xs font-semibold shadow";
+const TOGGLE_LIGHT_MODE_COLORS: &str = "bg-white text-gray-800 border border-gray-300";
+const TOGGLE_DARK_MODE_COLORS: &str = "bg-gray-800 text-white";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    Light,
+    Dark,
+
+    /// Whatever the operating system uses, switching along with it
+    #[default]
+    System,
+}
+
+impl Theme {
+    pub fn is_dark(self, system_dark: bool) -> bool {
+        match self {
+            Theme::Light => false,
+            Theme::Dark => true,
+            Theme::System => system_dark,
+        }
+    }
+
+    /// The theme after this one, for the toggle button.
+    fn next(self) -> Self {
+        match self {
+            Theme::Light => Theme::Dark,
+            Theme::Dark => Theme::System,
+            Theme::System => Theme::Light,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Theme::Light => "☀ Light",
+            Theme::Dark => "☾ Dark",
+            Theme::System => "◐ System",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FontSize {
+    Small,
+    #[default]
+    Medium,
+    Large,
+}
+
+impl FontSize {
+    /// Size of the root element; Tailwind sizes are in rem, so everything
+    /// scales with it.
+    fn root_size(self) -> &'static str {
+        match self {
+            FontSize::Small => "87.5%",
+            FontSize::Medium => "100%",
+            FontSize::Large => "112.5%",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            FontSize::Small => "Small",
+            FontSize::Medium => "Medium",
+            FontSize::Large => "Large",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    pub theme: Theme,
+    pub font_size: FontSize,
+
+    /// Tighter spacing, to see more of a long chat at once
+    pub compact: bool,
+}
+
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+/// The preferences saved last time, or the defaults.
+pub fn load_preferences() -> Preferences {
+    local_storage()
+        .and_then(|storage| storage.get_item(PREFERENCES_KEY).ok().flatten())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_preferences(preferences: &Preferences) {
+    let Some(storage) = local_storage() else {
+        return;
+    };
+    if let Ok(json) = serde_json::to_string(preferences) {
+        let _ = storage.set_item(PREFERENCES_KEY, &json);
+    }
+}
+
+/// Whether the system is in dark mode, updated when it switches.
+pub fn system_dark_mode() -> ReadSignal<bool> {
+    let query = web_sys::window().and_then(|window| window.match_media(SYSTEM_DARK_QUERY).ok().flatten());
+    let (system_dark, set_system_dark) = create_signal(query.as_ref().is_some_and(|q| q.matches()));
+
+    if let Some(query) = query {
+        let on_change = Closure::<dyn Fn(web_sys::MediaQueryListEvent)>::new(move |event: web_sys::MediaQueryListEvent| {
+            set_system_dark.set(event.matches());
+        });
+        let _ = query.add_event_listener_with_callback("change", on_change.as_ref().unchecked_ref());
+        // Listens for the lifetime of the page
+        on_change.forget();
+    }
+    system_dark
+}
+
+/// Apply what CSS classes can't: the root font size, and the color scheme
+/// of native controls and scrollbars.
+pub fn apply_preferences(preferences: &Preferences, dark: bool) {
+    let Some(root) = web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.document_element())
+        .and_then(|element| element.dyn_into::<web_sys::HtmlElement>().ok())
+    else {
+        return;
+    };
+
+    let style = root.style();
+    let _ = style.set_property("font-size", preferences.font_size.root_size());
+    let _ = style.set_property("color-scheme", if dark { "dark" } else { "light" });
+    let _ = style.set_property("background-color", if dark { "#111827" } else { "#f9fafb" });
+}
+
+/// `dark` or `light` classes, following the theme.
+pub fn themed(
+    dark_mode: ReadSignal<bool>,
+    dark: &'static str,
+    light: &'static str,
+) -> impl Fn() -> &'static str + Copy + 'static {
+    move || if dark_mode.get() { dark } else { light }
+}
+
+/// Button cycling light → dark → system.
+#[component]
+pub fn ThemeToggle() -> impl IntoView {
+    let dark_mode = use_context::<ReadSignal<bool>>()
+        .expect("should be able to get dark mode state");
+    let preferences = use_context::<ReadSignal<Preferences>>()
+        .expect("should be able to get the preferences");
+    let set_preferences = use_context::<WriteSignal<Preferences>>()
+        .expect("should be able to set the preferences");
+
+    let colors = themed(dark_mode, TOGGLE_DARK_MODE_COLORS, TOGGLE_LIGHT_MODE_COLORS);
+
+    view! {
+        <button
+            class=move || format!("{TOGGLE_CLASS} {}", colors())
+            title="Theme: light, dark or follow the system"
+            on:click=move |_| set_preferences.update(|p| p.theme = p.theme.next())
+        >
+            {move || preferences.with(|p| p.theme.label())}
+        </button>
+    }
+}
+
+/// Theme, font size and compact mode, for the settings drawer.
+#[component]
+pub fn AppearanceSettings(
+    /// Class of the section labels, as in the rest of the drawer
+    label_class: &'static str,
+    input_class: &'static str,
+) -> impl IntoView {
+    let preferences = use_context::<ReadSignal<Preferences>>()
+        .expect("should be able to get the preferences");
+    let set_preferences = use_context::<WriteSignal<Preferences>>()
+        .expect("should be able to set the preferences");
+
+    view! {
+        <span class=label_class>"Theme"</span>
+        <div class="flex gap-3 text-sm">
+            {[Theme::Light, Theme::Dark, Theme::System].into_iter().map(|theme| view! {
+                <label class="flex items-center gap-1 cursor-pointer">
+                    <input
+                        type="radio"
+                        name="ui-theme"
+                        prop:checked=move || preferences.with(|p| p.theme == theme)
+                        on:change=move |_| set_preferences.update(|p| p.theme = theme)
+                    />
+                    {theme.label()}
+                </label>
+            }).collect_view()}
+        </div>
+
+        <label class=label_class for="ui-font-size">"Font size"</label>
+        <select
+            id="ui-font-size"
+            class=input_class
+            on:change=move |ev| {
+                let size = match event_target_value(&ev).as_str() {
+                    "small" => FontSize::Small,
+                    "large" => FontSize::Large,
+                    _ => FontSize::Medium,
+                };
+                set_preferences.update(|p| p.font_size = size);
+            }
+        >
+            {[(FontSize::Small, "small"), (FontSize::Medium, "medium"), (FontSize::Large, "large")]
+                .into_iter()
+                .map(|(size, value)| view! {
+                    <option value=value selected=move || preferences.with(|p| p.font_size == size)>
+                        {size.label()}
+                    </option>
+                })
+                .collect_view()}
+        </select>
+
+        <label class="flex items-center gap-2 mt-3 text-sm cursor-pointer">
+            <input
+                type="checkbox"
+                prop:checked=move || preferences.with(|p| p.compact)
+                on:change=move |ev| set_preferences.update(|p| p.compact = event_target_checked(&ev))
+            />
+            "Compact layout"
+        </label>
+    }
+}
@@ -0,0 +1,244 @@
+// Dimensionless Developments Rust Ai
+// # Settings Drawer Component
+// The chat's version of the CLI's options: quick search, research or deep
+// research, the model, how many results per search, and the temperature.
+// They are sent with every question (`QueryOptions`) and remembered in
+// localStorage. Anything left on "server default" uses the server's config.
+// The drawer also holds the appearance preferences (see `preferences`).
+
+use gloo_net::http::Request;
+use leptos::*;
+use serde::Deserialize;
+
+use crate::app::api_url;
+use crate::components::preferences::{AppearanceSettings, ThemeToggle};
+use crate::protocol::{QueryOptions, ResearchMode, MAX_RESULTS_LIMIT};
+
+/// localStorage key of the settings.
+const SETTINGS_KEY: &str = "research-agent.settings";
+
+const DRAWER_CLASS: &str = "fixed top-0 right-0 z-50 h-full w-80 max-w-full overflow-y-auto p-5 shadow-xl";
+const DRAWER_LIGHT_MODE_COLORS: &str = "bg-white text-gray-800";
+const DRAWER_DARK_MODE_COLORS: &str = "bg-gray-900 text-gray-100";
+const LABEL_CLASS: &str = "block text-xs font-semibold uppercase tracking-wide text-gray-500 mt-5 mb-1";
+const INPUT_CLASS: &str = "w-full px-2 py-1 rounded border border-gray-400 bg-transparent text-sm focus:outline-none disabled:opacity-50";
+
+/// Response of `GET /api/models`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct ServerModels {
+    pub models: Vec<String>,
+    pub default_model: String,
+    pub default_max_results: usize,
+    pub default_temperature: f32,
+}
+
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+/// The settings saved last time, or the server's defaults.
+pub fn load_settings() -> QueryOptions {
+    local_storage()
+        .and_then(|storage| storage.get_item(SETTINGS_KEY).ok().flatten())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_settings(settings: &QueryOptions) {
+    let Some(storage) = local_storage() else {
+        return;
+    };
+    if let Ok(json) = serde_json::to_string(settings) {
+        let _ = storage.set_item(SETTINGS_KEY, &json);
+    }
+}
+
+/// The server's models and defaults; `None` when Ollama can't be reached.
+async fn fetch_models() -> Option<ServerModels> {
+    let response = Request::get(&api_url("/api/models")).send().await.ok()?;
+    if !response.ok() {
+        return None;
+    }
+    response.json().await.ok()
+}
+
+fn mode_label(mode: ResearchMode) -> &'static str {
+    match mode {
+        ResearchMode::Quick => "Quick search",
+        ResearchMode::Research => "Research",
+        ResearchMode::Deep => "Deep research",
+    }
+}
+
+fn mode_description(mode: ResearchMode) -> &'static str {
+    match mode {
+        ResearchMode::Quick => "Search results only, no model - fast",
+        ResearchMode::Research => "Searches, reads and summarizes",
+        ResearchMode::Deep => "Several searches on different angles, for harder questions",
+    }
+}
+
+#[component]
+pub fn SettingsDrawer(
+    settings: ReadSignal<QueryOptions>,
+    set_settings: WriteSignal<QueryOptions>,
+) -> impl IntoView {
+    let dark_mode = use_context::<ReadSignal<bool>>()
+        .expect("should be able to get dark mode state");
+
+    let (open, set_open) = create_signal(false);
+
+    let server = create_local_resource(|| (), |_| fetch_models());
+    let server_models = move || server.get().flatten();
+
+    let drawer_class = Signal::derive(move || {
+        if dark_mode.get() {
+            format!("{DRAWER_CLASS} {DRAWER_DARK_MODE_COLORS}")
+        } else {
+            format!("{DRAWER_CLASS} {DRAWER_LIGHT_MODE_COLORS}")
+        }
+    });
+    let quick = move || settings.with(|s| s.mode == ResearchMode::Quick);
+
+    // "" (or anything unparseable) means "server default"
+    let set_model = move |value: String| {
+        set_settings.update(|s| s.model = (!value.is_empty()).then_some(value));
+    };
+    let set_max_results = move |value: String| {
+        set_settings.update(|s| {
+            s.max_results = value
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .map(|n| n.clamp(1, MAX_RESULTS_LIMIT));
+        });
+    };
+    let set_temperature = move |value: String| {
+        set_settings.update(|s| {
+            s.temperature = value.trim().parse::<f32>().ok().map(|t| t.clamp(0.0, 2.0));
+        });
+    };
+
+    let default_hint = move |describe: fn(&ServerModels) -> String| {
+        server_models()
+            .map(|server| format!("Server default ({})", describe(&server)))
+            .unwrap_or_else(|| "Server default".to_string())
+    };
+
+    view! {
+        <div class="fixed top-12 right-3 z-40 flex gap-2">
+            <ThemeToggle/>
+            <button
+                class="px-3 py-1 rounded-full bg-gray-800 text-white text-xs font-semibold shadow"
+                title="Research settings"
+                on:click=move |_| set_open.set(true)
+            >
+                {move || format!("⚙ {}", mode_label(settings.with(|s| s.mode)))}
+            </button>
+        </div>
+
+        <Show when=move || open.get()>
+            <div class="fixed inset-0 z-40 bg-black bg-opacity-30" on:click=move |_| set_open.set(false)></div>
+            <aside class=move || drawer_class.get()>
+                <div class="flex items-center justify-between">
+                    <h2 class="text-lg font-bold">"Settings"</h2>
+                    <button class="text-xl leading-none opacity-70 hover:opacity-100" title="Close" on:click=move |_| set_open.set(false)>
+                        "×"
+                    </button>
+                </div>
+
+                <span class=LABEL_CLASS>"Mode"</span>
+                <div class="space-y-1">
+                    {[ResearchMode::Quick, ResearchMode::Research, ResearchMode::Deep].into_iter().map(|mode| view! {
+                        <label class="flex items-start gap-2 cursor-pointer text-sm">
+                            <input
+                                type="radio"
+                                name="research-mode"
+                                class="mt-1"
+                                prop:checked=move || settings.with(|s| s.mode == mode)
+                                on:change=move |_| set_settings.update(|s| s.mode = mode)
+                            />
+                            <span>
+                                <span class="font-semibold">{mode_label(mode)}</span>
+                                <span class="block text-xs text-gray-500">{mode_description(mode)}</span>
+                            </span>
+                        </label>
+                    }).collect_view()}
+                </div>
+
+                <label class=LABEL_CLASS for="settings-model">"Model"</label>
+                <select
+                    id="settings-model"
+                    class=INPUT_CLASS
+                    disabled=quick
+                    title=move || if quick() { "Quick searches don't use a model" } else { "" }
+                    on:change=move |ev| set_model(event_target_value(&ev))
+                >
+                    <option value="" selected=move || settings.with(|s| s.model.is_none())>
+                        {move || default_hint(|server| server.default_model.clone())}
+                    </option>
+                    {move || {
+                        // Keep a saved choice listed even if the server lost it
+                        let mut models = server_models().map(|server| server.models).unwrap_or_default();
+                        if let Some(chosen) = settings.with(|s| s.model.clone()) {
+                            if !models.contains(&chosen) {
+                                models.push(chosen);
+                            }
+                        }
+                        models.into_iter().map(|model| {
+                            let value = model.clone();
+                            view! {
+                                <option
+                                    value=value.clone()
+                                    selected=move || settings.with(|s| s.model.as_deref() == Some(value.as_str()))
+                                >
+                                    {model}
+                                </option>
+                            }
+                        }).collect_view()
+                    }}
+                </select>
+                <Show when=move || server.get().is_some_and(|models| models.is_none())>
+                    <p class="mt-1 text-xs text-red-500">"Couldn't list the server's models."</p>
+                </Show>
+
+                <label class=LABEL_CLASS for="settings-max-results">"Results per search"</label>
+                <input
+                    id="settings-max-results"
+                    class=INPUT_CLASS
+                    type="number"
+                    min="1"
+                    max=MAX_RESULTS_LIMIT.to_string()
+                    placeholder=move || default_hint(|server| server.default_max_results.to_string())
+                    prop:value=move || settings.with(|s| s.max_results.map(|n| n.to_string()).unwrap_or_default())
+                    on:change=move |ev| set_max_results(event_target_value(&ev))
+                />
+
+                <label class=LABEL_CLASS for="settings-temperature">"Temperature"</label>
+                <input
+                    id="settings-temperature"
+                    class=INPUT_CLASS
+                    type="number"
+                    min="0"
+                    max="2"
+                    step="0.1"
+                    disabled=quick
+                    placeholder=move || default_hint(|server| server.default_temperature.to_string())
+                    prop:value=move || settings.with(|s| s.temperature.map(|t| t.to_string()).unwrap_or_default())
+                    on:change=move |ev| set_temperature(event_target_value(&ev))
+                />
+                <p class="mt-1 text-xs text-gray-500">"Lower is more focused, higher more varied (0 - 2)."</p>
+
+                <button
+                    class="mt-6 w-full px-3 py-1.5 rounded border border-gray-400 text-sm hover:border-orange-500"
+                    on:click=move |_| set_settings.set(QueryOptions::default())
+                >
+                    "Reset to server defaults"
+                </button>
+
+                <h3 class="mt-8 text-sm font-bold">"Appearance"</h3>
+                <AppearanceSettings label_class=LABEL_CLASS input_class=INPUT_CLASS/>
+            </aside>
+        </Show>
+    }
+}
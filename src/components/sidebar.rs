@@ -0,0 +1,521 @@
+// Dimensionless Developments Rust Ai
+// # Sidebar Component
+// The list of chats ("threads"): create, rename, delete and search them,
+// and switch between them. Threads are kept in the browser's localStorage,
+// so they survive reloads; the server's own history (`/api/history`), when
+// it keeps one, is listed below them and can be opened as a thread too.
+
+use std::collections::HashSet;
+
+use gloo_net::http::Request;
+use leptos::*;
+use serde::{Deserialize, Serialize};
+
+use crate::app::api_url;
+use crate::components::chat_area::{timestamp_at, MetaLog};
+use crate::components::source_cards::SourceLog;
+use crate::model::conversation::{Conversation, Message};
+use crate::protocol::{AnswerMeta, DocumentInfo, Source};
+
+/// localStorage keys: every thread, and the one that is open.
+const THREADS_KEY: &str = "research-agent.threads";
+const CURRENT_THREAD_KEY: &str = "research-agent.current-thread";
+
+/// Threads kept in the browser; the least recently used are dropped beyond
+/// this, so localStorage (usually 5 MB) doesn't fill up.
+const MAX_THREADS: usize = 100;
+
+/// Server runs listed in the sidebar.
+const REMOTE_RUNS: usize = 50;
+
+/// Title of a thread until its first question (or a rename).
+pub const DEFAULT_TITLE: &str = "New chat";
+const TITLE_MAX_CHARS: usize = 60;
+
+/// Added to the title of a thread forked by editing a question.
+const EDITED_SUFFIX: &str = " (edited)";
+
+const SIDEBAR_CLASS: &str = "w-64 flex-shrink-0 flex flex-col overflow-hidden border-r";
+const SIDEBAR_LIGHT_MODE_COLORS: &str = "bg-white border-gray-200 text-gray-800";
+const SIDEBAR_DARK_MODE_COLORS: &str = "bg-gray-900 border-gray-700 text-gray-100";
+const ROW_CLASS: &str = "group flex items-center gap-1 rounded px-2 py-1.5 text-sm";
+const ICON_BUTTON_CLASS: &str = "hidden group-hover:block text-xs opacity-70 hover:opacity-100";
+const SECTION_CLASS: &str = "px-3 pt-3 pb-1 text-xs font-semibold uppercase tracking-wide text-gray-500";
+
+// =============================================================================
+// THREADS
+// =============================================================================
+/// A chat message as stored in localStorage.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredMessage {
+    pub text: String,
+    pub user: bool,
+    pub sender_name: String,
+    pub timestamp: String,
+}
+
+impl From<&Message> for StoredMessage {
+    fn from(message: &Message) -> Self {
+        Self {
+            text: message.text.clone(),
+            user: message.user,
+            sender_name: message.sender_name.clone(),
+            timestamp: message.timestamp.clone(),
+        }
+    }
+}
+
+impl From<StoredMessage> for Message {
+    fn from(message: StoredMessage) -> Self {
+        Message {
+            text: message.text,
+            user: message.user,
+            sender_name: message.sender_name,
+            timestamp: message.timestamp,
+        }
+    }
+}
+
+/// One chat, with everything needed to show it again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Thread {
+    pub id: String,
+    pub title: String,
+
+    /// Last change, in milliseconds since the Unix epoch
+    pub updated_at: f64,
+
+    pub messages: Vec<StoredMessage>,
+
+    /// Sources per assistant message, as in the chat
+    #[serde(default)]
+    pub sources: SourceLog,
+
+    /// Model, time, searches and tokens per assistant message
+    #[serde(default)]
+    pub meta: MetaLog,
+
+    /// Server history runs whose answers are in this thread
+    #[serde(default)]
+    pub run_ids: Vec<String>,
+
+    /// Files attached to this chat, searched by its questions
+    #[serde(default)]
+    pub documents: Vec<DocumentInfo>,
+}
+
+impl Thread {
+    pub fn new() -> Self {
+        let now = js_sys::Date::now();
+        Self {
+            id: format!("{:x}-{:x}", now as u64, (js_sys::Math::random() * 1e12) as u64),
+            title: DEFAULT_TITLE.to_string(),
+            updated_at: now,
+            messages: Vec::new(),
+            sources: SourceLog::new(),
+            meta: MetaLog::new(),
+            run_ids: Vec::new(),
+            documents: Vec::new(),
+        }
+    }
+
+    /// The thread as the chat shows it.
+    pub fn conversation(&self) -> Conversation {
+        let mut conversation = Conversation::new();
+        conversation.messages = self.messages.iter().cloned().map(Message::from).collect();
+        conversation
+    }
+
+    /// Take over the chat's current state. Returns false (and changes
+    /// nothing) when there is nothing new, so opening a thread doesn't
+    /// count as using it.
+    pub fn update(
+        &mut self,
+        conversation: &Conversation,
+        sources: &SourceLog,
+        meta: &MetaLog,
+        documents: &[DocumentInfo],
+    ) -> bool {
+        let messages: Vec<StoredMessage> = conversation.messages.iter().map(StoredMessage::from).collect();
+        if messages == self.messages
+            && *sources == self.sources
+            && *meta == self.meta
+            && documents == self.documents
+        {
+            return false;
+        }
+
+        // Named after its first question, unless the user named it
+        if self.title == DEFAULT_TITLE {
+            if let Some(first) = messages.iter().find(|m| m.user) {
+                self.title = title_from(&first.text);
+            }
+        }
+        self.messages = messages;
+        self.sources = sources.clone();
+        self.meta = meta.clone();
+        self.documents = documents.to_vec();
+        self.updated_at = js_sys::Date::now();
+        true
+    }
+
+    /// A new thread with this one's messages before `index`, for asking
+    /// an edited question from there.
+    pub fn fork(&self, index: usize) -> Thread {
+        let mut fork = Thread::new();
+        let index = index.min(self.messages.len());
+        if index > 0 {
+            let title = self.title.strip_suffix(EDITED_SUFFIX).unwrap_or(&self.title);
+            fork.title = format!("{}{}", title, EDITED_SUFFIX);
+        }
+        fork.messages = self.messages[..index].to_vec();
+        fork.sources = self
+            .sources
+            .iter()
+            .filter(|(&i, _)| i < index)
+            .map(|(&i, sources)| (i, sources.clone()))
+            .collect();
+        fork.meta = self
+            .meta
+            .iter()
+            .filter(|(&i, _)| i < index)
+            .map(|(&i, meta)| (i, meta.clone()))
+            .collect();
+        fork.documents = self.documents.clone();
+        fork
+    }
+
+    /// Whether the title or any message contains `needle` (lowercase).
+    fn matches(&self, needle: &str) -> bool {
+        self.title.to_lowercase().contains(needle)
+            || self.messages.iter().any(|m| m.text.to_lowercase().contains(needle))
+    }
+}
+
+impl Default for Thread {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A question shortened to fit the sidebar.
+fn title_from(text: &str) -> String {
+    let text = text.trim();
+    if text.chars().count() <= TITLE_MAX_CHARS {
+        return text.to_string();
+    }
+    let short: String = text.chars().take(TITLE_MAX_CHARS - 1).collect();
+    format!("{}…", short.trim_end())
+}
+
+// =============================================================================
+// BROWSER STORAGE
+// =============================================================================
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+/// The saved threads, most recently used first.
+pub fn load_threads() -> Vec<Thread> {
+    let mut threads: Vec<Thread> = local_storage()
+        .and_then(|storage| storage.get_item(THREADS_KEY).ok().flatten())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    threads.sort_by(|a, b| b.updated_at.total_cmp(&a.updated_at));
+    threads
+}
+
+/// Save the threads, keeping the `MAX_THREADS` most recently used.
+pub fn save_threads(threads: &[Thread]) {
+    let mut kept: Vec<&Thread> = threads.iter().collect();
+    kept.sort_by(|a, b| b.updated_at.total_cmp(&a.updated_at));
+    kept.truncate(MAX_THREADS);
+
+    let Some(storage) = local_storage() else {
+        return;
+    };
+    if let Ok(json) = serde_json::to_string(&kept) {
+        // Storage full: the threads still live in memory for this visit
+        let _ = storage.set_item(THREADS_KEY, &json);
+    }
+}
+
+/// Id of the thread that was open last.
+pub fn load_current() -> Option<String> {
+    local_storage()?.get_item(CURRENT_THREAD_KEY).ok().flatten()
+}
+
+pub fn save_current(id: &str) {
+    if let Some(storage) = local_storage() {
+        let _ = storage.set_item(CURRENT_THREAD_KEY, id);
+    }
+}
+
+// =============================================================================
+// SERVER HISTORY
+// =============================================================================
+/// A past run on the server, as listed by `GET /api/history`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RemoteRun {
+    pub id: String,
+    pub query: String,
+    pub status: String,
+}
+
+/// A past run with its answer, from `GET /api/history/{id}`.
+#[derive(Debug, Deserialize)]
+struct RemoteRunDetail {
+    id: String,
+    query: String,
+    answer: Option<String>,
+    error: Option<String>,
+    #[serde(default)]
+    sources: Vec<Source>,
+    quick: bool,
+    model: String,
+    #[serde(default)]
+    tool_calls: Vec<serde::de::IgnoredAny>,
+    input_tokens: u64,
+    output_tokens: u64,
+    started_at: u64,
+    duration_ms: u64,
+}
+
+/// The server's recent runs. Empty when it keeps no history (404), needs
+/// a token we don't have, or can't be reached.
+pub async fn fetch_history() -> Vec<RemoteRun> {
+    let url = api_url(&format!("/api/history?limit={}", REMOTE_RUNS));
+    match Request::get(&url).send().await {
+        Ok(response) if response.ok() => response.json().await.unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+/// A server run turned into a thread: its question, its answer (or
+/// error) and its sources.
+pub async fn fetch_thread(id: &str) -> Option<Thread> {
+    let response = Request::get(&api_url(&format!("/api/history/{}", id)))
+        .send()
+        .await
+        .ok()?;
+    if !response.ok() {
+        return None;
+    }
+    let run: RemoteRunDetail = response.json().await.ok()?;
+
+    let started_ms = run.started_at as f64 * 1000.0;
+    let timestamp = timestamp_at(started_ms);
+    // Failed runs show their error, not how it was made
+    let meta = run.error.is_none().then(|| AnswerMeta {
+        model: (!run.quick).then_some(run.model),
+        duration_ms: run.duration_ms,
+        searches: run.tool_calls.len(),
+        input_tokens: run.input_tokens,
+        output_tokens: run.output_tokens,
+    });
+    let answer = match (run.answer, run.error) {
+        (Some(answer), _) => answer,
+        (None, Some(error)) => format!("⚠️ {}", error),
+        (None, None) => String::new(),
+    };
+
+    let mut thread = Thread::new();
+    thread.title = title_from(&run.query);
+    thread.updated_at = started_ms;
+    thread.messages = vec![
+        StoredMessage {
+            text: run.query,
+            user: true,
+            sender_name: "You".to_string(),
+            timestamp,
+        },
+        StoredMessage {
+            text: answer,
+            user: false,
+            sender_name: "Assistant".to_string(),
+            timestamp: timestamp_at(started_ms + run.duration_ms as f64),
+        },
+    ];
+    if !run.sources.is_empty() {
+        thread.sources.insert(1, run.sources);
+    }
+    if let Some(meta) = meta {
+        thread.meta.insert(1, meta);
+    }
+    thread.run_ids.push(run.id);
+    Some(thread)
+}
+
+// =============================================================================
+// COMPONENT
+// =============================================================================
+#[component]
+pub fn Sidebar(
+    threads: ReadSignal<Vec<Thread>>,
+    current: ReadSignal<String>,
+    remote: ReadSignal<Vec<RemoteRun>>,
+
+    /// An answer is on its way; switching threads now would lose it
+    #[prop(into)]
+    busy: Signal<bool>,
+
+    on_select: Callback<String>,
+    on_new: Callback<()>,
+    on_rename: Callback<(String, String)>,
+    on_delete: Callback<String>,
+    on_import: Callback<String>,
+) -> impl IntoView {
+    let dark_mode = use_context::<ReadSignal<bool>>()
+        .expect("should be able to get dark mode state");
+
+    // Open by default where there's room for it
+    let wide = web_sys::window()
+        .and_then(|window| window.inner_width().ok())
+        .and_then(|width| width.as_f64())
+        .is_some_and(|width| width >= 768.0);
+    let (open, set_open) = create_signal(wide);
+    let (search, set_search) = create_signal(String::new());
+
+    let sidebar_class = Signal::derive(move || {
+        if dark_mode.get() {
+            format!("{SIDEBAR_CLASS} {SIDEBAR_DARK_MODE_COLORS}")
+        } else {
+            format!("{SIDEBAR_CLASS} {SIDEBAR_LIGHT_MODE_COLORS}")
+        }
+    });
+
+    let visible_threads = move || {
+        let needle = search.get().trim().to_lowercase();
+        threads.with(|threads| {
+            let mut visible: Vec<Thread> = threads
+                .iter()
+                .filter(|thread| needle.is_empty() || thread.matches(&needle))
+                .cloned()
+                .collect();
+            visible.sort_by(|a, b| b.updated_at.total_cmp(&a.updated_at));
+            visible
+        })
+    };
+
+    // Server runs that aren't a thread already
+    let visible_runs = move || {
+        let needle = search.get().trim().to_lowercase();
+        let known: HashSet<String> =
+            threads.with(|threads| threads.iter().flat_map(|t| t.run_ids.iter().cloned()).collect());
+        remote.with(|runs| {
+            runs.iter()
+                .filter(|run| !known.contains(&run.id))
+                .filter(|run| needle.is_empty() || run.query.to_lowercase().contains(&needle))
+                .cloned()
+                .collect::<Vec<_>>()
+        })
+    };
+
+    let rename = move |thread: Thread| {
+        let Some(window) = web_sys::window() else {
+            return;
+        };
+        if let Ok(Some(title)) = window.prompt_with_message_and_default("Rename chat", &thread.title) {
+            let title = title.trim();
+            if !title.is_empty() {
+                on_rename.call((thread.id, title_from(title)));
+            }
+        }
+    };
+
+    let delete = move |thread: Thread| {
+        let confirmed = web_sys::window()
+            .and_then(|window| window.confirm_with_message(&format!("Delete \"{}\"?", thread.title)).ok())
+            .unwrap_or(false);
+        if confirmed {
+            on_delete.call(thread.id);
+        }
+    };
+
+    view! {
+        <button
+            class="fixed top-3 left-3 z-50 px-2 py-1 rounded bg-gray-800 text-white text-sm shadow"
+            title="Chats"
+            on:click=move |_| set_open.update(|open| *open = !*open)
+        >
+            "☰"
+        </button>
+        <Show when=move || open.get()>
+            <aside class=move || sidebar_class.get()>
+                <div class="flex flex-col gap-2 px-3 pt-14 pb-2">
+                    <button
+                        class="w-full px-3 py-1.5 rounded bg-orange-500 hover:bg-orange-700 text-white text-sm font-semibold disabled:opacity-50"
+                        disabled=move || busy.get()
+                        on:click=move |_| on_new.call(())
+                    >
+                        "+ New chat"
+                    </button>
+                    <input
+                        class="w-full px-2 py-1 rounded border border-gray-300 bg-transparent text-sm focus:outline-none"
+                        type="search"
+                        placeholder="Search chats"
+                        prop:value=move || search.get()
+                        on:input=move |ev| set_search.set(event_target_value(&ev))
+                    />
+                </div>
+
+                <nav class="flex-1 overflow-y-auto px-1 pb-3">
+                    <For each=visible_threads key=|thread| (thread.id.clone(), thread.title.clone()) let:thread>
+                        {
+                            let id = thread.id.clone();
+                            let is_current = move || current.get() == id;
+                            let select_id = thread.id.clone();
+                            let rename_thread = thread.clone();
+                            let delete_thread = thread.clone();
+                            view! {
+                                <div class=move || {
+                                    if is_current() {
+                                        format!("{ROW_CLASS} bg-orange-500 bg-opacity-20")
+                                    } else {
+                                        format!("{ROW_CLASS} hover:bg-gray-500 hover:bg-opacity-10")
+                                    }
+                                }>
+                                    <button
+                                        class="flex-1 min-w-0 truncate text-left disabled:cursor-not-allowed"
+                                        title=thread.title.clone()
+                                        disabled=move || busy.get()
+                                        on:click=move |_| on_select.call(select_id.clone())
+                                    >
+                                        {thread.title.clone()}
+                                    </button>
+                                    <button class=ICON_BUTTON_CLASS title="Rename" on:click=move |_| rename(rename_thread.clone())>
+                                        "✏️"
+                                    </button>
+                                    <button class=ICON_BUTTON_CLASS title="Delete" on:click=move |_| delete(delete_thread.clone())>
+                                        "🗑"
+                                    </button>
+                                </div>
+                            }
+                        }
+                    </For>
+
+                    <Show when=move || !visible_runs().is_empty()>
+                        <div class=SECTION_CLASS>"Server history"</div>
+                        <For each=visible_runs key=|run| run.id.clone() let:run>
+                            {
+                                let id = run.id.clone();
+                                let failed = run.status != "success";
+                                view! {
+                                    <button
+                                        class=format!("{ROW_CLASS} w-full text-left hover:bg-gray-500 hover:bg-opacity-10 disabled:cursor-not-allowed")
+                                        title=run.query.clone()
+                                        disabled=move || busy.get()
+                                        on:click=move |_| on_import.call(id.clone())
+                                    >
+                                        <span class="truncate opacity-80">{run.query.clone()}</span>
+                                        {failed.then(|| view! { <span title="Failed">"⚠️"</span> })}
+                                    </button>
+                                }
+                            }
+                        </For>
+                    </Show>
+                </nav>
+            </aside>
+        </Show>
+    }
+}
@@ -0,0 +1,100 @@
+// Dimensionless Developments Rust Ai
+// # Source Cards Component
+// The web pages an answer is based on, as numbered cards under the
+// message: favicon, title, domain and snippet. A citation like [2] in the
+// answer scrolls to card 2 and highlights it for a moment. Passages from
+// attached documents get a document icon and no link.
+
+use std::collections::HashMap;
+
+use gloo_timers::callback::Timeout;
+use leptos::*;
+use web_sys::{ScrollBehavior, ScrollIntoViewOptions, ScrollLogicalPosition};
+
+use crate::app::api_url;
+use crate::components::markdown::safe_href;
+use crate::protocol::{Source, ATTACHMENT_URL_PREFIX};
+
+const CARD_CLASS: &str = "flex gap-2 rounded bg-white bg-opacity-70 p-2 text-xs source-card";
+const NUMBER_CLASS: &str = "shrink-0 font-mono font-semibold text-blue-700";
+const TITLE_CLASS: &str = "font-semibold text-blue-700 underline hover:text-orange-500 break-words";
+
+/// Class added to a card while it is highlighted (custom.css).
+const HIGHLIGHT_CLASS: &str = "source-highlight";
+const HIGHLIGHT_MS: u32 = 2_000;
+
+/// Sources per assistant message, keyed by the message's index in the
+/// conversation.
+pub type SourceLog = HashMap<usize, Vec<Source>>;
+
+/// DOM id of source `number` (from 1) of message `message`.
+pub fn source_id(message: usize, number: usize) -> String {
+    format!("source-{}-{}", message, number)
+}
+
+#[component]
+pub fn SourceCards(sources: Vec<Source>, message: usize) -> impl IntoView {
+    view! {
+        <div class="mt-2 grid gap-1.5">
+            {sources.into_iter().enumerate().map(|(i, source)| {
+                let number = i + 1;
+                let attachment = source.url.starts_with(ATTACHMENT_URL_PREFIX);
+                let icon = if attachment {
+                    view! { <span class="w-4 shrink-0 leading-none">"📄"</span> }.into_view()
+                } else {
+                    let favicon = api_url(&format!("/api/favicon/{}", domain(&source.url)));
+                    view! { <img src=favicon alt="" class="w-4 h-4 mt-0.5 shrink-0" loading="lazy"/> }.into_view()
+                };
+                let href = safe_href(&source.url).map(str::to_string);
+                let label = if attachment {
+                    "Attached document".to_string()
+                } else {
+                    domain(&source.url).trim_start_matches("www.").to_string()
+                };
+
+                view! {
+                    <div id=source_id(message, number) class=CARD_CLASS>
+                        <span class=NUMBER_CLASS>{format!("[{}]", number)}</span>
+                        {icon}
+                        <div class="min-w-0">
+                            <a href=href class=TITLE_CLASS target="_blank" rel="noopener noreferrer">
+                                {source.title}
+                            </a>
+                            <div class="text-gray-500 truncate">{label}</div>
+                            <div class="text-gray-700">{source.snippet}</div>
+                        </div>
+                    </div>
+                }
+            }).collect_view()}
+        </div>
+    }
+}
+
+/// Scroll the card with DOM id `id` into view and highlight it briefly.
+pub fn highlight_source(id: &str) {
+    let Some(card) = web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.get_element_by_id(id))
+    else {
+        return;
+    };
+
+    let options = ScrollIntoViewOptions::new();
+    options.set_behavior(ScrollBehavior::Smooth);
+    options.set_block(ScrollLogicalPosition::Center);
+    card.scroll_into_view_with_scroll_into_view_options(&options);
+
+    let _ = card.class_list().add_1(HIGHLIGHT_CLASS);
+    Timeout::new(HIGHLIGHT_MS, move || {
+        let _ = card.class_list().remove_1(HIGHLIGHT_CLASS);
+    })
+    .forget();
+}
+
+/// The host of `url`: `https://www.rust-lang.org:443/learn` -> `www.rust-lang.org`.
+fn domain(url: &str) -> &str {
+    let rest = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    host.split(':').next().unwrap_or_default()
+}
@@ -7,19 +7,33 @@
 // - Async programming with tokio
 // - The Agent pattern in AI applications
 
-use anyhow::Result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rig::agent::{CancelSignal, PromptHook};
 use rig::client::{CompletionClient, ProviderClient};
-use rig::completion::Prompt;
+use rig::completion::{CompletionModel, CompletionResponse, Message, Prompt};
 use rig::providers::ollama;
-use tracing::{debug, info};
+use tracing::{debug, field, info, info_span, instrument, Span};
+use uuid::Uuid;
 
 use crate::config::Config;
-use crate::tools::WebSearchTool;
+use crate::documents::Document;
+use crate::error::AgentError;
+use crate::monitoring;
+use crate::protocol::{ActivityStep, AnswerMeta};
+use crate::tools::{
+    ActivitySink, DocumentSearchTool, FetchPageTool, SearchResult, ToolCallLog, ToolCallRecord,
+    WebSearchTool,
+};
 
 // =============================================================================
 // SYSTEM PROMPT
 // =============================================================================
 /// The system prompt defines the agent's personality and behavior.
+///
+/// It is followed by `RESPONSE_FORMAT`, which every research run shares.
 const RESEARCH_SYSTEM_PROMPT: &str = r#"
 You are a helpful AI research assistant. Your task is to research topics and provide summaries.
 
@@ -28,17 +42,38 @@ IMPORTANT INSTRUCTIONS:
 2. After getting search results, IMMEDIATELY synthesize them into a summary
 3. DO NOT make multiple search requests - one search is sufficient
 4. If the first search returns no results, try ONE simpler query, then summarize
+5. If a result looks essential but its snippet says too little, you may read it with the
+   fetch_page tool - at most two pages per question
+"#;
+
+/// The system prompt of a deep research run: several searches, each
+/// filling a gap the earlier results left.
+const DEEP_RESEARCH_SYSTEM_PROMPT: &str = r#"
+You are a thorough AI research assistant. Your task is to research hard questions in depth and
+provide summaries.
+
+IMPORTANT INSTRUCTIONS:
+1. Start with one web_search for the question as asked
+2. Read the results and note what they leave open: other angles, details, recent developments
+3. Search again for each open point - up to FOUR searches in total, each with a new query
+4. Never repeat a query; if a search returns nothing, try a simpler one
+5. Where a snippet says too little, read the page with the fetch_page tool - at most two pages
+6. Once the results cover the question, stop searching and synthesize ALL of them into a summary
+"#;
 
+/// How every research answer is laid out.
+const RESPONSE_FORMAT: &str = r#"
 When responding after a search, format your response with clear sections separated by blank lines:
 
 **Overview**:
 Brief introduction to the topic
 
 **Key Sources Found**:
-List the URLs from the search
+List the sources as "[n] Title - URL", using the numbers shown in the search results
 
 **Summary**:
-Synthesize what these sources likely cover based on their titles/domains
+Synthesize what these sources likely cover based on their titles/domains. Cite the
+sources you rely on with their number in square brackets, like [1] or [2][3]
 
 **Next Steps**:
 Suggest what the user might explore
@@ -47,6 +82,81 @@ IMPORTANT: Add a blank line between each section for better readability.
 Always provide a response after seeing search results. Never keep searching indefinitely.
 "#;
 
+/// Tool-calling rounds allowed per research run (`multi_turn`).
+const MAX_TOOL_ROUNDS: usize = 5;
+
+/// Tool-calling rounds of a deep research run.
+const DEEP_TOOL_ROUNDS: usize = 10;
+
+/// Longest page text the agent gets from `fetch_page`. Small local models
+/// have small context windows; the start of a page is usually enough.
+const PAGE_TEXT_MAX_CHARS: usize = 6_000;
+
+/// Pages the agent may read per run, whatever the prompt says.
+const MAX_PAGES_PER_RUN: usize = 2;
+
+// =============================================================================
+// RUN OUTCOME
+// =============================================================================
+/// Everything a finished run produced, not just the final text.
+///
+/// `research()` and `quick_search()` return only the answer; the
+/// `_detailed` variants return this so callers (like the history store)
+/// can keep the sources and tool calls that led to it.
+#[derive(Debug, Clone, Default)]
+pub struct ResearchOutcome {
+    /// The final report shown to the user
+    pub answer: String,
+
+    /// Every tool call made during the run, in order
+    pub tool_calls: Vec<ToolCallRecord>,
+
+    /// Number of LLM completion rounds (0 for quick searches)
+    pub rounds: usize,
+
+    /// Token usage summed over all rounds
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+
+    /// Model that wrote the answer (`None` for quick searches)
+    pub model: Option<String>,
+
+    /// How long the run took, not counting time spent in the queue
+    pub duration_ms: u64,
+
+    /// Id of the run in the history, once the server has saved it
+    pub run_id: Option<String>,
+}
+
+impl ResearchOutcome {
+    /// Search results the run saw, without duplicate URLs.
+    pub fn sources(&self) -> Vec<SearchResult> {
+        let mut seen = std::collections::HashSet::new();
+        self.tool_calls
+            .iter()
+            .flat_map(|call| call.results.iter())
+            .filter(|result| seen.insert(result.url.clone()))
+            .cloned()
+            .collect()
+    }
+
+    /// Number of searches (web and attached documents) the run made.
+    pub fn searches(&self) -> usize {
+        self.tool_calls.len()
+    }
+
+    /// What the chat shows about how the answer was made.
+    pub fn meta(&self) -> AnswerMeta {
+        AnswerMeta {
+            model: self.model.clone(),
+            duration_ms: self.duration_ms,
+            searches: self.searches(),
+            input_tokens: self.input_tokens,
+            output_tokens: self.output_tokens,
+        }
+    }
+}
+
 // =============================================================================
 // RESEARCH AGENT STRUCT
 // =============================================================================
@@ -62,6 +172,18 @@ pub struct ResearchAgent {
 
     /// The web search tool
     search_tool: WebSearchTool,
+
+    /// Where progress is reported during a run, if anywhere
+    activity: Option<ActivitySink>,
+
+    /// Tool-calling rounds allowed per run
+    max_rounds: usize,
+
+    /// Instructions of the system prompt (`RESPONSE_FORMAT` follows them)
+    instructions: &'static str,
+
+    /// Files attached to the conversation, searchable next to the web
+    documents: Vec<Arc<Document>>,
 }
 
 impl ResearchAgent {
@@ -77,17 +199,88 @@ impl ResearchAgent {
         Self {
             config,
             search_tool,
+            activity: None,
+            max_rounds: MAX_TOOL_ROUNDS,
+            instructions: RESEARCH_SYSTEM_PROMPT,
+            documents: Vec::new(),
         }
     }
 
+    /// Research in depth: the agent is told to search several times, and
+    /// gets ten tool-calling rounds instead of the usual five to do it.
+    pub fn deep(mut self) -> Self {
+        self.max_rounds = DEEP_TOOL_ROUNDS;
+        self.instructions = DEEP_RESEARCH_SYSTEM_PROMPT;
+        self
+    }
+
+    /// The system prompt the agent is built with.
+    fn system_prompt(&self) -> String {
+        format!("{}{}", self.instructions, RESPONSE_FORMAT)
+    }
+
+    /// Let the agent search `documents` (with the `search_documents` tool)
+    /// as well as the web. Quick searches only search the web.
+    pub fn with_documents(mut self, documents: Vec<Arc<Document>>) -> Self {
+        self.documents = documents;
+        self
+    }
+
+    /// Report searches, page reads and model rounds to `activity` while
+    /// a run is in progress.
+    pub fn with_activity(mut self, activity: ActivitySink) -> Self {
+        self.search_tool = self.search_tool.with_activity(activity.clone());
+        self.activity = Some(activity);
+        self
+    }
+
     /// Research a topic and return a comprehensive summary.
     ///
     /// # Rust Concept: Ownership and Borrowing
     ///
     /// `&self` means we borrow the ResearchAgent immutably.
     /// `&str` for the query borrows the string data without copying.
-    pub async fn research(&self, query: &str) -> Result<String> {
-        info!(query = %query, "Starting research task");
+    ///
+    /// Failures are classified into an `AgentError` so callers can tell a
+    /// stopped Ollama apart from a missing model or a blocked search.
+    pub async fn research(&self, query: &str) -> Result<String, AgentError> {
+        self.research_detailed(query).await.map(|outcome| outcome.answer)
+    }
+
+    /// Like `research()`, but also returns the sources, tool calls and
+    /// token usage of the run.
+    ///
+    /// # Rust Concept: Spans with #[instrument]
+    ///
+    /// `#[instrument]` wraps the function in a tracing span. Every log line
+    /// emitted during the run (including from the search tool) carries the
+    /// run id, model and a hash of the query, so one run can be followed
+    /// through interleaved server logs without logging the query itself.
+    #[instrument(
+        name = "research",
+        skip_all,
+        fields(
+            run_id = %Uuid::new_v4(),
+            model = %self.config.model,
+            query_hash = %query_hash(query),
+            rounds = field::Empty,
+            tokens.input = field::Empty,
+            tokens.output = field::Empty,
+        )
+    )]
+    pub async fn research_detailed(&self, query: &str) -> Result<ResearchOutcome, AgentError> {
+        let started = Instant::now();
+        let mut result = self.run_research(query).await;
+        monitoring::record_research("research", outcome_label(&result), started.elapsed());
+        if let Ok(outcome) = &mut result {
+            outcome.duration_ms = started.elapsed().as_millis() as u64;
+        }
+        result
+    }
+
+    /// The research run itself; `research_detailed()` wraps it with metrics.
+    async fn run_research(&self, query: &str) -> Result<ResearchOutcome, AgentError> {
+        info!("Starting research task");
 
         // Step 1: Create the Ollama client using the builder pattern
         // In Rig 0.27, use ollama::Client::from_env() which reads OLLAMA_API_BASE_URL
@@ -112,69 +305,263 @@ impl ResearchAgent {
         // - Set the model
         // - Add a system prompt (preamble)
         // - Register tools the agent can use
-        let agent = ollama_client
+        //
+        // Each run gets its own call log, so concurrent runs don't mix
+        // their sources.
+        let tool_calls = ToolCallLog::default();
+        let mut fetch_tool =
+            FetchPageTool::new(PAGE_TEXT_MAX_CHARS).with_page_limit(MAX_PAGES_PER_RUN);
+        if let Some(activity) = &self.activity {
+            fetch_tool = fetch_tool.with_activity(activity.clone());
+        }
+        let mut builder = ollama_client
             .agent(&self.config.model)
-            .preamble(RESEARCH_SYSTEM_PROMPT)
-            .tool(self.search_tool.clone())
-            .build();
+            .preamble(&self.system_prompt())
+            .temperature(self.config.temperature as f64)
+            .tool(self.search_tool.clone().with_call_log(tool_calls.clone()))
+            .tool(fetch_tool);
+        if !self.documents.is_empty() {
+            let mut document_tool =
+                DocumentSearchTool::new(self.documents.clone()).with_call_log(tool_calls.clone());
+            if let Some(activity) = &self.activity {
+                document_tool = document_tool.with_activity(activity.clone());
+            }
+            builder = builder.tool(document_tool);
+        }
+        let agent = builder.build();
 
-        info!("Agent configured, executing research query");
+        info!(documents = self.documents.len(), "Agent configured, executing research query");
 
         // Step 3: Execute the research query
-        let enhanced_query = format!(
+        let mut enhanced_query = format!(
             "Research the following topic thoroughly. Use the web_search tool to find \
              current information, then provide a comprehensive summary with sources:\n\n{}",
             query
         );
+        if !self.documents.is_empty() {
+            let names: Vec<&str> = self.documents.iter().map(|d| d.name.as_str()).collect();
+            enhanced_query.push_str(&format!(
+                "\n\nThe user attached these documents: {}. Search them with the \
+                 search_documents tool as well, and cite their passages by number like \
+                 the web results.",
+                names.join(", ")
+            ));
+        }
 
-        let response = agent
-            .prompt(&enhanced_query)
-            .multi_turn(5) // Allow up to 5 iterations of tool calls
-            .await
-            .map_err(|e| anyhow::anyhow!("Agent execution failed: {}", e))?;
+        // # Rust Concept: Timeouts as Futures
+        // tokio::time::timeout wraps any future and resolves to Err(Elapsed)
+        // if it doesn't finish in time, so a hung model can't block forever.
+        let timeout_secs = self.config.research_timeout_secs;
+        let hook = RoundTracer {
+            activity: self.activity.clone(),
+            max_rounds: self.max_rounds,
+            ..Default::default()
+        };
+        let response = tokio::time::timeout(
+            Duration::from_secs(timeout_secs),
+            agent
+                .prompt(&enhanced_query)
+                .multi_turn(self.max_rounds) // Allow up to 5 (deep: 10) iterations of tool calls
+                .with_hook(hook.clone())
+                .extended_details(),
+        )
+        .await
+        .map_err(|_| AgentError::Timeout { secs: timeout_secs })?
+        .map_err(|e| {
+            AgentError::from_llm_error(&e, &self.config.ollama_host, &self.config.model)
+        })?;
+
+        let span = Span::current();
+        span.record("rounds", hook.rounds());
+        span.record("tokens.input", response.total_usage.input_tokens);
+        span.record("tokens.output", response.total_usage.output_tokens);
+        monitoring::record_tokens(
+            &self.config.model,
+            response.total_usage.input_tokens,
+            response.total_usage.output_tokens,
+        );
 
         info!("Research completed successfully");
 
-        Ok(response)
+        let tool_calls = tool_calls.lock().map(|calls| calls.clone()).unwrap_or_default();
+        Ok(ResearchOutcome {
+            answer: response.output,
+            tool_calls,
+            rounds: hook.rounds(),
+            input_tokens: response.total_usage.input_tokens,
+            output_tokens: response.total_usage.output_tokens,
+            model: Some(self.config.model.clone()),
+            ..Default::default()
+        })
     }
 
     /// Perform a quick search without full agent reasoning.
     ///
     /// This is useful when you just want search results without
     /// the agent synthesizing them.
-    pub async fn quick_search(&self, query: &str) -> Result<String> {
-        info!(query = %query, "Performing quick search");
+    pub async fn quick_search(&self, query: &str) -> Result<String, AgentError> {
+        self.quick_search_detailed(query).await.map(|outcome| outcome.answer)
+    }
+
+    /// Like `quick_search()`, but also returns the raw search results.
+    #[instrument(
+        name = "quick_search",
+        skip_all,
+        fields(run_id = %Uuid::new_v4(), query_hash = %query_hash(query))
+    )]
+    pub async fn quick_search_detailed(&self, query: &str) -> Result<ResearchOutcome, AgentError> {
+        let started = Instant::now();
+        let mut result = self.run_quick_search(query).await;
+        monitoring::record_research("quick", outcome_label(&result), started.elapsed());
+        if let Ok(outcome) = &mut result {
+            outcome.duration_ms = started.elapsed().as_millis() as u64;
+        }
+        result
+    }
+
+    async fn run_quick_search(&self, query: &str) -> Result<ResearchOutcome, AgentError> {
+        info!("Performing quick search");
+
+        let results = self.search_tool.search_reported(query).await?;
+        let answer = format_results(query, &results);
+
+        Ok(ResearchOutcome {
+            answer,
+            tool_calls: vec![ToolCallRecord {
+                tool: "web_search".to_string(),
+                query: query.to_string(),
+                results,
+                error: None,
+            }],
+            ..Default::default()
+        })
+    }
+}
+
+/// Markdown list of search results, as shown for quick searches.
+fn format_results(query: &str, results: &[SearchResult]) -> String {
+    if results.is_empty() {
+        return format!("No results found for: {}", query);
+    }
+
+    // Format results nicely
+    let formatted: String = results
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            format!(
+                "{}. **{}**\n   {}\n   URL: {}\n",
+                i + 1,
+                r.title,
+                r.snippet,
+                r.url
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!("## Search Results\n\n{}", formatted)
+}
+
+/// Metrics label for the outcome of a run: `success` or the error code.
+fn outcome_label<T>(result: &Result<T, AgentError>) -> &'static str {
+    match result {
+        Ok(_) => "success",
+        Err(e) => e.code(),
+    }
+}
+
+// =============================================================================
+// COMPLETION ROUND TRACING
+// =============================================================================
+/// A Rig prompt hook that opens one `llm_completion` span per model call.
+///
+/// `multi_turn(5)` hides the individual LLM calls from us; the hook is how
+/// Rig lets us observe them. The span is created when a call starts and
+/// dropped (closed) when its response arrives, so its duration is the
+/// model latency for that round, and it carries the round's token counts.
+///
+/// # Rust Concept: Shared State with Arc<Mutex<...>>
+///
+/// Rig clones the hook, so every clone must see the same counter and span.
+/// `Arc` shares ownership across clones; `Mutex` allows mutation.
+///
+/// It also reports each round to the run's activity sink, if it has one.
+#[derive(Clone, Default)]
+struct RoundTracer {
+    rounds: Arc<AtomicUsize>,
+    current: Arc<Mutex<Option<Span>>>,
+    activity: Option<ActivitySink>,
+    max_rounds: usize,
+}
 
-        let results = self
-            .search_tool
-            .search(query)
-            .await
-            .map_err(|e| anyhow::anyhow!("Search failed: {}", e))?;
+impl RoundTracer {
+    /// Number of completion rounds started so far.
+    fn rounds(&self) -> usize {
+        self.rounds.load(Ordering::SeqCst)
+    }
+}
+
+impl<M: CompletionModel> PromptHook<M> for RoundTracer {
+    async fn on_completion_call(
+        &self,
+        _prompt: &Message,
+        history: &[Message],
+        _cancel: CancelSignal,
+    ) {
+        let round = self.rounds.fetch_add(1, Ordering::SeqCst) + 1;
+        let span = info_span!(
+            "llm_completion",
+            round,
+            history_len = history.len(),
+            tokens.input = field::Empty,
+            tokens.output = field::Empty,
+        );
+        debug!(parent: &span, "Sending completion request");
 
-        if results.is_empty() {
-            return Ok(format!("No results found for: {}", query));
+        if let Ok(mut current) = self.current.lock() {
+            *current = Some(span);
         }
 
-        // Format results nicely
-        let formatted: String = results
-            .iter()
-            .enumerate()
-            .map(|(i, r)| {
-                format!(
-                    "{}. **{}**\n   {}\n   URL: {}\n",
-                    i + 1,
-                    r.title,
-                    r.snippet,
-                    r.url
-                )
-            })
-            .collect::<Vec<_>>()
-            .join("\n");
-
-        Ok(format!("## Search Results\n\n{}", formatted))
+        if let Some(activity) = &self.activity {
+            // Every tool round is followed by one more call for the answer
+            activity.report(ActivityStep::Thinking {
+                round,
+                max_rounds: self.max_rounds + 1,
+            });
+        }
+    }
+
+    async fn on_completion_response(
+        &self,
+        _prompt: &Message,
+        response: &CompletionResponse<M::Response>,
+        _cancel: CancelSignal,
+    ) {
+        let span = self.current.lock().ok().and_then(|mut current| current.take());
+
+        if let Some(span) = span {
+            span.record("tokens.input", response.usage.input_tokens);
+            span.record("tokens.output", response.usage.output_tokens);
+            // Dropping the span closes it and ends its timing
+        }
     }
 }
 
+/// Short, stable fingerprint of a query for log correlation.
+///
+/// This is 64-bit FNV-1a: tiny, dependency-free and identical across runs
+/// and Rust versions (unlike `DefaultHasher`), so the same question always
+/// gets the same hash in the logs.
+pub fn query_hash(query: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in query.trim().to_lowercase().bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
 // =============================================================================
 // UNIT TESTS
 // =============================================================================
@@ -190,9 +577,52 @@ mod tests {
         assert_eq!(agent.config.model, "llama3.2");
     }
 
+    #[test]
+    fn test_query_hash_is_stable() {
+        assert_eq!(query_hash("What is Rust?"), query_hash("  what is rust? "));
+        assert_ne!(query_hash("What is Rust?"), query_hash("What is Go?"));
+        assert_eq!(query_hash("").len(), 16);
+    }
+
+    #[test]
+    fn test_outcome_meta_counts_searches() {
+        let search = |tool: &str| ToolCallRecord {
+            tool: tool.to_string(),
+            query: "rust".to_string(),
+            results: Vec::new(),
+            error: None,
+        };
+        let outcome = ResearchOutcome {
+            tool_calls: vec![search("web_search"), search("search_documents"), search("web_search")],
+            input_tokens: 120,
+            output_tokens: 40,
+            model: Some("llama3.2".to_string()),
+            duration_ms: 1_500,
+            ..Default::default()
+        };
+
+        let meta = outcome.meta();
+        assert_eq!(meta.searches, 3);
+        assert_eq!(meta.model.as_deref(), Some("llama3.2"));
+        assert_eq!(meta.duration_ms, 1_500);
+        assert_eq!((meta.input_tokens, meta.output_tokens), (120, 40));
+    }
+
     #[test]
     fn test_system_prompt_not_empty() {
         assert!(!RESEARCH_SYSTEM_PROMPT.is_empty());
         assert!(RESEARCH_SYSTEM_PROMPT.contains("research"));
     }
+
+    #[test]
+    fn test_deep_agent_has_its_own_instructions() {
+        let research = ResearchAgent::new(Config::default());
+        let deep = ResearchAgent::new(Config::default()).deep();
+
+        assert_ne!(research.system_prompt(), deep.system_prompt());
+        assert!(research.system_prompt().contains("ONCE"));
+        assert!(!deep.system_prompt().contains("ONCE"));
+        assert!(deep.system_prompt().ends_with(RESPONSE_FORMAT));
+        assert_eq!((research.max_rounds, deep.max_rounds), (MAX_TOOL_ROUNDS, DEEP_TOOL_ROUNDS));
+    }
 }
\ No newline at end of file
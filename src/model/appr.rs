@@ -1,23 +1,145 @@
 // Dimensionless Developments Rust Ai
 // # Browser frontend UI
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::rc::Rc;
 
 use futures::stream::SplitSink;
+use futures::{SinkExt, StreamExt};
+use gloo_net::websocket::futures::WebSocket;
+use gloo_net::websocket::Message as WsMessage;
+use gloo_timers::future::TimeoutFuture;
 use leptos::*;
 use leptos_router::*;
 
-use crate::components::chat_area::ChatArea;
+use crate::components::activity_panel::ActivityLog;
+use crate::components::chat_area::{now_timestamp, ChatArea, MetaLog};
+use crate::components::connection_status::{ConnectionIndicator, ConnectionStatus};
 use crate::components::type_area::TypeArea;
 use crate::components::landing::Landing;
+use crate::components::preferences;
+use crate::components::settings_drawer::{self, SettingsDrawer};
+use crate::components::sidebar::{self, RemoteRun, Sidebar, Thread};
+use crate::components::source_cards::SourceLog;
 use crate::model::conversation::{Conversation, Message};
+use crate::protocol::{ClientMessage, Envelope, QueryOptions, ServerEvent};
+
+/// Placeholder shown in the assistant bubble while a query waits for a slot.
+const QUEUED_PREFIX: &str = "⏳ Waiting for a free research slot";
+
+/// Placeholder shown in the assistant bubble while the question waits for
+/// the connection to come back.
+const OFFLINE_PREFIX: &str = "📴 Offline - your question will be sent when the connection is back";
+
+/// Shown in place of an answer the user stopped.
+const STOPPED_TEXT: &str = "⏹ Stopped";
+
+/// Wait before the first reconnect attempt; it doubles after every failed
+/// attempt, up to `RECONNECT_MAX_MS`.
+const RECONNECT_BASE_MS: u32 = 1_000;
+const RECONNECT_MAX_MS: u32 = 30_000;
+
+/// The sending half of the current connection; `None` while offline.
+type SharedSender = Rc<RefCell<Option<SplitSink<WebSocket, WsMessage>>>>;
+
+/// sessionStorage keys for the server-side session, so reloading the page
+/// picks the chat up where it left off (including an answer still running).
+const SESSION_KEY: &str = "research-agent.session";
+const LAST_SEQ_KEY: &str = "research-agent.last-seq";
+
+/// The chat's signals, handed to the connection loop as one piece.
+///
+/// # Rust Concept: Copy Types
+///
+/// Leptos signals are small `Copy` handles into the reactive runtime, so a
+/// struct of them is `Copy` too and can be passed around freely.
+#[derive(Clone, Copy)]
+struct ChatState {
+    conversation: ReadSignal<Conversation>,
+    set_conversation: WriteSignal<Conversation>,
+    set_activity: WriteSignal<ActivityLog>,
+    set_sources: WriteSignal<SourceLog>,
+    set_meta: WriteSignal<MetaLog>,
+
+    /// The open thread, which answers are filed under
+    current: ReadSignal<String>,
+    set_threads: WriteSignal<Vec<Thread>>,
+}
+
+impl ChatState {
+    /// Index of the assistant message currently being answered, if any.
+    fn answering(&self) -> Option<usize> {
+        self.conversation.with_untracked(|c| {
+            let last = c.messages.len().checked_sub(1)?;
+            (!c.messages[last].user).then_some(last)
+        })
+    }
+
+    /// Close the answer in progress with `text`, as if it had arrived.
+    fn finish_answer(&self, text: &str) {
+        if let Some(index) = self.answering() {
+            self.set_activity.update(|log| {
+                if let Some(activity) = log.get_mut(&index) {
+                    activity.done = true;
+                }
+            });
+        }
+        self.set_conversation.update(|c| {
+            if awaiting_answer(c) {
+                if let Some(last_msg) = c.messages.last_mut() {
+                    last_msg.text = text.to_string();
+                    last_msg.timestamp = now_timestamp();
+                }
+            }
+        });
+    }
+}
+
+/// An empty assistant message, filled in as the server answers.
+fn answer_placeholder() -> Message {
+    Message {
+        text: String::new(),
+        user: false,
+        sender_name: "Assistant".to_string(),
+        timestamp: now_timestamp(),
+    }
+}
+
+/// Whether the last question is still waiting for its answer.
+fn awaiting_answer(conversation: &Conversation) -> bool {
+    conversation.messages.last().is_some_and(|last| {
+        !last.user
+            && (last.text.is_empty()
+                || last.text.starts_with(QUEUED_PREFIX)
+                || last.text.starts_with(OFFLINE_PREFIX))
+    })
+}
+
+/// The tab's sessionStorage, if the browser allows it.
+fn session_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.session_storage().ok().flatten()
+}
 
 #[component]
 pub fn App() -> impl IntoView {
+    // The theme, font size and layout the user picked
+    let (preferences, set_preferences) = create_signal(preferences::load_preferences());
+    let system_dark = preferences::system_dark_mode();
+    let is_dark = move || preferences.with(|p| p.theme.is_dark(system_dark.get()));
+
     // Allow any component to get dark mode state via context
-    let (dark_mode, _) = create_signal(true);
+    let (dark_mode, set_dark_mode) = create_signal(is_dark());
+    create_effect(move |_| set_dark_mode.set(is_dark()));
+    create_effect(move |_| {
+        preferences.with(|p| {
+            preferences::save_preferences(p);
+            preferences::apply_preferences(p, dark_mode.get());
+        });
+    });
     provide_context(dark_mode);
+    provide_context(preferences);
+    provide_context(set_preferences);
 
     view! {
         <Router>
@@ -29,122 +151,656 @@ pub fn App() -> impl IntoView {
     }
 }
 
+
 #[component]
 pub fn Chat() -> impl IntoView {
-    let (conversation, set_conversation) = create_signal(Conversation::new());
-
-    use gloo_net::websocket::futures::WebSocket;
-    use gloo_net::websocket::Message::Text as Txt;
-    use futures::{SinkExt, StreamExt};
-    
-    let client: Rc<RefCell<Option<SplitSink<WebSocket, gloo_net::websocket::Message>>>>
-        = Default::default();
-
-    let client_clone = client.clone();
-    
-    // Only run once on mount
-    let _setup = create_resource(
-        || (),
-        move |_| {
-            let client_clone = client_clone.clone();
-            let set_conversation = set_conversation.clone();
-            
-            async move {
-                let location = web_sys::window().unwrap().location();
-                let hostname = location.hostname().expect("failed to retrieve origin hostname");
-                let ws_url = format!("ws://{}:3000/ws", hostname);
-
-                if let Ok(connection) = WebSocket::open(&ws_url) {
-                    let (sender, mut recv) = connection.split();
-                    
-                    spawn_local(async move {
-                        let mut buffer = String::new();
-                        while let Some(msg) = recv.next().await {
-                            match msg {
-                                Ok(Txt(msg)) => {
-                                    buffer.push_str(&msg);
-                                    
-                                    if buffer.len() >= 10 || buffer.contains('\n') {
-                                        let text_to_add = buffer.clone();
-                                        buffer.clear();
-                                        
-                                        set_conversation.update(move |c| {
-                                            if let Some(last_msg) = c.messages.last_mut() {
-                                                if !last_msg.user {
-                                                    last_msg.text.push_str(&text_to_add);
-                                                }
-                                            }
-                                        });
-                                    }
-                                }
-                                _ => { 
-                                    if !buffer.is_empty() {
-                                        let text_to_add = buffer.clone();
-                                        set_conversation.update(move |c| {
-                                            if let Some(last_msg) = c.messages.last_mut() {
-                                                if !last_msg.user {
-                                                    last_msg.text.push_str(&text_to_add);
-                                                }
-                                            }
-                                        });
-                                    }
-                                    break; 
-                                }
+    // Reopen the thread that was open last, or start a new one
+    let mut saved_threads = sidebar::load_threads();
+    let open_thread = sidebar::load_current()
+        .and_then(|id| saved_threads.iter().find(|thread| thread.id == id).cloned())
+        .unwrap_or_else(|| {
+            let thread = Thread::new();
+            saved_threads.insert(0, thread.clone());
+            thread
+        });
+
+    let (threads, set_threads) = create_signal(saved_threads);
+    let (current, set_current) = create_signal(open_thread.id.clone());
+    let (remote, set_remote) = create_signal(Vec::<RemoteRun>::new());
+    let (conversation, set_conversation) = create_signal(open_thread.conversation());
+    let (activity, set_activity) = create_signal(ActivityLog::new());
+    let (attachments, set_attachments) = create_signal(open_thread.documents.clone());
+    let (sources, set_sources) = create_signal(open_thread.sources);
+    let (meta, set_meta) = create_signal(open_thread.meta);
+    let (status, set_status) = create_signal(ConnectionStatus::Connecting);
+    let (settings, set_settings) = create_signal(settings_drawer::load_settings());
+    let chat = ChatState {
+        conversation,
+        set_conversation,
+        set_activity,
+        set_sources,
+        set_meta,
+        current,
+        set_threads,
+    };
+    let busy = Signal::derive(move || conversation.with(awaiting_answer));
+
+    // File every change of the chat under the open thread, and keep the
+    // threads in localStorage
+    create_effect(move |_| {
+        let id = current.get_untracked();
+        conversation.with(|conversation| {
+            sources.with(|sources| {
+                meta.with(|meta| {
+                    attachments.with(|documents| {
+                        set_threads.update(|threads| {
+                            if let Some(thread) = threads.iter_mut().find(|thread| thread.id == id) {
+                                thread.update(conversation, sources, meta, documents);
                             }
-                        }
+                        });
                     });
+                });
+            });
+        });
+    });
+    create_effect(move |_| threads.with(|threads| sidebar::save_threads(threads)));
+    create_effect(move |_| sidebar::save_current(&current.get()));
+    create_effect(move |_| settings.with(settings_drawer::save_settings));
 
-                    *client_clone.borrow_mut() = Some(sender);
-                }
+    // Runs the server has in its history, if it keeps one
+    spawn_local(async move {
+        set_remote.set(sidebar::fetch_history().await);
+    });
+
+    // Show another thread. All signals change in one batch, so the
+    // effect above never files one thread's messages under another.
+    let open = move |thread: Thread| {
+        batch(|| {
+            set_current.set(thread.id.clone());
+            set_activity.set(ActivityLog::new());
+            set_sources.set(thread.sources.clone());
+            set_meta.set(thread.meta.clone());
+            set_attachments.set(thread.documents.clone());
+            set_conversation.set(thread.conversation());
+        });
+    };
+
+    // The settings, plus the open thread's attachments
+    let query_options = move || QueryOptions {
+        documents: attachments.with_untracked(|documents| documents.iter().map(|d| d.id.clone()).collect()),
+        ..settings.get_untracked()
+    };
+    let on_select = Callback::new(move |id: String| {
+        if busy.get_untracked() {
+            return;
+        }
+        let thread = threads.with_untracked(|threads| threads.iter().find(|thread| thread.id == id).cloned());
+        if let Some(thread) = thread {
+            open(thread);
+        }
+    });
+    let start_thread = move || {
+        let thread = Thread::new();
+        set_threads.update(|threads| threads.insert(0, thread.clone()));
+        open(thread);
+    };
+    let on_new = Callback::new(move |_: ()| {
+        // The open thread is still empty: that's the new chat
+        if busy.get_untracked() || conversation.with_untracked(|c| c.messages.is_empty()) {
+            return;
+        }
+        start_thread();
+    });
+    let on_rename = Callback::new(move |(id, title): (String, String)| {
+        set_threads.update(|threads| {
+            if let Some(thread) = threads.iter_mut().find(|thread| thread.id == id) {
+                thread.title = title;
             }
-        },
-    );
+        });
+    });
+    let on_delete = Callback::new(move |id: String| {
+        let deleting_current = current.get_untracked() == id;
+        if deleting_current && busy.get_untracked() {
+            return;
+        }
+        set_threads.update(|threads| threads.retain(|thread| thread.id != id));
+        if deleting_current {
+            let next = threads.with_untracked(|threads| threads.first().cloned());
+            match next {
+                Some(thread) => open(thread),
+                None => start_thread(),
+            }
+        }
+    });
+    let on_import = Callback::new(move |id: String| {
+        spawn_local(async move {
+            if busy.get_untracked() {
+                return;
+            }
+            if let Some(thread) = sidebar::fetch_thread(&id).await {
+                set_threads.update(|threads| threads.insert(0, thread.clone()));
+                open(thread);
+            }
+        });
+    });
+
+    let client: SharedSender = Default::default();
+
+    // Questions typed while offline, sent in order once we're back
+    let outbox: Rc<RefCell<VecDeque<String>>> = Default::default();
+
+    // Set when the chat unmounts, so the connection loop stops
+    let closed = Rc::new(Cell::new(false));
+
+    spawn_local(run_connection(
+        client.clone(),
+        outbox.clone(),
+        closed.clone(),
+        chat,
+        set_status,
+    ));
+
+    let cleanup_client = client.clone();
+    on_cleanup(move || {
+        closed.set(true);
+        let sender = cleanup_client.borrow_mut().take();
+        if let Some(mut sender) = sender {
+            spawn_local(async move {
+                let _ = sender.close().await;
+            });
+        }
+    });
 
     let send = create_action(move |new_message: &String| {
         let user_message = Message {
             text: new_message.clone(),
             user: true,
             sender_name: "You".to_string(),
-            timestamp: "now".to_string(),
+            timestamp: now_timestamp(),
         };
         set_conversation.update(move |c| {
             c.messages.push(user_message);
         });
 
-        let client2 = client.clone();
-        let msg = new_message.to_string();
+        let msg = ClientMessage::Query {
+            text: new_message.to_string(),
+            options: query_options(),
+        }
+        .to_json();
+        let delivery = deliver(client.clone(), outbox.clone(), status, msg, set_conversation);
         async move {
-            client2
-                .borrow_mut()
-                .as_mut()
-                .unwrap()
-                .send(Txt(msg.to_string()))
-                .await
-                .map_err(|_| ServerFnError::ServerError("WebSocket issue".to_string()))
+            delivery.await;
+            Ok(())
         }
     });
 
     create_effect(move |_| {
         if send.input().get().is_some() {
-            let model_message = Message {
-                text: String::new(),
-                user: false,
-                sender_name: "Assistant".to_string(),
-                timestamp: "now".to_string(),
-            };
-
             set_conversation.update(move |c| {
-                c.messages.push(model_message);
+                c.messages.push(answer_placeholder());
             });
         }
     });
 
+    // Stop the answer in progress. One still in the outbox never reached
+    // the server, so it is simply dropped.
+    let stop_client = client.clone();
+    let stop_outbox = outbox.clone();
+    let on_stop = move |_| {
+        let unsent = !stop_outbox.borrow().is_empty();
+        stop_outbox.borrow_mut().clear();
+        if unsent || status.get_untracked() != ConnectionStatus::Connected {
+            chat.finish_answer(STOPPED_TEXT);
+            return;
+        }
+
+        // The server answers with `cancelled`
+        let client = stop_client.clone();
+        spawn_local(async move {
+            let cancel = ClientMessage::Cancel.to_json();
+            if send_query(&client, status, cancel).await {
+                return;
+            }
+            chat.finish_answer(STOPPED_TEXT);
+        });
+    };
+
+    // Ask the last question again (with the current settings), replacing
+    // its answer
+    let on_regenerate = Callback::new(move |index: usize| {
+        if busy.get_untracked() {
+            return;
+        }
+        let question = conversation.with_untracked(|c| {
+            let question = c.messages.get(index.checked_sub(1)?)?;
+            question.user.then(|| question.text.clone())
+        });
+        let Some(question) = question else {
+            return;
+        };
+
+        batch(|| {
+            set_activity.update(|log| log.retain(|&i, _| i < index));
+            set_sources.update(|log| log.retain(|&i, _| i < index));
+            set_meta.update(|log| log.retain(|&i, _| i < index));
+            set_conversation.update(|c| {
+                c.messages.truncate(index);
+                c.messages.push(answer_placeholder());
+            });
+        });
+        let msg = ClientMessage::Query {
+            text: question,
+            options: query_options(),
+        }
+        .to_json();
+        spawn_local(deliver(client.clone(), outbox.clone(), status, msg, set_conversation));
+    });
+
+    // Edit a question: the chat up to it continues in a new thread with
+    // the edited question, and the original thread stays as it was
+    let on_edit = Callback::new(move |(index, text): (usize, String)| {
+        if busy.get_untracked() {
+            return;
+        }
+        let id = current.get_untracked();
+        let fork = threads.with_untracked(|threads| {
+            threads.iter().find(|thread| thread.id == id).map(|thread| thread.fork(index))
+        });
+        if let Some(fork) = fork {
+            set_threads.update(|threads| threads.insert(0, fork.clone()));
+            open(fork);
+            send.dispatch(text);
+        }
+    });
+
     view! {
-        <div class="h-screen w-full flex flex-col overflow-hidden">
-            <div class="flex-1 overflow-hidden">
-                <ChatArea conversation/>
+        <div class="h-screen w-full flex overflow-hidden">
+            <Sidebar threads current remote busy on_select on_new on_rename on_delete on_import/>
+            <div class="flex-1 min-w-0 flex flex-col overflow-hidden">
+                <ConnectionIndicator status/>
+                <SettingsDrawer settings set_settings/>
+                <div class="flex-1 overflow-hidden">
+                    <ChatArea conversation activity sources meta busy on_regenerate on_edit/>
+                </div>
+                <Show when=move || busy.get()>
+                    <div class="flex justify-center py-2 flex-shrink-0">
+                        <button
+                            class="px-4 py-1 rounded-full border border-gray-400 text-sm text-gray-500 hover:border-orange-500 hover:text-orange-500"
+                            on:click=on_stop.clone()
+                        >
+                            "⏹ Stop"
+                        </button>
+                    </div>
+                </Show>
+                <TypeArea send busy attachments set_attachments/>
             </div>
-            <TypeArea send/>
         </div>
     }
-}
\ No newline at end of file
+}
+
+// =============================================================================
+// CONNECTION
+// =============================================================================
+/// Where the research server's WebSocket lives.
+///
+/// A `RESEARCH_AGENT_WS_URL` set at build time wins, for a UI hosted apart
+/// from the API (`RESEARCH_AGENT_WS_URL=wss://api.example.com/ws trunk build`).
+/// Otherwise it is `/ws` on the page's own origin, over `wss` when the page
+/// came over HTTPS. `trunk serve` proxies `/ws` to the backend (trunk.toml).
+fn ws_endpoint() -> Option<String> {
+    if let Some(url) = option_env!("RESEARCH_AGENT_WS_URL") {
+        return Some(url.to_string());
+    }
+
+    let location = web_sys::window()?.location();
+    let scheme = match location.protocol().ok()?.as_str() {
+        "https:" => "wss",
+        _ => "ws",
+    };
+    Some(format!("{}://{}/ws", scheme, location.host().ok()?))
+}
+
+/// The `token` of the page URL (e.g. /chat?token=...), which servers with
+/// API keys expect on every request.
+fn page_token() -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+    search
+        .trim_start_matches('?')
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+        .and_then(|token| js_sys::decode_uri_component(token).ok())
+        .map(String::from)
+}
+
+/// `value` percent-encoded for use in a query string.
+fn encode_param(value: &str) -> String {
+    String::from(js_sys::encode_uri_component(value))
+}
+
+/// URL of an HTTP endpoint of the research server, e.g. `/api/favicon/docs.rs`.
+///
+/// The server is wherever the WebSocket is: `wss://host/ws` becomes
+/// `https://host` + `path`, with the page's token attached. `path` may
+/// carry its own query string (`/api/history?limit=50`).
+pub fn api_url(path: &str) -> String {
+    let endpoint = ws_endpoint().unwrap_or_default();
+    let endpoint = endpoint.strip_suffix("/ws").unwrap_or(&endpoint);
+    let base = if let Some(host) = endpoint.strip_prefix("wss://") {
+        format!("https://{}", host)
+    } else if let Some(host) = endpoint.strip_prefix("ws://") {
+        format!("http://{}", host)
+    } else {
+        endpoint.to_string()
+    };
+    match page_token() {
+        Some(token) => {
+            let separator = if path.contains('?') { '&' } else { '?' };
+            format!("{}{}{}token={}", base, path, separator, encode_param(&token))
+        }
+        None => format!("{}{}", base, path),
+    }
+}
+
+/// The server-side session of this tab's chat, once it has connected.
+fn chat_session() -> Option<String> {
+    session_storage()?.get_item(SESSION_KEY).ok().flatten()
+}
+
+/// `path` with this tab's chat session attached, for requests that belong
+/// to the chat (attachments).
+pub fn with_chat_session(path: &str) -> String {
+    match chat_session() {
+        Some(session) => format!("{}?session={}", path, encode_param(&session)),
+        None => path.to_string(),
+    }
+}
+
+/// The endpoint plus the page's API token and the session to resume.
+///
+/// Built again for every attempt, so a reconnect picks up right after the
+/// last event we saw.
+fn ws_url(endpoint: &str) -> String {
+    let mut params = Vec::new();
+
+    // Servers with API keys expect one in the handshake
+    if let Some(token) = page_token() {
+        params.push(format!("token={}", encode_param(&token)));
+    }
+
+    // Resume this tab's session: the server replays what we missed
+    let saved = session_storage().and_then(|s| {
+        let session = s.get_item(SESSION_KEY).ok().flatten()?;
+        let last_seq = s.get_item(LAST_SEQ_KEY).ok().flatten().unwrap_or_default();
+        Some((session, last_seq.parse::<u64>().unwrap_or(0)))
+    });
+    if let Some((session, last_seq)) = saved {
+        params.push(format!("session={}&last_seq={}", encode_param(&session), last_seq));
+    }
+
+    if params.is_empty() {
+        endpoint.to_string()
+    } else {
+        format!("{}?{}", endpoint, params.join("&"))
+    }
+}
+
+/// Wait before reconnect attempt `attempt` (from 1): 1s, 2s, 4s, ... 30s.
+fn reconnect_delay_ms(attempt: u32) -> u32 {
+    let doublings = attempt.saturating_sub(1).min(5);
+    RECONNECT_BASE_MS.saturating_mul(1 << doublings).min(RECONNECT_MAX_MS)
+}
+
+/// Keep the chat connected until it unmounts.
+///
+/// Connects, handles events until the socket drops (network, server
+/// restart, `ws_max_lifetime_secs`), then waits with backoff and
+/// reconnects, resuming the session so nothing is lost in between.
+async fn run_connection(
+    client: SharedSender,
+    outbox: Rc<RefCell<VecDeque<String>>>,
+    closed: Rc<Cell<bool>>,
+    chat: ChatState,
+    set_status: WriteSignal<ConnectionStatus>,
+) {
+    let Some(endpoint) = ws_endpoint() else {
+        set_status.set(ConnectionStatus::Closed);
+        return;
+    };
+
+    let mut attempt = 0;
+    while !closed.get() {
+        if let Ok(connection) = WebSocket::open(&ws_url(&endpoint)) {
+            let (sender, mut recv) = connection.split();
+            *client.borrow_mut() = Some(sender);
+
+            while let Some(Ok(message)) = recv.next().await {
+                let WsMessage::Text(text) = message else {
+                    continue;
+                };
+
+                // The server greets every connection with its session:
+                // from here on we're online
+                if handle_message(text, &client, chat).await && !closed.get() {
+                    attempt = 0;
+                    set_status.set(ConnectionStatus::Connected);
+                    flush_outbox(&client, &outbox).await;
+                }
+            }
+            client.borrow_mut().take();
+        }
+
+        if closed.get() {
+            break;
+        }
+        attempt += 1;
+        let delay = reconnect_delay_ms(attempt);
+        set_status.set(ConnectionStatus::Reconnecting {
+            attempt,
+            retry_in_secs: delay.div_ceil(1000),
+        });
+        TimeoutFuture::new(delay).await;
+    }
+}
+
+/// Send a text frame on the current connection.
+///
+/// The sender is taken out of the cell while the frame goes out, so no
+/// `RefCell` borrow is held across the `await`. Returns `None` when there
+/// is no sender (offline, or another send in progress), otherwise whether
+/// the frame went out.
+async fn send_frame(client: &SharedSender, text: String) -> Option<bool> {
+    let taken = client.borrow_mut().take();
+    let mut sender = taken?;
+    let sent = sender.send(WsMessage::Text(text)).await.is_ok();
+
+    // Put it back unless the connection was replaced meanwhile
+    if sent {
+        client.borrow_mut().get_or_insert(sender);
+    }
+    Some(sent)
+}
+
+/// Send a query if we're online. An ack may be going out at this very
+/// moment, so a busy sender is retried for a little while.
+async fn send_query(client: &SharedSender, status: ReadSignal<ConnectionStatus>, msg: String) -> bool {
+    for _ in 0..50 {
+        if status.get_untracked() != ConnectionStatus::Connected {
+            return false;
+        }
+        if let Some(sent) = send_frame(client, msg.clone()).await {
+            return sent;
+        }
+        TimeoutFuture::new(20).await;
+    }
+    false
+}
+
+/// Send a query, or keep it in the outbox until we're back online.
+async fn deliver(
+    client: SharedSender,
+    outbox: Rc<RefCell<VecDeque<String>>>,
+    status: ReadSignal<ConnectionStatus>,
+    msg: String,
+    set_conversation: WriteSignal<Conversation>,
+) {
+    if send_query(&client, status, msg.clone()).await {
+        return;
+    }
+
+    // Offline, or the socket dropped while sending: the connection
+    // loop sends it as soon as it has reconnected
+    outbox.borrow_mut().push_back(msg);
+    set_conversation.update(|c| {
+        if let Some(last_msg) = c.messages.last_mut() {
+            if !last_msg.user && last_msg.text.is_empty() {
+                last_msg.text = OFFLINE_PREFIX.to_string();
+            }
+        }
+    });
+}
+
+/// Send the questions typed while offline, oldest first.
+async fn flush_outbox(client: &SharedSender, outbox: &Rc<RefCell<VecDeque<String>>>) {
+    loop {
+        let next = outbox.borrow_mut().pop_front();
+        let Some(msg) = next else {
+            break;
+        };
+        if send_frame(client, msg.clone()).await != Some(true) {
+            outbox.borrow_mut().push_front(msg);
+            break;
+        }
+    }
+}
+
+/// Apply one server message to the conversation. Returns true for the
+/// `session` greeting that starts every connection.
+async fn handle_message(msg: String, client: &SharedSender, chat: ChatState) -> bool {
+    let set_conversation = chat.set_conversation;
+
+    // The server sends JSON events; fall back to raw text
+    // so an older backend still shows something.
+    let envelope = serde_json::from_str::<Envelope>(&msg);
+
+    // Remember how far we got, and let the server
+    // drop what we've seen from its buffer
+    if let Ok(Envelope { seq: Some(seq), .. }) = &envelope {
+        if let Some(storage) = session_storage() {
+            let _ = storage.set_item(LAST_SEQ_KEY, &seq.to_string());
+        }
+        // Acks are cumulative: if a query is being sent
+        // right now, the next event's ack covers this one
+        let _ = send_frame(client, ClientMessage::Ack { seq: *seq }.to_json()).await;
+    }
+    let event = envelope.map(|envelope| envelope.event);
+
+    if let Ok(ServerEvent::Session { session_id, resumed }) = &event {
+        if let Some(storage) = session_storage() {
+            let _ = storage.set_item(SESSION_KEY, session_id);
+            if !resumed {
+                // A fresh session starts counting from zero
+                let _ = storage.remove_item(LAST_SEQ_KEY);
+            }
+        }
+        return true;
+    }
+
+    // Queue positions are a placeholder, replaced by
+    // the next update or by the answer itself
+    if let Ok(ServerEvent::Queued { position }) = event {
+        set_conversation.update(move |c| {
+            if let Some(last_msg) = c.messages.last_mut() {
+                if !last_msg.user {
+                    last_msg.text = format!("{} (position {})", QUEUED_PREFIX, position);
+                }
+            }
+        });
+        return false;
+    }
+
+    // Progress of the answer in the making, shown above it
+    if let Ok(ServerEvent::Activity { step }) = event {
+        if let Some(index) = chat.answering() {
+            chat.set_activity.update(move |log| {
+                log.entry(index).or_default().steps.push(step);
+            });
+        }
+        return false;
+    }
+
+    // The pages the answer is based on, shown as cards under it
+    if let Ok(ServerEvent::Sources { sources }) = event {
+        if let Some(index) = chat.answering() {
+            chat.set_sources.update(move |log| {
+                log.insert(index, sources);
+            });
+        }
+        return false;
+    }
+
+    // How the answer was made, shown when hovering over it
+    if let Ok(ServerEvent::Answer { meta: Some(meta), .. }) = &event {
+        if let Some(index) = chat.answering() {
+            let meta = meta.clone();
+            chat.set_meta.update(move |log| {
+                log.insert(index, meta);
+            });
+        }
+    }
+
+    // Answers the server keeps in its history aren't listed there again
+    if let Ok(ServerEvent::Answer { run_id: Some(run_id), .. }) = &event {
+        let (id, run_id) = (chat.current.get_untracked(), run_id.clone());
+        chat.set_threads.update(move |threads| {
+            if let Some(thread) = threads.iter_mut().find(|thread| thread.id == id) {
+                thread.run_ids.push(run_id);
+            }
+        });
+    }
+
+    // The answer (or its error) is in: collapse the activity panel
+    let finished = matches!(
+        event,
+        Ok(ServerEvent::Answer { .. } | ServerEvent::Error { .. } | ServerEvent::Cancelled)
+    );
+    if let Some(index) = chat.answering().filter(|_| finished) {
+        chat.set_activity.update(move |log| {
+            if let Some(activity) = log.get_mut(&index) {
+                activity.done = true;
+            }
+        });
+    }
+
+    let text_to_add = match event {
+        Ok(ServerEvent::Answer { text, .. }) => text,
+        Ok(ServerEvent::Error { message, hint, .. }) => match hint {
+            Some(hint) => format!("⚠️ {}\n\n💡 {}", message, hint),
+            None => format!("⚠️ {}", message),
+        },
+        Ok(ServerEvent::Cancelled) => STOPPED_TEXT.to_string(),
+        Ok(ServerEvent::ShuttingDown { message, .. }) => {
+            format!("\n\n⏳ {}\n\n", message)
+        }
+        Ok(
+            ServerEvent::Queued { .. }
+            | ServerEvent::Session { .. }
+            | ServerEvent::Activity { .. }
+            | ServerEvent::Sources { .. },
+        ) => return false,
+        Err(_) => msg,
+    };
+
+    set_conversation.update(move |c| {
+        if let Some(last_msg) = c.messages.last_mut() {
+            if !last_msg.user {
+                if last_msg.text.starts_with(QUEUED_PREFIX) || last_msg.text.starts_with(OFFLINE_PREFIX) {
+                    last_msg.text.clear();
+                }
+                last_msg.text.push_str(&text_to_add);
+                // Dated when the answer came in, not when it was asked for
+                last_msg.timestamp = now_timestamp();
+            }
+        }
+    });
+    false
+}
@@ -0,0 +1,120 @@
+// Dimensionless Developments Rust Ai
+// # Assets Module
+// Serves the web UI from files embedded in the binary.
+// Only compiled with `--features embed-frontend`.
+//
+// `trunk build --release` writes the frontend to `dist/`; with this feature
+// the build copies it into the executable, so the server works from any
+// working directory and ships as a single file. Run trunk *before*
+// `cargo build`, since the files are read at compile time; a build without
+// `dist/` still compiles, and its server says how to fix it.
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use rust_embed::RustEmbed;
+
+/// The trunk build output, compressed inside the binary.
+///
+/// # Rust Concept: Derive Macros that Read Files
+///
+/// `#[derive(RustEmbed)]` runs at compile time: it walks `dist/` and
+/// generates a `get()` function returning each file's bytes, its SHA-256
+/// hash and its MIME type. Nothing is read from disk at runtime.
+/// `allow_missing` embeds nothing (rather than failing) when `dist/`
+/// doesn't exist yet.
+#[derive(RustEmbed)]
+#[folder = "dist/"]
+#[allow_missing = true]
+struct Frontend;
+
+/// Page served for client-side routes like `/chat`.
+const INDEX: &str = "index.html";
+
+/// Serve an embedded file, with ETag revalidation.
+///
+/// Response compression (gzip/brotli) is done by the `CompressionLayer`
+/// on the router, which also covers the on-disk `static_dir`.
+pub async fn serve_embedded(uri: Uri, headers: HeaderMap) -> Response {
+    let path = uri.path().trim_start_matches('/');
+    let path = if path.is_empty() || path.ends_with('/') {
+        format!("{}{}", path, INDEX)
+    } else {
+        path.to_string()
+    };
+
+    // Unknown paths without an extension are routes of the single-page app
+    let (path, file) = match Frontend::get(&path) {
+        Some(file) => (path, file),
+        None if !path.rsplit('/').next().unwrap_or_default().contains('.') => {
+            match Frontend::get(INDEX) {
+                Some(file) => (INDEX.to_string(), file),
+                None => return missing_frontend(),
+            }
+        }
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let etag = etag(&file.metadata.sha256_hash());
+    let cache_control = if path.ends_with(".html") {
+        // Always revalidate the page so a new build's assets get picked up
+        "no-cache"
+    } else {
+        "public, max-age=3600"
+    };
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == etag));
+
+    let mut response = if not_modified {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        let mut response = Response::new(Body::from(file.data.into_owned()));
+        if let Ok(mime) = HeaderValue::from_str(file.metadata.mimetype()) {
+            response.headers_mut().insert(header::CONTENT_TYPE, mime);
+        }
+        response
+    };
+
+    let response_headers = response.headers_mut();
+    if let Ok(etag) = HeaderValue::from_str(&etag) {
+        response_headers.insert(header::ETAG, etag);
+    }
+    response_headers.insert(header::CACHE_CONTROL, HeaderValue::from_static(cache_control));
+    response
+}
+
+/// Strong ETag from a file's SHA-256 hash.
+fn etag(hash: &[u8; 32]) -> String {
+    let hex: String = hash.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("\"{}\"", hex)
+}
+
+/// The binary was built before `trunk build`, so `dist/` was missing or empty.
+fn missing_frontend() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        "The web UI was not embedded: run `trunk build --release` before \
+         `cargo build --features embed-frontend`, or set static_dir",
+    )
+        .into_response()
+}
+
+// =============================================================================
+// UNIT TESTS
+// =============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_etag_is_quoted_hex() {
+        let tag = etag(&[0xab; 32]);
+        assert_eq!(tag.len(), 66);
+        assert!(tag.starts_with("\"abab") && tag.ends_with("ab\""));
+    }
+}
@@ -0,0 +1,439 @@
+// Dimensionless Developments Rust Ai
+// # Auth Module
+// API key authentication and per-key limits for the web server.
+//
+// Keys are defined in the config file:
+//
+// ```toml
+// [[api_keys]]
+// name = "ci"
+// key_env = "CI_RESEARCH_KEY"     # or `key = "..."` inline
+// scopes = ["search"]             # "search" (quick search), "research", "history"
+// daily_quota = 200
+// requests_per_minute = 10
+// ```
+//
+// Clients send the key as `Authorization: Bearer <key>` (or `X-API-Key`).
+// Browsers can't set headers on a WebSocket handshake, so `/ws` also
+// accepts it as a `?token=<key>` query parameter.
+//
+// With no keys configured the server stays open, as before.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use axum::extract::FromRequestParts;
+use axum::http::{header, request::Parts};
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::error::AgentError;
+use crate::monitoring;
+use crate::server::AppState;
+
+/// Window for `requests_per_minute`.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+// =============================================================================
+// SCOPES AND KEY CONFIG
+// =============================================================================
+/// What a key is allowed to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Quick searches only - no LLM time
+    Search,
+
+    /// Full research runs (implies `Search`), and reading the key's own
+    /// runs in the history
+    Research,
+
+    /// Deleting the key's own runs from the history (not implied by
+    /// `Research`, since it destroys records)
+    History,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Search => "search",
+            Scope::Research => "research",
+            Scope::History => "history",
+        }
+    }
+}
+
+/// One `[[api_keys]]` entry of the config file.
+#[derive(Clone, Deserialize)]
+pub struct ApiKeyConfig {
+    /// Name used in logs, metrics and errors (never the key itself)
+    pub name: String,
+
+    /// The key, inline
+    pub key: Option<String>,
+
+    /// Environment variable holding the key, to keep it out of the file
+    pub key_env: Option<String>,
+
+    pub scopes: Vec<Scope>,
+
+    /// Requests allowed per UTC day
+    pub daily_quota: Option<u32>,
+
+    /// Requests allowed per rolling minute
+    pub requests_per_minute: Option<u32>,
+}
+
+/// # Rust Concept: Manual Trait Implementations
+///
+/// `#[derive(Debug)]` would print the key, and `Config` gets logged at
+/// debug level. Writing `Debug` by hand lets us redact it.
+impl fmt::Debug for ApiKeyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiKeyConfig")
+            .field("name", &self.name)
+            .field("key", &self.key.as_ref().map(|_| "<redacted>"))
+            .field("key_env", &self.key_env)
+            .field("scopes", &self.scopes)
+            .field("daily_quota", &self.daily_quota)
+            .field("requests_per_minute", &self.requests_per_minute)
+            .finish()
+    }
+}
+
+// =============================================================================
+// PRINCIPAL
+// =============================================================================
+/// The caller of a request, as identified by its API key.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    /// Key name, or `anonymous` when auth is disabled
+    pub name: String,
+    scopes: Vec<Scope>,
+}
+
+impl Principal {
+    fn anonymous() -> Self {
+        Self {
+            name: "anonymous".to_string(),
+            scopes: vec![Scope::Search, Scope::Research, Scope::History],
+        }
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+            || (scope == Scope::Search && self.scopes.contains(&Scope::Research))
+    }
+
+    /// `Forbidden` unless the key has `scope`. Unlike
+    /// `Authenticator::authorize`, this doesn't count against the quota.
+    pub fn require(&self, scope: Scope) -> Result<(), AgentError> {
+        if self.has_scope(scope) {
+            return Ok(());
+        }
+        Err(AgentError::Forbidden {
+            key: self.name.clone(),
+            scope: scope.as_str(),
+        })
+    }
+}
+
+/// # Rust Concept: Custom Extractors
+///
+/// Implementing `FromRequestParts` lets handlers take a `Principal`
+/// argument like any other extractor. If the key is missing or wrong the
+/// handler never runs: axum turns the `AgentError` into a 401 response.
+#[axum::async_trait]
+impl FromRequestParts<Arc<AppState>> for Principal {
+    type Rejection = AgentError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        state.auth.authenticate(token_from_parts(parts).as_deref())
+    }
+}
+
+/// Find the API key in the `Authorization` / `X-API-Key` headers or the
+/// `token` query parameter.
+fn token_from_parts(parts: &Parts) -> Option<String> {
+    let headers = &parts.headers;
+
+    if let Some(value) = headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()) {
+        if let Some(token) = value.strip_prefix("Bearer ") {
+            return Some(token.trim().to_string());
+        }
+    }
+
+    if let Some(value) = headers.get("x-api-key").and_then(|v| v.to_str().ok()) {
+        return Some(value.trim().to_string());
+    }
+
+    parts.uri.query().and_then(|query| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.strip_prefix("token="))
+            .find_map(|value| urlencoding::decode(value).ok().map(|v| v.into_owned()))
+    })
+}
+
+// =============================================================================
+// AUTHENTICATOR
+// =============================================================================
+/// A configured key with its secret resolved.
+struct KeyEntry {
+    name: String,
+    secret: String,
+    scopes: Vec<Scope>,
+    daily_quota: Option<u32>,
+    requests_per_minute: Option<u32>,
+}
+
+/// Requests made with one key.
+#[derive(Default)]
+struct Usage {
+    /// UTC day (days since the epoch) that `today` counts
+    day: u64,
+    today: u32,
+
+    /// Request times within the last `RATE_WINDOW`
+    recent: VecDeque<Instant>,
+}
+
+/// Checks API keys and enforces scopes, quotas and rate limits.
+pub struct Authenticator {
+    keys: Vec<KeyEntry>,
+    usage: Mutex<HashMap<String, Usage>>,
+}
+
+impl Authenticator {
+    /// Build the authenticator from `config.api_keys`, reading `key_env`
+    /// variables now so a missing secret fails at startup, not per request.
+    pub fn from_config(config: &Config) -> Result<Self, AgentError> {
+        let mut keys = Vec::new();
+
+        for key in &config.api_keys {
+            let secret = match (&key.key, &key.key_env) {
+                (Some(secret), None) => secret.clone(),
+                (None, Some(var)) => std::env::var(var).map_err(|_| {
+                    AgentError::InvalidConfig(format!(
+                        "API key '{}' reads its key from ${}, which is not set",
+                        key.name, var
+                    ))
+                })?,
+                _ => {
+                    return Err(AgentError::InvalidConfig(format!(
+                        "API key '{}' needs exactly one of `key` or `key_env`",
+                        key.name
+                    )))
+                }
+            };
+
+            keys.push(KeyEntry {
+                name: key.name.clone(),
+                secret,
+                scopes: key.scopes.clone(),
+                daily_quota: key.daily_quota,
+                requests_per_minute: key.requests_per_minute,
+            });
+        }
+
+        Ok(Self {
+            keys,
+            usage: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Is authentication required at all?
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    /// Identify the caller from the key they sent.
+    pub fn authenticate(&self, token: Option<&str>) -> Result<Principal, AgentError> {
+        if !self.is_enabled() {
+            return Ok(Principal::anonymous());
+        }
+
+        let token = token.ok_or(AgentError::Unauthorized)?;
+        self.keys
+            .iter()
+            .find(|key| constant_time_eq(key.secret.as_bytes(), token.as_bytes()))
+            .map(|key| Principal {
+                name: key.name.clone(),
+                scopes: key.scopes.clone(),
+            })
+            .ok_or(AgentError::Unauthorized)
+    }
+
+    /// Check that `principal` may use `scope` and count the request
+    /// against its quota and rate limit.
+    pub fn authorize(&self, principal: &Principal, scope: Scope) -> Result<(), AgentError> {
+        if !principal.has_scope(scope) {
+            return Err(AgentError::Forbidden {
+                key: principal.name.clone(),
+                scope: scope.as_str(),
+            });
+        }
+
+        let Some(key) = self.keys.iter().find(|k| k.name == principal.name) else {
+            // Anonymous access (auth disabled) is unlimited
+            return Ok(());
+        };
+
+        let mut usage = self.usage.lock().unwrap_or_else(|p| p.into_inner());
+        let usage = usage.entry(key.name.clone()).or_default();
+        usage.check(key, Instant::now(), utc_day())
+    }
+}
+
+impl Usage {
+    /// Count one request, or refuse it if a limit is reached.
+    fn check(&mut self, key: &KeyEntry, now: Instant, day: u64) -> Result<(), AgentError> {
+        if self.day != day {
+            self.day = day;
+            self.today = 0;
+        }
+
+        while self.recent.front().is_some_and(|t| now.duration_since(*t) >= RATE_WINDOW) {
+            self.recent.pop_front();
+        }
+
+        if let Some(limit) = key.daily_quota {
+            if self.today >= limit {
+                monitoring::record_rate_limit("api_key");
+                return Err(AgentError::QuotaExceeded {
+                    key: key.name.clone(),
+                    limit,
+                    period: "day",
+                });
+            }
+        }
+
+        if let Some(limit) = key.requests_per_minute {
+            if self.recent.len() >= limit as usize {
+                monitoring::record_rate_limit("api_key");
+                return Err(AgentError::QuotaExceeded {
+                    key: key.name.clone(),
+                    limit,
+                    period: "minute",
+                });
+            }
+        }
+
+        self.today += 1;
+        self.recent.push_back(now);
+        Ok(())
+    }
+}
+
+/// Days since the Unix epoch, in UTC.
+fn utc_day() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / SECS_PER_DAY)
+        .unwrap_or_default()
+}
+
+/// Compare two byte strings without returning early, so the time taken
+/// doesn't reveal how much of a guessed key was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// =============================================================================
+// UNIT TESTS
+// =============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn authenticator() -> Authenticator {
+        let mut config = Config::default();
+        config.api_keys = vec![ApiKeyConfig {
+            name: "ci".to_string(),
+            key: Some("secret-ci".to_string()),
+            key_env: None,
+            scopes: vec![Scope::Search],
+            daily_quota: Some(3),
+            requests_per_minute: Some(2),
+        }];
+        Authenticator::from_config(&config).unwrap()
+    }
+
+    #[test]
+    fn test_auth_disabled_without_keys() {
+        let auth = Authenticator::from_config(&Config::default()).unwrap();
+        let principal = auth.authenticate(None).unwrap();
+        assert!(principal.has_scope(Scope::Research));
+        assert!(auth.authorize(&principal, Scope::Research).is_ok());
+    }
+
+    #[test]
+    fn test_authenticate_and_scopes() {
+        let auth = authenticator();
+        assert_eq!(auth.authenticate(None).err().unwrap().code(), "unauthorized");
+        assert_eq!(auth.authenticate(Some("wrong")).err().unwrap().code(), "unauthorized");
+
+        let principal = auth.authenticate(Some("secret-ci")).unwrap();
+        assert_eq!(principal.name, "ci");
+        assert!(auth.authorize(&principal, Scope::Search).is_ok());
+        assert_eq!(
+            auth.authorize(&principal, Scope::Research).err().unwrap().code(),
+            "forbidden"
+        );
+    }
+
+    #[test]
+    fn test_history_scope_is_not_implied() {
+        let principal = Principal {
+            name: "reader".to_string(),
+            scopes: vec![Scope::Research],
+        };
+        assert!(principal.require(Scope::Research).is_ok());
+        assert_eq!(principal.require(Scope::History).err().unwrap().code(), "forbidden");
+    }
+
+    #[test]
+    fn test_rate_limit_and_daily_quota() {
+        let auth = authenticator();
+        let key = &auth.keys[0];
+        let mut usage = Usage::default();
+        let start = Instant::now();
+
+        assert!(usage.check(key, start, 1).is_ok());
+        assert!(usage.check(key, start, 1).is_ok());
+        // Third request within the minute hits requests_per_minute = 2
+        assert!(usage.check(key, start, 1).is_err());
+
+        // A minute later the window has moved on
+        let later = start + RATE_WINDOW;
+        assert!(usage.check(key, later, 1).is_ok());
+        // ...but daily_quota = 3 is used up
+        assert!(usage.check(key, later + RATE_WINDOW, 1).is_err());
+
+        // The next day starts fresh
+        assert!(usage.check(key, later + RATE_WINDOW, 2).is_ok());
+    }
+
+    #[test]
+    fn test_api_key_debug_is_redacted() {
+        let auth_config = ApiKeyConfig {
+            name: "ci".to_string(),
+            key: Some("super-secret".to_string()),
+            key_env: None,
+            scopes: vec![Scope::Research],
+            daily_quota: None,
+            requests_per_minute: None,
+        };
+        assert!(!format!("{:?}", auth_config).contains("super-secret"));
+    }
+}
@@ -6,9 +6,37 @@
 // - The Default trait for sensible defaults
 // - Error handling with Result types
 // - String ownership vs borrowing
+//
+// Configuration is layered. Each layer overrides the one before it:
+//
+//   defaults < config file(s) < selected profile < environment < CLI flags
+//
+// Config files are TOML and are looked up in two places:
+// - `$XDG_CONFIG_HOME/ai-research-agent/config.toml` (per-user)
+// - `./research-agent.toml` (per-project, wins over the per-user file)
+//
+// State kept between runs (unfinished jobs, the research history database)
+// lives in `$XDG_DATA_HOME/ai-research-agent/` unless `data_dir` says otherwise.
 
 use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::auth::ApiKeyConfig;
+use crate::error::AgentError;
+use crate::protocol::{QueryOptions, MAX_RESULTS_LIMIT};
+
+/// File name of the project-local config file (looked up in the working directory).
+pub const PROJECT_CONFIG_FILE: &str = "research-agent.toml";
+
+/// Directory name used under `$XDG_CONFIG_HOME` for the per-user config file
+/// (and under `$XDG_DATA_HOME` for the data directory).
+pub const APP_CONFIG_DIR: &str = "ai-research-agent";
 
 // =============================================================================
 // CONFIGURATION STRUCT
@@ -39,8 +67,292 @@ pub struct Config {
     /// Maximum number of search results to analyze
     pub max_search_results: usize,
 
-    /// Log level for the application
+    /// Log level for the application. Accepts full `EnvFilter` directives,
+    /// e.g. `info,ai_research_agent::tools=debug`
     pub log_level: String,
+
+    /// Output format for console logs (text or JSON)
+    pub log_format: LogFormat,
+
+    /// Directory for daily-rotated JSON log files (disabled when `None`)
+    pub log_dir: Option<PathBuf>,
+
+    /// OTLP collector endpoint for trace export, e.g. `http://localhost:4317`
+    /// (requires the `otel` feature; disabled when `None`)
+    pub otlp_endpoint: Option<String>,
+
+    /// Maximum time a single research run may take, in seconds
+    pub research_timeout_secs: u64,
+
+    /// How long the server waits for running jobs on shutdown, in seconds
+    pub shutdown_grace_secs: u64,
+
+    /// Research runs the server sends to the LLM at the same time
+    pub max_concurrent_jobs: usize,
+
+    /// Research runs allowed to wait for a slot before new ones are refused
+    pub max_queued_jobs: usize,
+
+    /// How long a chat session survives without a connection, in seconds
+    pub session_ttl_secs: u64,
+
+    /// Seconds between WebSocket pings
+    pub ws_ping_interval_secs: u64,
+
+    /// Close a WebSocket that sent nothing (not even a pong) for this long, in seconds
+    pub ws_idle_timeout_secs: u64,
+
+    /// Close every WebSocket after this long, in seconds (0 = never);
+    /// clients reconnect and resume their session
+    pub ws_max_lifetime_secs: u64,
+
+    /// Open WebSockets allowed per client IP address
+    pub ws_max_connections_per_ip: usize,
+
+    /// Largest WebSocket message (e.g. a query) the server accepts, in bytes
+    pub ws_max_message_bytes: usize,
+
+    /// Directory for state kept between runs (unfinished jobs, history)
+    pub data_dir: PathBuf,
+
+    /// Record every run in the history database (`<data_dir>/history.db`)
+    pub save_history: bool,
+
+    /// API keys accepted by the web server (open access when empty)
+    pub api_keys: Vec<ApiKeyConfig>,
+
+    /// Address the web server listens on (`0.0.0.0` = all interfaces)
+    pub bind_address: String,
+
+    /// Origins allowed to call the server from a browser (`*` = any origin,
+    /// empty = same origin only)
+    pub cors_origins: Vec<String>,
+
+    /// Directory the web UI is served from
+    pub static_dir: PathBuf,
+
+    /// PEM certificate chain for HTTPS (requires the `tls` feature)
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key for HTTPS
+    pub tls_key: Option<PathBuf>,
+
+    /// Name of the profile that was applied, if any
+    pub profile: Option<String>,
+
+    /// Where each value above came from (used by `config show --resolved`)
+    pub sources: ConfigSources,
+}
+
+// =============================================================================
+// LOG FORMAT
+// =============================================================================
+/// How log lines are written to the console.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines (the default)
+    #[default]
+    Text,
+
+    /// One JSON object per line, for log shippers
+    Json,
+}
+
+/// # Rust Concept: FromStr
+///
+/// Implementing FromStr lets us call `"json".parse::<LogFormat>()`, and
+/// clap uses it automatically to parse the `--log-format` flag.
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("unknown log format '{}' (expected text or json)", other)),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogFormat::Text => write!(f, "text"),
+            LogFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+// =============================================================================
+// VALUE PROVENANCE
+// =============================================================================
+/// Where a single configuration value came from.
+///
+/// # Rust Concept: Enums with Data
+///
+/// Unlike C enums, Rust enum variants can carry data. `File` holds the
+/// path it was read from, `Env` the variable name, and so on. This lets
+/// us describe *exactly* where a value came from in one type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueSource {
+    /// Built-in default
+    Default,
+
+    /// Top-level setting in a config file
+    File(PathBuf),
+
+    /// Setting inside a `[profiles.<name>]` table of a config file
+    Profile { name: String, path: PathBuf },
+
+    /// Environment variable (or `.env` file)
+    Env(&'static str),
+
+    /// Command-line flag
+    Cli(&'static str),
+}
+
+impl fmt::Display for ValueSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueSource::Default => write!(f, "default"),
+            ValueSource::File(path) => write!(f, "file {}", path.display()),
+            ValueSource::Profile { name, path } => {
+                write!(f, "profile '{}' in {}", name, path.display())
+            }
+            ValueSource::Env(var) => write!(f, "env {}", var),
+            ValueSource::Cli(flag) => write!(f, "flag {}", flag),
+        }
+    }
+}
+
+/// Records the source of every configuration key.
+///
+/// Keys that were never overridden are reported as `ValueSource::Default`.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSources(BTreeMap<&'static str, ValueSource>);
+
+impl ConfigSources {
+    /// Remember that `key` was last set by `source`.
+    pub fn set(&mut self, key: &'static str, source: ValueSource) {
+        self.0.insert(key, source);
+    }
+
+    /// Look up where `key` came from.
+    pub fn get(&self, key: &str) -> ValueSource {
+        self.0.get(key).cloned().unwrap_or(ValueSource::Default)
+    }
+}
+
+// =============================================================================
+// CONFIG FILE FORMAT
+// =============================================================================
+/// Settings that may appear at the top level of a config file or inside a
+/// profile. Every field is optional - only the keys present override anything.
+///
+/// # Example
+/// ```toml
+/// profile = "fast"            # profile used when --profile is not given
+/// ollama_host = "http://localhost:11434"
+///
+/// [profiles.fast]
+/// model = "llama3.2:1b"
+/// max_search_results = 3
+///
+/// [profiles.deep]
+/// model = "llama3.1:70b"
+/// max_search_results = 10
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FileSettings {
+    pub model: Option<String>,
+    pub ollama_host: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_search_results: Option<usize>,
+    pub log_level: Option<String>,
+    pub log_format: Option<LogFormat>,
+    pub log_dir: Option<PathBuf>,
+    pub otlp_endpoint: Option<String>,
+    pub research_timeout_secs: Option<u64>,
+    pub shutdown_grace_secs: Option<u64>,
+    pub max_concurrent_jobs: Option<usize>,
+    pub max_queued_jobs: Option<usize>,
+    pub session_ttl_secs: Option<u64>,
+    pub ws_ping_interval_secs: Option<u64>,
+    pub ws_idle_timeout_secs: Option<u64>,
+    pub ws_max_lifetime_secs: Option<u64>,
+    pub ws_max_connections_per_ip: Option<usize>,
+    pub ws_max_message_bytes: Option<usize>,
+    pub data_dir: Option<PathBuf>,
+    pub save_history: Option<bool>,
+    pub api_keys: Option<Vec<ApiKeyConfig>>,
+    pub bind_address: Option<String>,
+    pub cors_origins: Option<Vec<String>>,
+    pub static_dir: Option<PathBuf>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+}
+
+/// A parsed config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ConfigFile {
+    /// Default profile to apply when none is selected explicitly
+    pub profile: Option<String>,
+
+    /// Top-level settings shared by every profile
+    #[serde(flatten)]
+    pub settings: FileSettings,
+
+    /// Named profiles, e.g. `[profiles.fast]`
+    #[serde(default)]
+    pub profiles: HashMap<String, FileSettings>,
+}
+
+impl ConfigFile {
+    /// Parse a config file from TOML text.
+    pub fn parse(text: &str) -> Result<Self> {
+        toml::from_str(text).context("Config file is not valid TOML")
+    }
+
+    /// Read and parse the config file at `path`.
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+}
+
+/// Return the config files that exist, lowest precedence first.
+///
+/// `RESEARCH_AGENT_CONFIG` points at an explicit file and replaces the
+/// project-local lookup (the per-user file still applies underneath it).
+pub fn config_file_paths() -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+
+    let xdg_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    if let Some(dir) = xdg_dir {
+        candidates.push(dir.join(APP_CONFIG_DIR).join("config.toml"));
+    }
+
+    match env::var_os("RESEARCH_AGENT_CONFIG") {
+        Some(path) => candidates.push(PathBuf::from(path)),
+        None => candidates.push(PathBuf::from(PROJECT_CONFIG_FILE)),
+    }
+
+    candidates.into_iter().filter(|p| p.is_file()).collect()
+}
+
+/// Default data directory: `$XDG_DATA_HOME/ai-research-agent`, falling back
+/// to `~/.local/share/ai-research-agent`, or `./.ai-research-agent` when
+/// there is no home directory (e.g. in a bare container).
+pub fn default_data_dir() -> PathBuf {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .map(|dir| dir.join(APP_CONFIG_DIR))
+        .unwrap_or_else(|| PathBuf::from(format!(".{}", APP_CONFIG_DIR)))
 }
 
 // =============================================================================
@@ -70,6 +382,55 @@ impl Default for Config {
 
             // Info level logging by default
             log_level: "info".to_string(),
+
+            // Plain text on the console, no log files
+            log_format: LogFormat::Text,
+            log_dir: None,
+
+            // Trace export is opt-in
+            otlp_endpoint: None,
+
+            // Five minutes is plenty for a few search + synthesis rounds
+            research_timeout_secs: 300,
+
+            // Long enough for most runs to finish, short enough for deploys
+            shutdown_grace_secs: 30,
+
+            // One Ollama instance on one GPU answers one prompt at a time
+            max_concurrent_jobs: 1,
+            max_queued_jobs: 32,
+
+            // Enough to ride out a dropped connection or a page reload
+            session_ttl_secs: 300,
+
+            // Three missed pings and the connection is considered dead;
+            // long-lived sockets are recycled every few hours
+            ws_ping_interval_secs: 30,
+            ws_idle_timeout_secs: 90,
+            ws_max_lifetime_secs: 4 * 60 * 60,
+            ws_max_connections_per_ip: 16,
+
+            // A query is a sentence or two, not a document
+            ws_max_message_bytes: 16 * 1024,
+
+            data_dir: default_data_dir(),
+            save_history: true,
+
+            // No keys: the server is open, as it always was
+            api_keys: Vec::new(),
+
+            // Listen everywhere, allow any origin, serve the Trunk build
+            // output, plain HTTP - the behaviour before these were settings
+            bind_address: "0.0.0.0".to_string(),
+            cors_origins: vec!["*".to_string()],
+            static_dir: PathBuf::from("dist"),
+            tls_cert: None,
+            tls_key: None,
+
+            // No profile unless one is selected
+            profile: None,
+
+            sources: ConfigSources::default(),
         }
     }
 }
@@ -78,7 +439,7 @@ impl Default for Config {
 // CONFIGURATION LOADING
 // =============================================================================
 impl Config {
-    /// Load configuration from environment variables.
+    /// Load the layered configuration: defaults < files < profile < env.
     ///
     /// # Rust Concept: Result Type
     ///
@@ -95,12 +456,16 @@ impl Config {
     /// If the Result is Ok, it unwraps the value.
     /// If the Result is Err, it returns early from the function with that error.
     ///
+    /// `profile` comes from `--profile`; when it is `None` we fall back to
+    /// `RESEARCH_AGENT_PROFILE`, then to the `profile` key of the config files.
+    /// CLI flags are applied afterwards by the caller (see `main.rs`).
+    ///
     /// # Example
     /// ```
-    /// let config = Config::from_env()?;
+    /// let config = Config::load(Some("fast"))?;
     /// println!("Using model: {}", config.model);
     /// ```
-    pub fn from_env() -> Result<Self> {
+    pub fn load(profile: Option<&str>) -> Result<Self> {
         // Load .env file if it exists (silently ignore if not found)
         // This is useful for local development
         let _ = dotenvy::dotenv();
@@ -108,8 +473,177 @@ impl Config {
         // Start with default values
         let mut config = Config::default();
 
-        // Override with environment variables if set
-        //
+        // Read every config file that exists, lowest precedence first
+        let mut files = Vec::new();
+        for path in config_file_paths() {
+            let file = ConfigFile::read(&path)?;
+            files.push((path, file));
+        }
+
+        // Top-level file settings
+        for (path, file) in &files {
+            config.apply_settings(&file.settings, &|| ValueSource::File(path.clone()));
+        }
+
+        // Profile: --profile > RESEARCH_AGENT_PROFILE > `profile` key in a file
+        let selected = profile
+            .map(str::to_string)
+            .or_else(|| env::var("RESEARCH_AGENT_PROFILE").ok())
+            .or_else(|| files.iter().rev().find_map(|(_, f)| f.profile.clone()));
+
+        if let Some(name) = selected {
+            config.apply_profile(&name, &files)?;
+        }
+
+        config.apply_env()?;
+
+        Ok(config)
+    }
+
+    /// Apply the profile called `name` from every file that defines it.
+    ///
+    /// It's an error to ask for a profile that no file defines - a typo in
+    /// `--profile` should not silently fall back to the defaults.
+    pub fn apply_profile(&mut self, name: &str, files: &[(PathBuf, ConfigFile)]) -> Result<()> {
+        let mut found = false;
+
+        for (path, file) in files {
+            if let Some(settings) = file.profiles.get(name) {
+                found = true;
+                self.apply_settings(settings, &|| ValueSource::Profile {
+                    name: name.to_string(),
+                    path: path.clone(),
+                });
+            }
+        }
+
+        if !found {
+            anyhow::bail!(
+                "Unknown profile '{}': no config file defines [profiles.{}]",
+                name,
+                name
+            );
+        }
+
+        self.profile = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Override fields with the values present in `settings`.
+    ///
+    /// # Rust Concept: Closures as Parameters
+    ///
+    /// `source` is a closure that builds the ValueSource on demand, so we
+    /// only clone the path for keys that are actually set.
+    fn apply_settings(&mut self, settings: &FileSettings, source: &dyn Fn() -> ValueSource) {
+        if let Some(val) = &settings.model {
+            self.model = val.clone();
+            self.sources.set("model", source());
+        }
+        if let Some(val) = &settings.ollama_host {
+            self.ollama_host = val.clone();
+            self.sources.set("ollama_host", source());
+        }
+        if let Some(val) = settings.temperature {
+            self.temperature = val;
+            self.sources.set("temperature", source());
+        }
+        if let Some(val) = settings.max_search_results {
+            self.max_search_results = val;
+            self.sources.set("max_search_results", source());
+        }
+        if let Some(val) = &settings.log_level {
+            self.log_level = val.clone();
+            self.sources.set("log_level", source());
+        }
+        if let Some(val) = settings.log_format {
+            self.log_format = val;
+            self.sources.set("log_format", source());
+        }
+        if let Some(val) = &settings.log_dir {
+            self.log_dir = Some(val.clone());
+            self.sources.set("log_dir", source());
+        }
+        if let Some(val) = &settings.otlp_endpoint {
+            self.otlp_endpoint = Some(val.clone());
+            self.sources.set("otlp_endpoint", source());
+        }
+        if let Some(val) = settings.research_timeout_secs {
+            self.research_timeout_secs = val;
+            self.sources.set("research_timeout_secs", source());
+        }
+        if let Some(val) = settings.shutdown_grace_secs {
+            self.shutdown_grace_secs = val;
+            self.sources.set("shutdown_grace_secs", source());
+        }
+        if let Some(val) = settings.max_concurrent_jobs {
+            self.max_concurrent_jobs = val;
+            self.sources.set("max_concurrent_jobs", source());
+        }
+        if let Some(val) = settings.max_queued_jobs {
+            self.max_queued_jobs = val;
+            self.sources.set("max_queued_jobs", source());
+        }
+        if let Some(val) = settings.session_ttl_secs {
+            self.session_ttl_secs = val;
+            self.sources.set("session_ttl_secs", source());
+        }
+        if let Some(val) = settings.ws_ping_interval_secs {
+            self.ws_ping_interval_secs = val;
+            self.sources.set("ws_ping_interval_secs", source());
+        }
+        if let Some(val) = settings.ws_idle_timeout_secs {
+            self.ws_idle_timeout_secs = val;
+            self.sources.set("ws_idle_timeout_secs", source());
+        }
+        if let Some(val) = settings.ws_max_lifetime_secs {
+            self.ws_max_lifetime_secs = val;
+            self.sources.set("ws_max_lifetime_secs", source());
+        }
+        if let Some(val) = settings.ws_max_connections_per_ip {
+            self.ws_max_connections_per_ip = val;
+            self.sources.set("ws_max_connections_per_ip", source());
+        }
+        if let Some(val) = settings.ws_max_message_bytes {
+            self.ws_max_message_bytes = val;
+            self.sources.set("ws_max_message_bytes", source());
+        }
+        if let Some(val) = &settings.data_dir {
+            self.data_dir = val.clone();
+            self.sources.set("data_dir", source());
+        }
+        if let Some(val) = settings.save_history {
+            self.save_history = val;
+            self.sources.set("save_history", source());
+        }
+        if let Some(val) = &settings.api_keys {
+            self.api_keys = val.clone();
+            self.sources.set("api_keys", source());
+        }
+        if let Some(val) = &settings.bind_address {
+            self.bind_address = val.clone();
+            self.sources.set("bind_address", source());
+        }
+        if let Some(val) = &settings.cors_origins {
+            self.cors_origins = val.clone();
+            self.sources.set("cors_origins", source());
+        }
+        if let Some(val) = &settings.static_dir {
+            self.static_dir = val.clone();
+            self.sources.set("static_dir", source());
+        }
+        if let Some(val) = &settings.tls_cert {
+            self.tls_cert = Some(val.clone());
+            self.sources.set("tls_cert", source());
+        }
+        if let Some(val) = &settings.tls_key {
+            self.tls_key = Some(val.clone());
+            self.sources.set("tls_key", source());
+        }
+    }
+
+    /// Override fields with environment variables if set.
+    fn apply_env(&mut self) -> Result<()> {
         // # Rust Concept: if let
         // `if let` is a concise way to handle a single pattern match.
         // It's equivalent to:
@@ -118,59 +652,368 @@ impl Config {
         //       Err(_) => { /* do nothing */ }
         //   }
         if let Ok(val) = env::var("OLLAMA_MODEL") {
-            config.model = val;
+            self.model = val;
+            self.sources.set("model", ValueSource::Env("OLLAMA_MODEL"));
         }
 
         if let Ok(val) = env::var("OLLAMA_API_BASE_URL") {
-            config.ollama_host = val;
+            self.ollama_host = val;
+            self.sources.set("ollama_host", ValueSource::Env("OLLAMA_API_BASE_URL"));
         }
 
         // Parse temperature from string to f32
         // .context() adds helpful error messages when things fail
         if let Ok(val) = env::var("TEMPERATURE") {
-            config.temperature = val
+            self.temperature = val
                 .parse()
                 .context("TEMPERATURE must be a valid floating-point number (e.g., 0.7)")?;
+            self.sources.set("temperature", ValueSource::Env("TEMPERATURE"));
         }
 
         if let Ok(val) = env::var("MAX_SEARCH_RESULTS") {
-            config.max_search_results = val
+            self.max_search_results = val
                 .parse()
                 .context("MAX_SEARCH_RESULTS must be a valid positive integer")?;
+            self.sources.set("max_search_results", ValueSource::Env("MAX_SEARCH_RESULTS"));
         }
 
         if let Ok(val) = env::var("RUST_LOG") {
-            config.log_level = val;
+            self.log_level = val;
+            self.sources.set("log_level", ValueSource::Env("RUST_LOG"));
         }
 
-        Ok(config)
+        if let Ok(val) = env::var("LOG_FORMAT") {
+            self.log_format = val.parse().map_err(anyhow::Error::msg)?;
+            self.sources.set("log_format", ValueSource::Env("LOG_FORMAT"));
+        }
+
+        if let Ok(val) = env::var("LOG_DIR") {
+            self.log_dir = Some(PathBuf::from(val));
+            self.sources.set("log_dir", ValueSource::Env("LOG_DIR"));
+        }
+
+        // Standard OpenTelemetry variable, so existing collector setups just work
+        if let Ok(val) = env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            self.otlp_endpoint = Some(val);
+            self.sources.set("otlp_endpoint", ValueSource::Env("OTEL_EXPORTER_OTLP_ENDPOINT"));
+        }
+
+        if let Ok(val) = env::var("RESEARCH_TIMEOUT_SECS") {
+            self.research_timeout_secs = val
+                .parse()
+                .context("RESEARCH_TIMEOUT_SECS must be a whole number of seconds")?;
+            self.sources.set("research_timeout_secs", ValueSource::Env("RESEARCH_TIMEOUT_SECS"));
+        }
+
+        if let Ok(val) = env::var("SHUTDOWN_GRACE_SECS") {
+            self.shutdown_grace_secs = val
+                .parse()
+                .context("SHUTDOWN_GRACE_SECS must be a whole number of seconds")?;
+            self.sources.set("shutdown_grace_secs", ValueSource::Env("SHUTDOWN_GRACE_SECS"));
+        }
+
+        if let Ok(val) = env::var("MAX_CONCURRENT_JOBS") {
+            self.max_concurrent_jobs = val
+                .parse()
+                .context("MAX_CONCURRENT_JOBS must be a valid positive integer")?;
+            self.sources.set("max_concurrent_jobs", ValueSource::Env("MAX_CONCURRENT_JOBS"));
+        }
+
+        if let Ok(val) = env::var("MAX_QUEUED_JOBS") {
+            self.max_queued_jobs = val
+                .parse()
+                .context("MAX_QUEUED_JOBS must be a valid non-negative integer")?;
+            self.sources.set("max_queued_jobs", ValueSource::Env("MAX_QUEUED_JOBS"));
+        }
+
+        if let Ok(val) = env::var("SESSION_TTL_SECS") {
+            self.session_ttl_secs = val
+                .parse()
+                .context("SESSION_TTL_SECS must be a whole number of seconds")?;
+            self.sources.set("session_ttl_secs", ValueSource::Env("SESSION_TTL_SECS"));
+        }
+
+        if let Ok(val) = env::var("WS_PING_INTERVAL_SECS") {
+            self.ws_ping_interval_secs = val
+                .parse()
+                .context("WS_PING_INTERVAL_SECS must be a whole number of seconds")?;
+            self.sources.set("ws_ping_interval_secs", ValueSource::Env("WS_PING_INTERVAL_SECS"));
+        }
+
+        if let Ok(val) = env::var("WS_IDLE_TIMEOUT_SECS") {
+            self.ws_idle_timeout_secs = val
+                .parse()
+                .context("WS_IDLE_TIMEOUT_SECS must be a whole number of seconds")?;
+            self.sources.set("ws_idle_timeout_secs", ValueSource::Env("WS_IDLE_TIMEOUT_SECS"));
+        }
+
+        if let Ok(val) = env::var("WS_MAX_LIFETIME_SECS") {
+            self.ws_max_lifetime_secs = val
+                .parse()
+                .context("WS_MAX_LIFETIME_SECS must be a whole number of seconds")?;
+            self.sources.set("ws_max_lifetime_secs", ValueSource::Env("WS_MAX_LIFETIME_SECS"));
+        }
+
+        if let Ok(val) = env::var("WS_MAX_CONNECTIONS_PER_IP") {
+            self.ws_max_connections_per_ip = val
+                .parse()
+                .context("WS_MAX_CONNECTIONS_PER_IP must be a valid positive integer")?;
+            self.sources.set(
+                "ws_max_connections_per_ip",
+                ValueSource::Env("WS_MAX_CONNECTIONS_PER_IP"),
+            );
+        }
+
+        if let Ok(val) = env::var("WS_MAX_MESSAGE_BYTES") {
+            self.ws_max_message_bytes = val
+                .parse()
+                .context("WS_MAX_MESSAGE_BYTES must be a number of bytes")?;
+            self.sources.set("ws_max_message_bytes", ValueSource::Env("WS_MAX_MESSAGE_BYTES"));
+        }
+
+        if let Ok(val) = env::var("RESEARCH_AGENT_DATA_DIR") {
+            self.data_dir = PathBuf::from(val);
+            self.sources.set("data_dir", ValueSource::Env("RESEARCH_AGENT_DATA_DIR"));
+        }
+
+        if let Ok(val) = env::var("SAVE_HISTORY") {
+            self.save_history = val
+                .parse()
+                .context("SAVE_HISTORY must be true or false")?;
+            self.sources.set("save_history", ValueSource::Env("SAVE_HISTORY"));
+        }
+
+        if let Ok(val) = env::var("BIND_ADDRESS") {
+            self.bind_address = val;
+            self.sources.set("bind_address", ValueSource::Env("BIND_ADDRESS"));
+        }
+
+        // Comma-separated, e.g. `https://research.example.com,http://localhost:3000`
+        if let Ok(val) = env::var("CORS_ORIGINS") {
+            self.cors_origins = val
+                .split(',')
+                .map(str::trim)
+                .filter(|origin| !origin.is_empty())
+                .map(str::to_string)
+                .collect();
+            self.sources.set("cors_origins", ValueSource::Env("CORS_ORIGINS"));
+        }
+
+        if let Ok(val) = env::var("STATIC_DIR") {
+            self.static_dir = PathBuf::from(val);
+            self.sources.set("static_dir", ValueSource::Env("STATIC_DIR"));
+        }
+
+        if let Ok(val) = env::var("TLS_CERT") {
+            self.tls_cert = Some(PathBuf::from(val));
+            self.sources.set("tls_cert", ValueSource::Env("TLS_CERT"));
+        }
+
+        if let Ok(val) = env::var("TLS_KEY") {
+            self.tls_key = Some(PathBuf::from(val));
+            self.sources.set("tls_key", ValueSource::Env("TLS_KEY"));
+        }
+
+        Ok(())
+    }
+
+    /// List every key with its current value, for `config show`.
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("model", self.model.clone()),
+            ("ollama_host", self.ollama_host.clone()),
+            ("temperature", self.temperature.to_string()),
+            ("max_search_results", self.max_search_results.to_string()),
+            ("log_level", self.log_level.clone()),
+            ("log_format", self.log_format.to_string()),
+            (
+                "log_dir",
+                self.log_dir.as_ref().map(|d| d.display().to_string()).unwrap_or_default(),
+            ),
+            ("otlp_endpoint", self.otlp_endpoint.clone().unwrap_or_default()),
+            ("research_timeout_secs", self.research_timeout_secs.to_string()),
+            ("shutdown_grace_secs", self.shutdown_grace_secs.to_string()),
+            ("max_concurrent_jobs", self.max_concurrent_jobs.to_string()),
+            ("max_queued_jobs", self.max_queued_jobs.to_string()),
+            ("session_ttl_secs", self.session_ttl_secs.to_string()),
+            ("ws_ping_interval_secs", self.ws_ping_interval_secs.to_string()),
+            ("ws_idle_timeout_secs", self.ws_idle_timeout_secs.to_string()),
+            ("ws_max_lifetime_secs", self.ws_max_lifetime_secs.to_string()),
+            ("ws_max_connections_per_ip", self.ws_max_connections_per_ip.to_string()),
+            ("ws_max_message_bytes", self.ws_max_message_bytes.to_string()),
+            ("data_dir", self.data_dir.display().to_string()),
+            ("save_history", self.save_history.to_string()),
+            (
+                "api_keys",
+                // Names only - never print the keys themselves
+                self.api_keys.iter().map(|k| k.name.as_str()).collect::<Vec<_>>().join(", "),
+            ),
+            ("bind_address", self.bind_address.clone()),
+            ("cors_origins", self.cors_origins.join(", ")),
+            ("static_dir", self.static_dir.display().to_string()),
+            (
+                "tls_cert",
+                self.tls_cert.as_ref().map(|p| p.display().to_string()).unwrap_or_default(),
+            ),
+            (
+                "tls_key",
+                self.tls_key.as_ref().map(|p| p.display().to_string()).unwrap_or_default(),
+            ),
+        ]
     }
 
     /// Validate the configuration.
     ///
     /// This ensures all values are within acceptable ranges before the agent starts.
     /// It's better to fail fast with a clear error than to fail later with a confusing one!
-    pub fn validate(&self) -> Result<()> {
+    pub fn validate(&self) -> Result<(), AgentError> {
         // Temperature must be between 0 and 2 (OpenAI/Ollama range)
         if !(0.0..=2.0).contains(&self.temperature) {
-            anyhow::bail!(
+            return Err(AgentError::InvalidConfig(format!(
                 "Temperature must be between 0.0 and 2.0, got: {}",
                 self.temperature
-            );
+            )));
         }
 
         // Must have at least 1 search result
         if self.max_search_results == 0 {
-            anyhow::bail!("MAX_SEARCH_RESULTS must be at least 1");
+            return Err(AgentError::InvalidConfig(
+                "MAX_SEARCH_RESULTS must be at least 1".to_string(),
+            ));
         }
 
         // Model name can't be empty
         if self.model.is_empty() {
-            anyhow::bail!("OLLAMA_MODEL cannot be empty");
+            return Err(AgentError::InvalidConfig(
+                "OLLAMA_MODEL cannot be empty".to_string(),
+            ));
+        }
+
+        // With no slots nothing would ever run
+        if self.max_concurrent_jobs == 0 {
+            return Err(AgentError::InvalidConfig(
+                "MAX_CONCURRENT_JOBS must be at least 1".to_string(),
+            ));
+        }
+
+        // Key names show up in logs and errors, so they must tell keys apart
+        for (i, key) in self.api_keys.iter().enumerate() {
+            if key.name.is_empty() || key.scopes.is_empty() {
+                return Err(AgentError::InvalidConfig(
+                    "every [[api_keys]] entry needs a name and at least one scope".to_string(),
+                ));
+            }
+            if self.api_keys[..i].iter().any(|other| other.name == key.name) {
+                return Err(AgentError::InvalidConfig(format!(
+                    "API key name '{}' is used more than once",
+                    key.name
+                )));
+            }
+        }
+
+        if self.bind_address.parse::<IpAddr>().is_err() {
+            return Err(AgentError::InvalidConfig(format!(
+                "BIND_ADDRESS must be an IP address like 127.0.0.1 or ::, got: {}",
+                self.bind_address
+            )));
+        }
+
+        // Browsers send `Origin: scheme://host[:port]`, nothing else matches
+        for origin in &self.cors_origins {
+            let valid = origin == "*"
+                || ((origin.starts_with("http://") || origin.starts_with("https://"))
+                    && !origin.ends_with('/')
+                    && !origin.contains(char::is_whitespace));
+            if !valid {
+                return Err(AgentError::InvalidConfig(format!(
+                    "CORS origin '{}' must be `*` or like https://example.com (no trailing slash)",
+                    origin
+                )));
+            }
+        }
+
+        // HTTPS needs both halves
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            return Err(AgentError::InvalidConfig(
+                "TLS_CERT and TLS_KEY must be set together".to_string(),
+            ));
+        }
+
+        // A zero timeout would fail every run immediately
+        if self.research_timeout_secs == 0 {
+            return Err(AgentError::InvalidConfig(
+                "RESEARCH_TIMEOUT_SECS must be at least 1".to_string(),
+            ));
+        }
+
+        // Healthy clients answer every ping, so the idle timeout must leave
+        // room for at least one of them
+        if self.ws_ping_interval_secs == 0
+            || self.ws_idle_timeout_secs <= self.ws_ping_interval_secs
+        {
+            return Err(AgentError::InvalidConfig(format!(
+                "WS_IDLE_TIMEOUT_SECS ({}) must be longer than WS_PING_INTERVAL_SECS ({}), \
+                 which must be at least 1",
+                self.ws_idle_timeout_secs, self.ws_ping_interval_secs
+            )));
+        }
+
+        if self.ws_max_connections_per_ip == 0 {
+            return Err(AgentError::InvalidConfig(
+                "WS_MAX_CONNECTIONS_PER_IP must be at least 1".to_string(),
+            ));
+        }
+
+        // Room for a query plus the JSON around it
+        if self.ws_max_message_bytes < 256 {
+            return Err(AgentError::InvalidConfig(
+                "WS_MAX_MESSAGE_BYTES must be at least 256".to_string(),
+            ));
         }
 
         Ok(())
     }
+
+    /// This configuration with a query's own settings applied on top.
+    ///
+    /// Options come from clients, so out-of-range values are the client's
+    /// mistake (`InvalidOptions`), not the server's. Whether a requested
+    /// model is installed is checked by the caller, which can ask Ollama.
+    pub fn with_query_options(&self, options: &QueryOptions) -> Result<Config, AgentError> {
+        let mut config = self.clone();
+
+        if let Some(model) = &options.model {
+            let model = model.trim();
+            if model.is_empty() || model.len() > 200 {
+                return Err(AgentError::InvalidOptions(
+                    "model must be a model name".to_string(),
+                ));
+            }
+            config.model = model.to_string();
+        }
+
+        if let Some(max_results) = options.max_results {
+            if !(1..=MAX_RESULTS_LIMIT).contains(&max_results) {
+                return Err(AgentError::InvalidOptions(format!(
+                    "max_results must be between 1 and {}, got: {}",
+                    MAX_RESULTS_LIMIT, max_results
+                )));
+            }
+            config.max_search_results = max_results;
+        }
+
+        if let Some(temperature) = options.temperature {
+            if !(0.0..=2.0).contains(&temperature) {
+                return Err(AgentError::InvalidOptions(format!(
+                    "temperature must be between 0.0 and 2.0, got: {}",
+                    temperature
+                )));
+            }
+            config.temperature = temperature;
+        }
+
+        Ok(config)
+    }
 }
 
 // =============================================================================
@@ -195,6 +1038,14 @@ mod tests {
         assert_eq!(config.ollama_host, "http://localhost:11434");
         assert!((config.temperature - 0.7).abs() < f32::EPSILON);
         assert_eq!(config.max_search_results, 5);
+        assert_eq!(config.log_format, LogFormat::Text);
+    }
+
+    #[test]
+    fn test_log_format_parsing() {
+        assert_eq!("json".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert_eq!("TEXT".parse::<LogFormat>(), Ok(LogFormat::Text));
+        assert!("xml".parse::<LogFormat>().is_err());
     }
 
     #[test]
@@ -216,4 +1067,164 @@ mod tests {
         config.max_search_results = 0; // Invalid: must be at least 1
         assert!(config.validate().is_err());
     }
-}
\ No newline at end of file
+
+    const SAMPLE_FILE: &str = r#"
+profile = "fast"
+ollama_host = "http://gpu-box:11434"
+
+[profiles.fast]
+model = "llama3.2:1b"
+max_search_results = 3
+
+[profiles.deep]
+model = "llama3.1:70b"
+max_search_results = 10
+"#;
+
+    #[test]
+    fn test_config_file_parsing() {
+        let file = ConfigFile::parse(SAMPLE_FILE).unwrap();
+
+        assert_eq!(file.profile.as_deref(), Some("fast"));
+        assert_eq!(file.settings.ollama_host.as_deref(), Some("http://gpu-box:11434"));
+        assert_eq!(file.profiles.len(), 2);
+        assert_eq!(file.profiles["deep"].max_search_results, Some(10));
+    }
+
+    #[test]
+    fn test_profile_overrides_file_settings() {
+        let path = PathBuf::from("research-agent.toml");
+        let files = vec![(path.clone(), ConfigFile::parse(SAMPLE_FILE).unwrap())];
+
+        let mut config = Config::default();
+        config.apply_settings(&files[0].1.settings, &|| ValueSource::File(path.clone()));
+        config.apply_profile("deep", &files).unwrap();
+
+        assert_eq!(config.model, "llama3.1:70b");
+        assert_eq!(config.max_search_results, 10);
+        assert_eq!(config.ollama_host, "http://gpu-box:11434");
+        assert_eq!(config.profile.as_deref(), Some("deep"));
+        assert_eq!(config.sources.get("ollama_host"), ValueSource::File(path.clone()));
+        assert_eq!(
+            config.sources.get("model"),
+            ValueSource::Profile { name: "deep".to_string(), path }
+        );
+        assert_eq!(config.sources.get("temperature"), ValueSource::Default);
+    }
+
+    #[test]
+    fn test_api_keys_parsing_and_validation() {
+        let file = ConfigFile::parse(
+            r#"
+[[api_keys]]
+name = "ci"
+key = "secret"
+scopes = ["search"]
+daily_quota = 100
+
+[[api_keys]]
+name = "ci"
+key_env = "OTHER_KEY"
+scopes = ["research"]
+"#,
+        )
+        .unwrap();
+
+        let mut config = Config::default();
+        config.apply_settings(&file.settings, &|| ValueSource::Default);
+        assert_eq!(config.api_keys.len(), 2);
+        assert_eq!(config.api_keys[0].daily_quota, Some(100));
+
+        // Duplicate names are rejected
+        assert!(config.validate().is_err());
+        config.api_keys[1].name = "admin".to_string();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_server_settings_validation() {
+        let mut config = Config::default();
+        config.bind_address = "localhost".to_string();
+        assert!(config.validate().is_err());
+
+        config.bind_address = "::1".to_string();
+        config.cors_origins = vec!["https://research.example.com/".to_string()];
+        assert!(config.validate().is_err());
+
+        config.cors_origins = vec!["https://research.example.com".to_string()];
+        config.tls_cert = Some(PathBuf::from("cert.pem"));
+        assert!(config.validate().is_err());
+
+        config.tls_key = Some(PathBuf::from("key.pem"));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_websocket_limits_validation() {
+        let mut config = Config::default();
+        config.ws_idle_timeout_secs = config.ws_ping_interval_secs;
+        assert!(config.validate().is_err());
+
+        config.ws_idle_timeout_secs = 60;
+        config.ws_ping_interval_secs = 0;
+        assert!(config.validate().is_err());
+
+        config.ws_ping_interval_secs = 20;
+        config.ws_max_connections_per_ip = 0;
+        assert!(config.validate().is_err());
+
+        config.ws_max_connections_per_ip = 4;
+        config.ws_max_message_bytes = 100;
+        assert!(config.validate().is_err());
+
+        config.ws_max_message_bytes = 4096;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_unknown_profile_is_an_error() {
+        let files = vec![(
+            PathBuf::from("research-agent.toml"),
+            ConfigFile::parse(SAMPLE_FILE).unwrap(),
+        )];
+
+        let mut config = Config::default();
+        assert!(config.apply_profile("turbo", &files).is_err());
+    }
+
+    #[test]
+    fn test_query_options_override_config() {
+        let config = Config::default();
+
+        let options = QueryOptions {
+            model: Some("qwen2.5".to_string()),
+            max_results: Some(10),
+            temperature: Some(0.2),
+            ..Default::default()
+        };
+        let applied = config.with_query_options(&options).unwrap();
+        assert_eq!(applied.model, "qwen2.5");
+        assert_eq!(applied.max_search_results, 10);
+        assert!((applied.temperature - 0.2).abs() < f32::EPSILON);
+
+        // Unset options keep the server's settings
+        let applied = config.with_query_options(&QueryOptions::default()).unwrap();
+        assert_eq!(applied.model, config.model);
+        assert_eq!(applied.max_search_results, config.max_search_results);
+
+        let too_many = QueryOptions {
+            max_results: Some(MAX_RESULTS_LIMIT + 1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.with_query_options(&too_many),
+            Err(AgentError::InvalidOptions(_))
+        ));
+
+        let too_hot = QueryOptions {
+            temperature: Some(3.0),
+            ..Default::default()
+        };
+        assert!(config.with_query_options(&too_hot).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+// Dimensionless Developments Rust Ai
+// # Connections Module
+// Caps how many WebSockets one IP address may hold open.
+//
+// Every open socket costs a task, buffers and a heartbeat. Without a cap a
+// single misbehaving client (or a script in a reconnect loop) can hold
+// thousands of them. The cap is per IP address as seen by the server, so
+// behind a reverse proxy raise `ws_max_connections_per_ip` accordingly.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::error::AgentError;
+
+/// Open WebSocket connections per client IP address.
+pub struct ConnectionLimiter {
+    open: Mutex<HashMap<IpAddr, usize>>,
+    max_per_ip: usize,
+}
+
+impl ConnectionLimiter {
+    pub fn new(max_per_ip: usize) -> Self {
+        Self {
+            open: Mutex::new(HashMap::new()),
+            max_per_ip,
+        }
+    }
+
+    /// Count a new connection from `ip`, or refuse it when `ip` is at its cap.
+    ///
+    /// The slot holds its own `Arc` to the limiter because it moves into
+    /// the socket's task, which outlives the upgrade request.
+    pub fn acquire(self: &Arc<Self>, ip: IpAddr) -> Result<ConnectionSlot, AgentError> {
+        let mut open = self.lock();
+        let count = open.entry(ip).or_default();
+        if *count >= self.max_per_ip {
+            return Err(AgentError::TooManyConnections {
+                ip: ip.to_string(),
+                limit: self.max_per_ip,
+            });
+        }
+
+        *count += 1;
+        Ok(ConnectionSlot {
+            limiter: self.clone(),
+            ip,
+        })
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let mut open = self.lock();
+        if let Some(count) = open.get_mut(&ip) {
+            *count -= 1;
+            if *count == 0 {
+                // Don't keep an entry for every address that ever connected
+                open.remove(&ip);
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<IpAddr, usize>> {
+        self.open.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// One counted connection; dropping it frees the slot.
+pub struct ConnectionSlot {
+    limiter: Arc<ConnectionLimiter>,
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.limiter.release(self.ip);
+    }
+}
+
+// =============================================================================
+// UNIT TESTS
+// =============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_is_per_ip() {
+        let limiter = Arc::new(ConnectionLimiter::new(2));
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let first = limiter.acquire(a).unwrap();
+        let _second = limiter.acquire(a).unwrap();
+        let err = limiter.acquire(a).err().unwrap();
+        assert_eq!(err.code(), "too_many_connections");
+
+        // Other addresses are unaffected
+        let _other = limiter.acquire(b).unwrap();
+
+        drop(first);
+        assert!(limiter.acquire(a).is_ok());
+    }
+
+    #[test]
+    fn test_closed_connections_are_forgotten() {
+        let limiter = Arc::new(ConnectionLimiter::new(1));
+        let ip: IpAddr = "::1".parse().unwrap();
+
+        drop(limiter.acquire(ip).unwrap());
+        assert!(limiter.lock().is_empty());
+    }
+}
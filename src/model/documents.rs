@@ -0,0 +1,459 @@
+// Dimensionless Developments Rust Ai
+// # Documents Module
+// Files attached in the chat - text, Markdown, HTML or PDF - turned into
+// plain text the agent can search next to the web.
+//
+// `POST /api/documents` extracts a file's text once, splits it into
+// passages and keeps it in memory under a random id. The chat sends the
+// ids of a conversation's attachments with every query, and the agent gets
+// a `search_documents` tool over them (see `tools::DocumentSearchTool`).
+// Documents are dropped a day after they were last used; the chat then
+// asks for the file again.
+//
+// A document belongs to the API key that uploaded it and, when the chat
+// names its session, to that session: other keys and other chats can't
+// have the agent search it, even with its id.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::protocol::{DocumentInfo, ATTACHMENT_URL_PREFIX, MAX_UPLOAD_BYTES};
+use crate::tools::{html_to_text, truncate_chars};
+
+/// Longest text kept per document; a book's worth is plenty to search.
+const MAX_TEXT_CHARS: usize = 1_000_000;
+
+/// Documents kept in memory; the least recently used are dropped beyond this.
+const MAX_DOCUMENTS: usize = 256;
+
+/// How long a document is kept after it was last used.
+const DOCUMENT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Passages are cut at paragraph breaks, at about this many characters.
+const PASSAGE_CHARS: usize = 1_200;
+
+/// Search terms shorter than this are ignored ("a", "of", "is").
+const MIN_TERM_CHARS: usize = 3;
+
+// =============================================================================
+// TEXT EXTRACTION
+// =============================================================================
+/// Why an uploaded file couldn't be attached.
+#[derive(Error, Debug)]
+pub enum DocumentError {
+    #[error("Unsupported file type: {0} (attach text, Markdown, HTML or PDF files)")]
+    Unsupported(String),
+
+    #[error("The file is larger than {} MB", MAX_UPLOAD_BYTES / 1024 / 1024)]
+    TooLarge,
+
+    #[error("No text could be found in {0}")]
+    Empty(String),
+
+    #[error("Could not read the PDF: {0}")]
+    Pdf(String),
+}
+
+/// The file formats we can read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentKind {
+    /// Plain text and Markdown, used as they are
+    Text,
+    Html,
+    Pdf,
+}
+
+impl DocumentKind {
+    /// The format of a file, from its extension or else its content type.
+    pub fn detect(name: &str, content_type: Option<&str>) -> Option<Self> {
+        let extension = name.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("txt" | "text" | "md" | "markdown") => return Some(DocumentKind::Text),
+            Some("html" | "htm") => return Some(DocumentKind::Html),
+            Some("pdf") => return Some(DocumentKind::Pdf),
+            _ => {}
+        }
+
+        let content_type = content_type?.split(';').next()?.trim().to_ascii_lowercase();
+        match content_type.as_str() {
+            "text/html" | "application/xhtml+xml" => Some(DocumentKind::Html),
+            "application/pdf" => Some(DocumentKind::Pdf),
+            "text/markdown" => Some(DocumentKind::Text),
+            other if other.starts_with("text/") => Some(DocumentKind::Text),
+            _ => None,
+        }
+    }
+}
+
+/// The readable text of a file of `kind`.
+///
+/// PDF parsing is CPU-bound; call this from `spawn_blocking` on a server.
+pub fn extract_text(kind: DocumentKind, bytes: &[u8]) -> Result<String, DocumentError> {
+    let text = match kind {
+        DocumentKind::Text => String::from_utf8_lossy(bytes).into_owned(),
+        DocumentKind::Html => html_to_text(&String::from_utf8_lossy(bytes)),
+        DocumentKind::Pdf => pdf_extract::extract_text_from_mem(bytes)
+            .map_err(|e| DocumentError::Pdf(e.to_string()))?,
+    };
+    Ok(truncate_chars(text.trim(), MAX_TEXT_CHARS))
+}
+
+// =============================================================================
+// DOCUMENTS
+// =============================================================================
+/// An attached file's text, split into passages for searching.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub id: String,
+
+    /// The file name, as uploaded
+    pub name: String,
+
+    passages: Vec<String>,
+    chars: usize,
+}
+
+/// A passage that matched a search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentHit {
+    /// e.g. `notes.pdf (part 3)`
+    pub title: String,
+
+    /// `attachment:<id>#<part>`, so citations of it have a stable source
+    pub url: String,
+
+    pub text: String,
+}
+
+impl Document {
+    pub fn new(name: &str, text: &str) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            passages: split_passages(text),
+            chars: text.chars().count(),
+        }
+    }
+
+    /// Read an uploaded file.
+    pub fn from_upload(
+        name: &str,
+        content_type: Option<&str>,
+        bytes: &[u8],
+    ) -> Result<Self, DocumentError> {
+        if bytes.len() > MAX_UPLOAD_BYTES {
+            return Err(DocumentError::TooLarge);
+        }
+        let kind = DocumentKind::detect(name, content_type)
+            .ok_or_else(|| DocumentError::Unsupported(name.to_string()))?;
+
+        let text = extract_text(kind, bytes)?;
+        if text.is_empty() {
+            return Err(DocumentError::Empty(name.to_string()));
+        }
+        Ok(Self::new(name, &text))
+    }
+
+    /// What the chat shows of the document.
+    pub fn info(&self) -> DocumentInfo {
+        DocumentInfo {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            chars: self.chars,
+        }
+    }
+}
+
+/// Cut `text` into passages of about `PASSAGE_CHARS`, at paragraph (or
+/// line) breaks where possible.
+fn split_passages(text: &str) -> Vec<String> {
+    let mut passages = Vec::new();
+    let mut current = String::new();
+
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if !current.is_empty() && current.chars().count() + line.chars().count() > PASSAGE_CHARS {
+            passages.push(std::mem::take(&mut current));
+        }
+
+        // A single huge line (common in PDFs) is cut wherever it has to be
+        let mut rest = line;
+        while rest.chars().count() > PASSAGE_CHARS {
+            let cut = rest.char_indices().nth(PASSAGE_CHARS).map_or(rest.len(), |(i, _)| i);
+            let cut = rest[..cut].rfind(' ').filter(|&i| i > 0).unwrap_or(cut);
+            passages.push(rest[..cut].trim().to_string());
+            rest = rest[cut..].trim_start();
+        }
+
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(rest);
+    }
+    if !current.is_empty() {
+        passages.push(current);
+    }
+    passages
+}
+
+/// Lowercase words of at least `MIN_TERM_CHARS` characters.
+fn terms(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= MIN_TERM_CHARS)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// The `limit` passages of `documents` that best match `query`.
+///
+/// A small keyword ranking rather than embeddings: each query term found
+/// in a passage counts once per occurrence, weighted up when few passages
+/// contain it, so "tokio" outranks "runtime" in a document about runtimes.
+pub fn search(documents: &[Arc<Document>], query: &str, limit: usize) -> Vec<DocumentHit> {
+    let query_terms: Vec<String> = terms(query)
+        .into_iter()
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    if query_terms.is_empty() {
+        return Vec::new();
+    }
+
+    let passages: Vec<(&Document, usize, Vec<String>)> = documents
+        .iter()
+        .flat_map(|document| {
+            document
+                .passages
+                .iter()
+                .enumerate()
+                .map(move |(i, passage)| (document.as_ref(), i, terms(passage)))
+        })
+        .collect();
+
+    let total = passages.len() as f64;
+    let weights: HashMap<&str, f64> = query_terms
+        .iter()
+        .map(|term| {
+            let containing = passages
+                .iter()
+                .filter(|(_, _, words)| words.contains(term))
+                .count() as f64;
+            (term.as_str(), ((total + 1.0) / (containing + 1.0)).ln() + 1.0)
+        })
+        .collect();
+
+    let mut scored: Vec<(f64, &Document, usize)> = passages
+        .iter()
+        .map(|(document, i, words)| {
+            let score = words
+                .iter()
+                .filter_map(|word| weights.get(word.as_str()))
+                .sum::<f64>();
+            (score, *document, *i)
+        })
+        .filter(|(score, _, _)| *score > 0.0)
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    scored
+        .into_iter()
+        .take(limit)
+        .map(|(_, document, i)| DocumentHit {
+            title: format!("{} (part {})", document.name, i + 1),
+            url: format!("{}{}#{}", ATTACHMENT_URL_PREFIX, document.id, i + 1),
+            text: document.passages[i].clone(),
+        })
+        .collect()
+}
+
+// =============================================================================
+// DOCUMENT STORE
+// =============================================================================
+struct StoredDocument {
+    document: Arc<Document>,
+    last_used: Instant,
+
+    /// API key name that uploaded the document
+    owner: String,
+
+    /// Chat session the document was attached in; `None` for uploads over
+    /// the REST API, which any query of the key may use
+    session: Option<String>,
+}
+
+impl StoredDocument {
+    fn usable_by(&self, owner: &str, session: Option<&str>) -> bool {
+        self.owner == owner
+            && self.session.as_deref().map_or(true, |s| Some(s) == session)
+            && self.last_used.elapsed() < DOCUMENT_TTL
+    }
+}
+
+/// Attached documents, shared by all requests.
+pub struct DocumentStore {
+    entries: Mutex<HashMap<String, StoredDocument>>,
+}
+
+impl DocumentStore {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Keep `document` for `owner` (and its chat `session`, if any),
+    /// making room if the store is full.
+    pub fn insert(&self, document: Document, owner: &str, session: Option<&str>) -> DocumentInfo {
+        let info = document.info();
+        let mut entries = self.lock();
+        entries.retain(|_, entry| entry.last_used.elapsed() < DOCUMENT_TTL);
+        while entries.len() >= MAX_DOCUMENTS {
+            let stalest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(id, _)| id.clone());
+            match stalest {
+                Some(id) => entries.remove(&id),
+                None => break,
+            };
+        }
+
+        entries.insert(
+            document.id.clone(),
+            StoredDocument {
+                document: Arc::new(document),
+                last_used: Instant::now(),
+                owner: owner.to_string(),
+                session: session.map(str::to_string),
+            },
+        );
+        info
+    }
+
+    /// The documents with these ids that `owner` may use in `session`,
+    /// which count as used now. `Err` holds the first id that is unknown,
+    /// has expired or belongs to someone else - they look the same.
+    pub fn get(
+        &self,
+        ids: &[String],
+        owner: &str,
+        session: Option<&str>,
+    ) -> Result<Vec<Arc<Document>>, String> {
+        let mut entries = self.lock();
+        ids.iter()
+            .map(|id| {
+                let entry = entries
+                    .get_mut(id)
+                    .filter(|entry| entry.usable_by(owner, session))
+                    .ok_or_else(|| id.clone())?;
+                entry.last_used = Instant::now();
+                Ok(entry.document.clone())
+            })
+            .collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, StoredDocument>> {
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for DocumentStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// =============================================================================
+// UNIT TESTS
+// =============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_kind() {
+        assert_eq!(DocumentKind::detect("notes.MD", None), Some(DocumentKind::Text));
+        assert_eq!(DocumentKind::detect("page.htm", None), Some(DocumentKind::Html));
+        assert_eq!(DocumentKind::detect("paper.pdf", Some("application/octet-stream")), Some(DocumentKind::Pdf));
+        assert_eq!(DocumentKind::detect("upload", Some("application/pdf")), Some(DocumentKind::Pdf));
+        assert_eq!(DocumentKind::detect("data.csv", Some("text/csv; charset=utf-8")), Some(DocumentKind::Text));
+        assert_eq!(DocumentKind::detect("photo.png", Some("image/png")), None);
+    }
+
+    #[test]
+    fn test_upload_extracts_text() {
+        let html = b"<html><body><h1>Tokio</h1><p>An async runtime</p><script>x()</script></body></html>";
+        let document = Document::from_upload("tokio.html", None, html).unwrap();
+        assert_eq!(document.passages, ["Tokio\nAn async runtime"]);
+        assert_eq!(document.info().name, "tokio.html");
+
+        assert!(matches!(
+            Document::from_upload("empty.txt", None, b"  \n "),
+            Err(DocumentError::Empty(_))
+        ));
+        assert!(matches!(
+            Document::from_upload("archive.zip", None, b"PK"),
+            Err(DocumentError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_passages_stay_near_the_limit() {
+        let paragraph = "word ".repeat(100);
+        let text = vec![paragraph.trim(); 10].join("\n\n");
+        let passages = split_passages(&text);
+        assert!(passages.len() > 1);
+        assert!(passages.iter().all(|p| p.chars().count() <= PASSAGE_CHARS));
+
+        let long_line = "x".repeat(PASSAGE_CHARS * 2 + 10);
+        assert_eq!(split_passages(&long_line).len(), 3);
+    }
+
+    #[test]
+    fn test_search_ranks_matching_passages() {
+        let text = format!(
+            "{}\n{}\n{}",
+            "Runtimes schedule tasks. ".repeat(60),
+            "Tokio is a runtime for async Rust with a work-stealing scheduler.",
+            "Unrelated closing remarks about gardening."
+        );
+        let document = Arc::new(Document::new("runtimes.md", &text));
+
+        let hits = search(&[document.clone()], "What does Tokio do?", 3);
+        assert!(!hits.is_empty());
+        assert!(hits[0].text.contains("Tokio"));
+        assert!(hits[0].url.starts_with(&format!("{}{}#", ATTACHMENT_URL_PREFIX, document.id)));
+
+        assert!(search(&[document], "photosynthesis", 3).is_empty());
+    }
+
+    #[test]
+    fn test_store_returns_known_documents() {
+        let store = DocumentStore::new();
+        let info = store.insert(Document::new("a.txt", "alpha"), "ci", None);
+
+        let found = store.get(&[info.id.clone()], "ci", None).unwrap();
+        assert_eq!(found[0].name, "a.txt");
+        assert_eq!(store.get(&[info.id, "gone".to_string()], "ci", None).unwrap_err(), "gone");
+    }
+
+    #[test]
+    fn test_store_keeps_documents_to_their_owner_and_chat() {
+        let store = DocumentStore::new();
+        let info = store.insert(Document::new("a.txt", "alpha"), "ci", Some("chat-1"));
+        let ids = [info.id.clone()];
+
+        assert!(store.get(&ids, "ci", Some("chat-1")).is_ok());
+        assert_eq!(store.get(&ids, "ci", Some("chat-2")).unwrap_err(), info.id);
+        assert_eq!(store.get(&ids, "ci", None).unwrap_err(), info.id);
+        assert_eq!(store.get(&ids, "other", Some("chat-1")).unwrap_err(), info.id);
+
+        // REST uploads belong to the key, whichever chat uses them
+        let info = store.insert(Document::new("b.txt", "beta"), "ci", None);
+        assert!(store.get(&[info.id.clone()], "ci", Some("chat-2")).is_ok());
+        assert!(store.get(&[info.id], "other", None).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+// Dimensionless Developments Rust Ai
+// # Error Module
+// This module defines the typed error hierarchy for the research agent.
+// It demonstrates:
+// - Modelling failure modes as an enum instead of strings
+// - Classifying foreign errors by walking the `source()` chain
+// - Mapping one error type onto several "outer" protocols
+//   (process exit codes, WebSocket error codes, HTTP statuses)
+
+use axum::http::StatusCode;
+use thiserror::Error;
+
+use crate::tools::SearchError;
+
+// =============================================================================
+// AGENT ERROR
+// =============================================================================
+/// Everything that can go wrong while answering a research query.
+///
+/// Each variant maps to a stable process exit code, a stable WebSocket
+/// error code string and an HTTP status, so scripts and the web UI can
+/// react to *what* failed rather than parsing messages.
+///
+/// | Variant               | Exit code | WebSocket code         | HTTP status |
+/// |-----------------------|-----------|------------------------|-------------|
+/// | `Other`               | 1         | `agent_failed`         | 500         |
+/// | `InvalidConfig`       | 3         | `invalid_config`       | 500         |
+/// | `ProviderUnreachable` | 4         | `provider_unreachable` | 502         |
+/// | `ModelMissing`        | 5         | `model_missing`        | 503         |
+/// | `ToolFailure`         | 6         | `tool_failure`         | 502         |
+/// | `RateLimited`         | 7         | `rate_limited`         | 429         |
+/// | `Timeout`             | 8         | `timeout`              | 504         |
+/// | `ShuttingDown`        | 9         | `shutting_down`        | 503         |
+/// | `ServerBusy`          | 10        | `server_busy`          | 503         |
+/// | `Unauthorized`        | 11        | `unauthorized`         | 401         |
+/// | `Forbidden`           | 12        | `forbidden`            | 403         |
+/// | `QuotaExceeded`       | 13        | `quota_exceeded`       | 429         |
+/// | `TooManyConnections`  | 14        | `too_many_connections` | 429         |
+/// | `InvalidOptions`      | 15        | `invalid_options`      | 400         |
+///
+/// Exit code 2 is reserved for command-line usage errors (reported by clap).
+#[derive(Error, Debug)]
+pub enum AgentError {
+    #[error("Cannot reach the LLM provider at {host}: {reason}")]
+    ProviderUnreachable { host: String, reason: String },
+
+    #[error("Model '{model}' is not available on the LLM provider")]
+    ModelMissing { model: String },
+
+    #[error("Tool '{tool}' failed: {reason}")]
+    ToolFailure { tool: String, reason: String },
+
+    #[error("Rate limited by {provider}, please wait and retry")]
+    RateLimited { provider: String },
+
+    #[error("Research timed out after {secs}s")]
+    Timeout { secs: u64 },
+
+    #[error("The server is shutting down and not accepting new queries")]
+    ShuttingDown,
+
+    #[error("The server is busy: {queued} queries are already waiting")]
+    ServerBusy { queued: usize },
+
+    #[error("Missing or invalid API key")]
+    Unauthorized,
+
+    #[error("API key '{key}' is not allowed to use {scope}")]
+    Forbidden { key: String, scope: &'static str },
+
+    #[error("API key '{key}' used its {limit} requests per {period}")]
+    QuotaExceeded {
+        key: String,
+        limit: u32,
+        period: &'static str,
+    },
+
+    #[error("Too many WebSocket connections from {ip} (limit {limit})")]
+    TooManyConnections { ip: String, limit: usize },
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Invalid query options: {0}")]
+    InvalidOptions(String),
+
+    #[error("Agent execution failed: {0}")]
+    Other(String),
+}
+
+impl AgentError {
+    /// Process exit code for this error (see the table above).
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            AgentError::Other(_) => 1,
+            AgentError::InvalidConfig(_) => 3,
+            AgentError::ProviderUnreachable { .. } => 4,
+            AgentError::ModelMissing { .. } => 5,
+            AgentError::ToolFailure { .. } => 6,
+            AgentError::RateLimited { .. } => 7,
+            AgentError::Timeout { .. } => 8,
+            AgentError::ShuttingDown => 9,
+            AgentError::ServerBusy { .. } => 10,
+            AgentError::Unauthorized => 11,
+            AgentError::Forbidden { .. } => 12,
+            AgentError::QuotaExceeded { .. } => 13,
+            AgentError::TooManyConnections { .. } => 14,
+            AgentError::InvalidOptions(_) => 15,
+        }
+    }
+
+    /// Stable machine-readable code sent to WebSocket and REST clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AgentError::Other(_) => "agent_failed",
+            AgentError::InvalidConfig(_) => "invalid_config",
+            AgentError::ProviderUnreachable { .. } => "provider_unreachable",
+            AgentError::ModelMissing { .. } => "model_missing",
+            AgentError::ToolFailure { .. } => "tool_failure",
+            AgentError::RateLimited { .. } => "rate_limited",
+            AgentError::Timeout { .. } => "timeout",
+            AgentError::ShuttingDown => "shutting_down",
+            AgentError::ServerBusy { .. } => "server_busy",
+            AgentError::Unauthorized => "unauthorized",
+            AgentError::Forbidden { .. } => "forbidden",
+            AgentError::QuotaExceeded { .. } => "quota_exceeded",
+            AgentError::TooManyConnections { .. } => "too_many_connections",
+            AgentError::InvalidOptions(_) => "invalid_options",
+        }
+    }
+
+    /// HTTP status for REST responses.
+    pub fn http_status(&self) -> StatusCode {
+        match self {
+            AgentError::Other(_) | AgentError::InvalidConfig(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            AgentError::ProviderUnreachable { .. } | AgentError::ToolFailure { .. } => {
+                StatusCode::BAD_GATEWAY
+            }
+            AgentError::ModelMissing { .. }
+            | AgentError::ShuttingDown
+            | AgentError::ServerBusy { .. } => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            AgentError::RateLimited { .. }
+            | AgentError::QuotaExceeded { .. }
+            | AgentError::TooManyConnections { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AgentError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AgentError::Forbidden { .. } => StatusCode::FORBIDDEN,
+            AgentError::InvalidOptions(_) => StatusCode::BAD_REQUEST,
+            AgentError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+
+    /// An actionable suggestion for the user, if we have one.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            AgentError::ProviderUnreachable { host, .. } => Some(format!(
+                "Make sure Ollama is running (`ollama serve`) and reachable at {}",
+                host
+            )),
+            AgentError::ModelMissing { model } => Some(format!(
+                "Install the model with `ollama pull {}` or pick another with --model",
+                model
+            )),
+            AgentError::RateLimited { .. } => {
+                Some("Wait a minute before searching again".to_string())
+            }
+            AgentError::Timeout { .. } => Some(
+                "Try a smaller model or raise research_timeout_secs in your config".to_string(),
+            ),
+            AgentError::ShuttingDown => {
+                Some("The server is restarting; retry in a few seconds".to_string())
+            }
+            AgentError::ServerBusy { .. } => {
+                Some("Too many queries are waiting; try again in a minute".to_string())
+            }
+            AgentError::Unauthorized => Some(
+                "Send your key as `Authorization: Bearer <key>` (or `?token=<key>` for /ws)"
+                    .to_string(),
+            ),
+            AgentError::Forbidden { scope, .. } => {
+                Some(format!("Ask for a key with the '{}' scope", scope))
+            }
+            AgentError::QuotaExceeded { period, .. } => {
+                Some(format!("The limit resets within a {}", period))
+            }
+            AgentError::TooManyConnections { .. } => Some(
+                "Close other tabs using the research agent, or raise ws_max_connections_per_ip"
+                    .to_string(),
+            ),
+            AgentError::InvalidConfig(_) => Some(
+                "Run `ai-research-agent config show --resolved` to inspect settings".to_string(),
+            ),
+            AgentError::InvalidOptions(_) => {
+                Some("Check the chat's settings (or the request's options)".to_string())
+            }
+            AgentError::ToolFailure { .. } | AgentError::Other(_) => None,
+        }
+    }
+
+    /// Classify an error returned by the LLM framework.
+    ///
+    /// # Rust Concept: Error Source Chains
+    ///
+    /// Errors in Rust can wrap other errors, exposed via `source()`.
+    /// Rig wraps the underlying HTTP or tool error several levels deep, so
+    /// we walk the chain and use `downcast_ref` to find the types we know.
+    /// If nothing matches we fall back to inspecting the message, which is
+    /// how Ollama reports a missing model.
+    pub fn from_llm_error(
+        err: &(dyn std::error::Error + 'static),
+        host: &str,
+        model: &str,
+    ) -> Self {
+        let mut current = Some(err);
+        let mut chain_text = String::new();
+
+        while let Some(e) = current {
+            chain_text.push_str(&e.to_string());
+            chain_text.push('\n');
+
+            if let Some(search_err) = e.downcast_ref::<SearchError>() {
+                return AgentError::from_search_error(search_err);
+            }
+
+            if let Some(http_err) = e.downcast_ref::<reqwest::Error>() {
+                if http_err.is_connect() {
+                    return AgentError::ProviderUnreachable {
+                        host: host.to_string(),
+                        reason: http_err.to_string(),
+                    };
+                }
+            }
+
+            current = e.source();
+        }
+
+        let message = err.to_string();
+        let lower = chain_text.to_lowercase();
+
+        if lower.contains("connection refused")
+            || lower.contains("error sending request")
+            || lower.contains("tcp connect")
+        {
+            AgentError::ProviderUnreachable {
+                host: host.to_string(),
+                reason: message,
+            }
+        } else if lower.contains("model") && (lower.contains("not found") || lower.contains("pull"))
+        {
+            AgentError::ModelMissing {
+                model: model.to_string(),
+            }
+        } else {
+            AgentError::Other(message)
+        }
+    }
+
+    /// Map a web search failure onto the agent error hierarchy.
+    pub fn from_search_error(err: &SearchError) -> Self {
+        match err {
+            SearchError::RateLimited => AgentError::RateLimited {
+                provider: "duckduckgo".to_string(),
+            },
+            other => AgentError::ToolFailure {
+                tool: "web_search".to_string(),
+                reason: other.to_string(),
+            },
+        }
+    }
+}
+
+impl From<SearchError> for AgentError {
+    fn from(err: SearchError) -> Self {
+        AgentError::from_search_error(&err)
+    }
+}
+
+// =============================================================================
+// UNIT TESTS
+// =============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let errors = [
+            AgentError::Other("x".into()),
+            AgentError::InvalidConfig("x".into()),
+            AgentError::ProviderUnreachable { host: "h".into(), reason: "r".into() },
+            AgentError::ModelMissing { model: "m".into() },
+            AgentError::ToolFailure { tool: "t".into(), reason: "r".into() },
+            AgentError::RateLimited { provider: "p".into() },
+            AgentError::Timeout { secs: 1 },
+            AgentError::ShuttingDown,
+            AgentError::ServerBusy { queued: 1 },
+            AgentError::Unauthorized,
+            AgentError::Forbidden { key: "k".into(), scope: "research" },
+            AgentError::QuotaExceeded { key: "k".into(), limit: 1, period: "day" },
+            AgentError::TooManyConnections { ip: "127.0.0.1".into(), limit: 1 },
+            AgentError::InvalidOptions("x".into()),
+        ];
+
+        let mut codes: Vec<u8> = errors.iter().map(|e| e.exit_code()).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+        assert!(!codes.contains(&0) && !codes.contains(&2));
+    }
+
+    #[test]
+    fn test_search_rate_limit_maps_to_rate_limited() {
+        let err: AgentError = SearchError::RateLimited.into();
+        assert_eq!(err.code(), "rate_limited");
+        assert_eq!(err.http_status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[test]
+    fn test_classify_by_message() {
+        let refused = std::io::Error::other("Connection refused (os error 111)");
+        let err = AgentError::from_llm_error(&refused, "http://localhost:11434", "llama3.2");
+        assert!(matches!(err, AgentError::ProviderUnreachable { .. }));
+
+        let missing = std::io::Error::other("model \"llama3.2\" not found, try pulling it first");
+        let err = AgentError::from_llm_error(&missing, "http://localhost:11434", "llama3.2");
+        assert!(matches!(err, AgentError::ModelMissing { ref model } if model == "llama3.2"));
+        assert!(err.hint().unwrap().contains("ollama pull llama3.2"));
+    }
+}
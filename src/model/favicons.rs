@@ -0,0 +1,251 @@
+// Dimensionless Developments Rust Ai
+// # Favicons Module
+// Fetches and caches the icons shown on the chat's source cards.
+//
+// The browser asks us (`GET /api/favicon/{domain}`) instead of the
+// sites themselves: the user's IP and browsing don't leak to every domain
+// in a result list, and each icon is downloaded once per day, not once
+// per answer. Icons come from DuckDuckGo's icon service, which we already
+// depend on for search. Unknown domains get a neutral globe icon, so the
+// cards never show a broken image.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use tracing::debug;
+
+use crate::tools::is_public_http_url;
+
+/// Icon service; `{domain}.ico` is appended.
+const ICON_SERVICE_URL: &str = "https://icons.duckduckgo.com/ip3/";
+
+/// How long a fetched icon, or the fact that there is none, is kept.
+const ICON_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const MISSING_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Domains kept in memory; the stalest are dropped beyond this.
+const MAX_ENTRIES: usize = 2048;
+
+/// Larger "icons" are refused.
+const MAX_ICON_BYTES: usize = 64 * 1024;
+
+/// Image types passed on from the icon service. Raster formats only: an
+/// SVG is a document that can carry script, and we serve icons from our
+/// own origin.
+const RASTER_TYPES: &[&str] = &[
+    "image/x-icon",
+    "image/vnd.microsoft.icon",
+    "image/png",
+    "image/gif",
+    "image/jpeg",
+    "image/webp",
+];
+
+/// Shown for domains without an icon.
+const FALLBACK_ICON: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 16 16"><circle cx="8" cy="8" r="7" fill="none" stroke="#9ca3af" stroke-width="1.3"/><path d="M1 8h14M8 1c2 2 2.8 4.3 2.8 7S10 13 8 15M8 1C6 3 5.2 5.3 5.2 8S6 13 8 15" fill="none" stroke="#9ca3af" stroke-width="1.1"/></svg>"##;
+
+/// An icon ready to be served.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Favicon {
+    pub content_type: String,
+    pub bytes: Vec<u8>,
+}
+
+impl Favicon {
+    fn fallback() -> Self {
+        Self {
+            content_type: "image/svg+xml".to_string(),
+            bytes: FALLBACK_ICON.as_bytes().to_vec(),
+        }
+    }
+}
+
+struct CachedIcon {
+    /// `None` when the domain has no usable icon
+    icon: Option<Favicon>,
+    fetched: Instant,
+}
+
+impl CachedIcon {
+    fn is_fresh(&self) -> bool {
+        let ttl = if self.icon.is_some() { ICON_TTL } else { MISSING_TTL };
+        self.fetched.elapsed() < ttl
+    }
+}
+
+/// In-memory favicon cache, shared by all requests.
+pub struct FaviconCache {
+    entries: Mutex<HashMap<String, CachedIcon>>,
+    client: reqwest::Client,
+}
+
+impl FaviconCache {
+    pub fn new() -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            client,
+        }
+    }
+
+    /// The icon of `domain`, or the fallback icon. `None` when `domain`
+    /// isn't a public host name at all.
+    pub async fn get(&self, domain: &str) -> Option<Favicon> {
+        let domain = domain.trim().trim_end_matches('.').to_ascii_lowercase();
+        if !is_valid_domain(&domain) {
+            return None;
+        }
+
+        let cached = self
+            .lock()
+            .get(&domain)
+            .filter(|entry| entry.is_fresh())
+            .map(|entry| entry.icon.clone());
+        let icon = match cached {
+            Some(icon) => icon,
+            None => {
+                let icon = self.fetch(&domain).await;
+                self.insert(domain, icon.clone());
+                icon
+            }
+        };
+
+        Some(icon.unwrap_or_else(Favicon::fallback))
+    }
+
+    /// Download the icon; any failure means "no icon".
+    async fn fetch(&self, domain: &str) -> Option<Favicon> {
+        let url = format!("{}{}.ico", ICON_SERVICE_URL, domain);
+        let response = match self.client.get(&url).send().await {
+            Ok(response) if response.status().is_success() => response,
+            Ok(response) => {
+                debug!(domain, status = response.status().as_u16(), "No favicon");
+                return None;
+            }
+            Err(e) => {
+                debug!(domain, "Favicon fetch failed: {}", e);
+                return None;
+            }
+        };
+
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(raster_type)?;
+
+        let bytes = response.bytes().await.ok()?;
+        if bytes.is_empty() || bytes.len() > MAX_ICON_BYTES {
+            return None;
+        }
+
+        Some(Favicon {
+            content_type: content_type.to_string(),
+            bytes: bytes.to_vec(),
+        })
+    }
+
+    fn insert(&self, domain: String, icon: Option<Favicon>) {
+        let mut entries = self.lock();
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_, entry| entry.is_fresh());
+        }
+        if entries.len() >= MAX_ENTRIES {
+            let stalest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.fetched)
+                .map(|(domain, _)| domain.clone());
+            if let Some(stalest) = stalest {
+                entries.remove(&stalest);
+            }
+        }
+
+        entries.insert(
+            domain,
+            CachedIcon {
+                icon,
+                fetched: Instant::now(),
+            },
+        );
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, CachedIcon>> {
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for FaviconCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The raster image type of a `Content-Type` header (parameters and case
+/// ignored), or `None` for anything else.
+fn raster_type(content_type: &str) -> Option<&'static str> {
+    let essence = content_type.split(';').next()?.trim().to_ascii_lowercase();
+    RASTER_TYPES.iter().copied().find(|&t| t == essence)
+}
+
+/// A public DNS name like `www.rust-lang.org`: letters, digits, dashes and
+/// dots only, with at least one dot, and not a private address.
+fn is_valid_domain(domain: &str) -> bool {
+    let charset_ok = domain
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+    if domain.is_empty() || domain.len() > 253 || !charset_ok || !domain.contains('.') {
+        return false;
+    }
+
+    reqwest::Url::parse(&format!("https://{}/", domain))
+        .map(|url| is_public_http_url(&url))
+        .unwrap_or(false)
+}
+
+// =============================================================================
+// UNIT TESTS
+// =============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_domain_validation() {
+        assert!(is_valid_domain("www.rust-lang.org"));
+        assert!(is_valid_domain("docs.rs"));
+        assert!(!is_valid_domain("localhost"));
+        assert!(!is_valid_domain("printer.local"));
+        assert!(!is_valid_domain("192.168.1.1"));
+        assert!(!is_valid_domain("evil.com/../admin"));
+        assert!(!is_valid_domain(""));
+    }
+
+    #[test]
+    fn test_only_raster_icons_are_accepted() {
+        assert_eq!(raster_type("image/png"), Some("image/png"));
+        assert_eq!(raster_type("Image/X-Icon; charset=binary"), Some("image/x-icon"));
+        assert_eq!(raster_type("image/svg+xml"), None);
+        assert_eq!(raster_type("text/html"), None);
+        assert_eq!(raster_type(""), None);
+    }
+
+    #[tokio::test]
+    async fn test_cached_icons_are_served_without_fetching() {
+        let cache = FaviconCache::new();
+        let icon = Favicon {
+            content_type: "image/png".to_string(),
+            bytes: vec![1, 2, 3],
+        };
+        cache.insert("example.com".to_string(), Some(icon.clone()));
+        cache.insert("no-icon.example".to_string(), None);
+
+        assert_eq!(cache.get("Example.com.").await, Some(icon));
+        assert_eq!(cache.get("no-icon.example").await, Some(Favicon::fallback()));
+        assert_eq!(cache.get("localhost").await, None);
+    }
+}
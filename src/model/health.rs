@@ -0,0 +1,282 @@
+// Dimensionless Developments Rust Ai
+// # Health Module
+// Liveness and readiness checks for the web server.
+//
+// - `/healthz` answers as long as the process is running (liveness).
+// - `/readyz` checks that we can actually answer queries (readiness):
+//   Ollama is reachable, the configured model is installed, and the
+//   search provider is reachable (or answered a real search recently).
+//
+// Reverse proxies and process supervisors use these to route traffic
+// away from, or restart, a broken instance.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::error::AgentError;
+use crate::tools::{WebSearchTool, DUCKDUCKGO_URL};
+
+/// Timeout for each individual probe. Probes must be fast - the proxy
+/// calling `/readyz` usually has a short timeout of its own.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A real search within this window counts as "search provider is up".
+const SEARCH_SUCCESS_WINDOW: Duration = Duration::from_secs(300);
+
+/// How long a search probe result is reused before probing again.
+const SEARCH_PROBE_TTL: Duration = Duration::from_secs(60);
+
+// =============================================================================
+// RESPONSE TYPES
+// =============================================================================
+/// Result of one readiness check.
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckResult {
+    pub ok: bool,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+}
+
+impl CheckResult {
+    fn pass(detail: impl Into<String>, latency: Option<Duration>) -> Self {
+        Self {
+            ok: true,
+            detail: detail.into(),
+            latency_ms: latency.map(|d| d.as_millis() as u64),
+        }
+    }
+
+    fn fail(detail: impl Into<String>, latency: Option<Duration>) -> Self {
+        Self {
+            ok: false,
+            detail: detail.into(),
+            latency_ms: latency.map(|d| d.as_millis() as u64),
+        }
+    }
+}
+
+/// Body of `/readyz`.
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+
+    /// The server is shutting down and finishing its running jobs
+    pub draining: bool,
+
+    pub ollama: CheckResult,
+    pub model: CheckResult,
+    pub search: CheckResult,
+}
+
+/// Body of `/healthz`.
+#[derive(Debug, Serialize)]
+pub struct Liveness {
+    pub status: &'static str,
+    pub version: &'static str,
+    pub uptime_secs: u64,
+}
+
+// =============================================================================
+// OLLAMA API
+// =============================================================================
+/// Response of Ollama's `GET /api/tags`.
+#[derive(Debug, Deserialize)]
+struct TagsResponse {
+    models: Vec<ModelTag>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelTag {
+    name: String,
+}
+
+/// List the models installed on the Ollama server at `host`.
+pub async fn list_models(host: &str) -> Result<Vec<String>, AgentError> {
+    let url = format!("{}/api/tags", host.trim_end_matches('/'));
+    let unreachable = |e: reqwest::Error| AgentError::ProviderUnreachable {
+        host: host.to_string(),
+        reason: e.to_string(),
+    };
+
+    let client = reqwest::Client::builder()
+        .timeout(PROBE_TIMEOUT)
+        .build()
+        .map_err(unreachable)?;
+
+    let tags: TagsResponse = client
+        .get(&url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(unreachable)?
+        .json()
+        .await
+        .map_err(unreachable)?;
+
+    Ok(tags.models.into_iter().map(|m| m.name).collect())
+}
+
+/// Does `installed` contain `model`? Ollama reports `llama3.2:latest` for a
+/// model configured as plain `llama3.2`, so an untagged name matches `:latest`.
+pub fn model_installed(installed: &[String], model: &str) -> bool {
+    installed.iter().any(|name| {
+        name == model || (!model.contains(':') && name == &format!("{}:latest", model))
+    })
+}
+
+// =============================================================================
+// HEALTH CHECKER
+// =============================================================================
+/// Runs the readiness checks and caches the search probe.
+pub struct HealthChecker {
+    started: Instant,
+    search_probe: Mutex<Option<(Instant, CheckResult)>>,
+}
+
+impl HealthChecker {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            search_probe: Mutex::new(None),
+        }
+    }
+
+    /// Liveness: if we can run this code, we're alive.
+    pub fn liveness(&self) -> Liveness {
+        Liveness {
+            status: "ok",
+            version: env!("CARGO_PKG_VERSION"),
+            uptime_secs: self.started.elapsed().as_secs(),
+        }
+    }
+
+    /// Readiness: probe Ollama and the search provider.
+    ///
+    /// # Rust Concept: Concurrent Futures with tokio::join!
+    ///
+    /// The Ollama and search probes don't depend on each other, so we run
+    /// them at the same time; the check takes as long as the slowest probe.
+    pub async fn readiness(&self, config: &Config) -> Readiness {
+        let (models, search) = tokio::join!(self.probe_ollama(config), self.probe_search());
+        let (ollama, model) = models;
+
+        Readiness {
+            ready: ollama.ok && model.ok && search.ok,
+            draining: false,
+            ollama,
+            model,
+            search,
+        }
+    }
+
+    /// Check that Ollama answers and has the configured model.
+    async fn probe_ollama(&self, config: &Config) -> (CheckResult, CheckResult) {
+        let started = Instant::now();
+
+        match list_models(&config.ollama_host).await {
+            Ok(installed) => {
+                let latency = Some(started.elapsed());
+                let ollama =
+                    CheckResult::pass(format!("reachable at {}", config.ollama_host), latency);
+                let model = if model_installed(&installed, &config.model) {
+                    CheckResult::pass(format!("'{}' is installed", config.model), None)
+                } else {
+                    let hint = format!("run `ollama pull {}`", config.model);
+                    CheckResult::fail(format!("'{}' not installed; {}", config.model, hint), None)
+                };
+                (ollama, model)
+            }
+            Err(e) => (
+                CheckResult::fail(e.to_string(), Some(started.elapsed())),
+                CheckResult::fail("unknown: Ollama is unreachable", None),
+            ),
+        }
+    }
+
+    /// Check the search provider, preferring evidence from real searches.
+    async fn probe_search(&self) -> CheckResult {
+        if let Some(age) = WebSearchTool::last_success_age() {
+            if age < SEARCH_SUCCESS_WINDOW {
+                return CheckResult::pass(
+                    format!("cached: last search succeeded {}s ago", age.as_secs()),
+                    None,
+                );
+            }
+        }
+
+        if let Some((at, result)) = self.search_probe.lock().ok().and_then(|p| p.clone()) {
+            if at.elapsed() < SEARCH_PROBE_TTL {
+                return result;
+            }
+        }
+
+        let started = Instant::now();
+        let result = match head_status(DUCKDUCKGO_URL).await {
+            Ok(status) => {
+                let latency = Some(started.elapsed());
+                if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                    CheckResult::fail("duckduckgo is rate limiting us", latency)
+                } else if status.is_success()
+                    || status.is_redirection()
+                    || status == reqwest::StatusCode::METHOD_NOT_ALLOWED
+                {
+                    // Any of these proves the provider is up and answering
+                    CheckResult::pass(format!("duckduckgo reachable (HTTP {})", status), latency)
+                } else {
+                    CheckResult::fail(format!("duckduckgo returned HTTP {}", status), latency)
+                }
+            }
+            Err(e) => CheckResult::fail(
+                format!("duckduckgo unreachable: {}", e),
+                Some(started.elapsed()),
+            ),
+        };
+
+        if let Ok(mut probe) = self.search_probe.lock() {
+            *probe = Some((Instant::now(), result.clone()));
+        }
+
+        result
+    }
+}
+
+impl Default for HealthChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Send a HEAD request and return the status code.
+async fn head_status(url: &str) -> Result<reqwest::StatusCode, reqwest::Error> {
+    let client = reqwest::Client::builder().timeout(PROBE_TIMEOUT).build()?;
+    Ok(client.head(url).send().await?.status())
+}
+
+// =============================================================================
+// UNIT TESTS
+// =============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_model_installed_matches_latest_tag() {
+        let installed = vec!["llama3.2:latest".to_string(), "mistral:7b".to_string()];
+
+        assert!(model_installed(&installed, "llama3.2"));
+        assert!(model_installed(&installed, "llama3.2:latest"));
+        assert!(model_installed(&installed, "mistral:7b"));
+        assert!(!model_installed(&installed, "mistral"));
+        assert!(!model_installed(&installed, "gemma2"));
+    }
+
+    #[test]
+    fn test_liveness_reports_ok() {
+        let checker = HealthChecker::new();
+        assert_eq!(checker.liveness().status, "ok");
+    }
+}
@@ -0,0 +1,503 @@
+// Dimensionless Developments Rust Ai
+// # History Module
+// Keeps every research run in a local SQLite database so past answers can
+// be browsed, searched and deleted later - from the CLI (`history ...`) or
+// over REST (`/api/history`).
+//
+// The database lives at `<data_dir>/history.db`. Each run is one row with
+// the query, the options it ran with, the final report, the sources and
+// the tool calls the agent made (its transcript), plus timing.
+//
+// Set `save_history = false` (or `SAVE_HISTORY=false`) to turn it off.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, Row};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::agent::ResearchOutcome;
+use crate::config::Config;
+use crate::error::AgentError;
+use crate::tools::{SearchResult, ToolCallRecord};
+
+/// File (inside the data directory) holding the history database.
+pub const HISTORY_DB_FILE: &str = "history.db";
+
+/// Schema migrations, applied in order. The database's `user_version`
+/// records how many have run, so only new ones are applied on upgrade.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE runs (
+        id            TEXT PRIMARY KEY,
+        query         TEXT NOT NULL,
+        quick         INTEGER NOT NULL,
+        model         TEXT NOT NULL,
+        temperature   REAL NOT NULL,
+        max_results   INTEGER NOT NULL,
+        client        TEXT,
+        status        TEXT NOT NULL,
+        answer        TEXT,
+        error         TEXT,
+        sources       TEXT NOT NULL,
+        tool_calls    TEXT NOT NULL,
+        rounds        INTEGER NOT NULL,
+        input_tokens  INTEGER NOT NULL,
+        output_tokens INTEGER NOT NULL,
+        started_at    INTEGER NOT NULL,
+        duration_ms   INTEGER NOT NULL
+    );
+    CREATE INDEX runs_started_at ON runs (started_at DESC);",
+];
+
+/// Path of the history database inside `data_dir`.
+pub fn history_db_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(HISTORY_DB_FILE)
+}
+
+// =============================================================================
+// RECORDS
+// =============================================================================
+/// One stored run, as returned by `history show` and `GET /api/history/{id}`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunRecord {
+    pub id: String,
+    pub query: String,
+
+    /// Just searched, didn't synthesize
+    pub quick: bool,
+
+    /// Options the run used
+    pub model: String,
+    pub temperature: f32,
+    pub max_results: usize,
+
+    /// API key name (web server) or `cli`
+    pub client: Option<String>,
+
+    /// `success` or the error code of the failure
+    pub status: String,
+
+    /// The final report (only for successful runs)
+    pub answer: Option<String>,
+
+    /// Error message (only for failed runs)
+    pub error: Option<String>,
+
+    pub sources: Vec<SearchResult>,
+
+    /// The tool calls the agent made, in order
+    pub tool_calls: Vec<ToolCallRecord>,
+
+    pub rounds: usize,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+
+    /// When the run started (seconds since the Unix epoch)
+    pub started_at: u64,
+    pub duration_ms: u64,
+}
+
+impl RunRecord {
+    /// Build the record of a finished run.
+    pub fn new(
+        config: &Config,
+        query: &str,
+        quick: bool,
+        client: Option<&str>,
+        started: Instant,
+        result: &Result<ResearchOutcome, AgentError>,
+    ) -> Self {
+        let duration = started.elapsed();
+        let started_at = SystemTime::now()
+            .checked_sub(duration)
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let mut record = Self {
+            id: Uuid::new_v4().to_string(),
+            query: query.to_string(),
+            quick,
+            model: config.model.clone(),
+            temperature: config.temperature,
+            max_results: config.max_search_results,
+            client: client.map(str::to_string),
+            status: "success".to_string(),
+            answer: None,
+            error: None,
+            sources: Vec::new(),
+            tool_calls: Vec::new(),
+            rounds: 0,
+            input_tokens: 0,
+            output_tokens: 0,
+            started_at,
+            duration_ms: duration.as_millis() as u64,
+        };
+
+        match result {
+            Ok(outcome) => {
+                record.answer = Some(outcome.answer.clone());
+                record.sources = outcome.sources();
+                record.tool_calls = outcome.tool_calls.clone();
+                record.rounds = outcome.rounds;
+                record.input_tokens = outcome.input_tokens;
+                record.output_tokens = outcome.output_tokens;
+            }
+            Err(e) => {
+                record.status = e.code().to_string();
+                record.error = Some(e.to_string());
+            }
+        }
+        record
+    }
+}
+
+/// A row of `history list` and `GET /api/history`: everything but the
+/// (potentially long) report, sources and transcript.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunSummary {
+    pub id: String,
+    pub query: String,
+    pub quick: bool,
+    pub model: String,
+    pub status: String,
+    pub started_at: u64,
+    pub duration_ms: u64,
+}
+
+// =============================================================================
+// HISTORY STORE
+// =============================================================================
+/// The history database.
+///
+/// # Rust Concept: Blocking Work in Async Code
+///
+/// rusqlite is synchronous. Queries on a small local database take
+/// microseconds, but the server still calls these methods through
+/// `spawn_blocking` so a slow disk never stalls the async runtime.
+pub struct History {
+    conn: Mutex<Connection>,
+}
+
+impl History {
+    /// Open (or create) the database at `path` and bring its schema up to date.
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open history database {}", path.display()))?;
+        Self::with_connection(conn)
+    }
+
+    /// An in-memory database, for tests.
+    #[cfg(test)]
+    fn in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> Result<Self> {
+        migrate(&conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Store a finished run.
+    pub fn record(&self, run: &RunRecord) -> Result<()> {
+        self.lock().execute(
+            "INSERT INTO runs (id, query, quick, model, temperature, max_results, client,
+                               status, answer, error, sources, tool_calls, rounds,
+                               input_tokens, output_tokens, started_at, duration_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+            params![
+                run.id,
+                run.query,
+                run.quick,
+                run.model,
+                run.temperature,
+                run.max_results,
+                run.client,
+                run.status,
+                run.answer,
+                run.error,
+                serde_json::to_string(&run.sources)?,
+                serde_json::to_string(&run.tool_calls)?,
+                run.rounds,
+                run.input_tokens,
+                run.output_tokens,
+                run.started_at,
+                run.duration_ms,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Most recent runs first. With `text`, only runs whose query or
+    /// report contains it (case-insensitive); with `client`, only the runs
+    /// of that client (e.g. an API key name).
+    pub fn list(
+        &self,
+        text: Option<&str>,
+        client: Option<&str>,
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<RunSummary>> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(
+            "SELECT id, query, quick, model, status, started_at, duration_ms FROM runs
+             WHERE (?1 IS NULL OR query LIKE ?1 ESCAPE '\\' OR answer LIKE ?1 ESCAPE '\\')
+               AND (?4 IS NULL OR client = ?4)
+             ORDER BY started_at DESC, rowid DESC
+             LIMIT ?2 OFFSET ?3",
+        )?;
+
+        let pattern = text.map(|t| format!("%{}%", escape_like(t)));
+        let rows = stmt.query_map(params![pattern, limit, offset, client], summary_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Look up a run by id, or by a unique prefix of it (as printed by
+    /// `history list`). For the CLI; the web API uses `get_exact`.
+    pub fn get(&self, id: &str) -> Result<Option<RunRecord>> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM runs WHERE id LIKE ?1 ESCAPE '\\' LIMIT 2",
+            RECORD_COLUMNS
+        ))?;
+
+        let pattern = format!("{}%", escape_like(id));
+        let mut runs = stmt
+            .query_map([pattern], record_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        match runs.len() {
+            0 => Ok(None),
+            1 => Ok(runs.pop()),
+            _ => anyhow::bail!("Run id '{}' is ambiguous, give more characters", id),
+        }
+    }
+
+    /// Look up a run by its full id. With `client`, runs of other clients
+    /// are `None`, as if they didn't exist.
+    pub fn get_exact(&self, id: &str, client: Option<&str>) -> Result<Option<RunRecord>> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM runs WHERE id = ?1 AND (?2 IS NULL OR client = ?2)",
+            RECORD_COLUMNS
+        ))?;
+        let mut runs = stmt
+            .query_map(params![id, client], record_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(runs.pop())
+    }
+
+    /// Delete a run (by id or unique prefix). Returns false if there was none.
+    pub fn delete(&self, id: &str) -> Result<bool> {
+        let Some(run) = self.get(id)? else {
+            return Ok(false);
+        };
+        self.lock().execute("DELETE FROM runs WHERE id = ?1", [&run.id])?;
+        Ok(true)
+    }
+
+    /// Delete a run by its full id, only if it belongs to `client` (when
+    /// given). Returns false if there was no such run.
+    pub fn delete_exact(&self, id: &str, client: Option<&str>) -> Result<bool> {
+        let deleted = self.lock().execute(
+            "DELETE FROM runs WHERE id = ?1 AND (?2 IS NULL OR client = ?2)",
+            params![id, client],
+        )?;
+        Ok(deleted > 0)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        // A panic mid-query leaves nothing half-written (SQLite rolls back),
+        // so keep using the connection
+        self.conn.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Columns read by `record_from_row`, in order.
+const RECORD_COLUMNS: &str = "id, query, quick, model, temperature, max_results, client, status,
+     answer, error, sources, tool_calls, rounds, input_tokens,
+     output_tokens, started_at, duration_ms";
+
+/// Apply the migrations the database hasn't seen yet.
+fn migrate(conn: &Connection) -> Result<()> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        conn.execute_batch(migration)
+            .with_context(|| format!("History database migration {} failed", index + 1))?;
+        conn.pragma_update(None, "user_version", index + 1)?;
+    }
+    Ok(())
+}
+
+/// Escape `%`, `_` and `\` so user text matches literally in LIKE.
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+fn summary_from_row(row: &Row<'_>) -> rusqlite::Result<RunSummary> {
+    Ok(RunSummary {
+        id: row.get(0)?,
+        query: row.get(1)?,
+        quick: row.get(2)?,
+        model: row.get(3)?,
+        status: row.get(4)?,
+        started_at: row.get(5)?,
+        duration_ms: row.get(6)?,
+    })
+}
+
+fn record_from_row(row: &Row<'_>) -> rusqlite::Result<RunRecord> {
+    Ok(RunRecord {
+        id: row.get(0)?,
+        query: row.get(1)?,
+        quick: row.get(2)?,
+        model: row.get(3)?,
+        temperature: row.get(4)?,
+        max_results: row.get(5)?,
+        client: row.get(6)?,
+        status: row.get(7)?,
+        answer: row.get(8)?,
+        error: row.get(9)?,
+        sources: json_column(row, 10)?,
+        tool_calls: json_column(row, 11)?,
+        rounds: row.get(12)?,
+        input_tokens: row.get(13)?,
+        output_tokens: row.get(14)?,
+        started_at: row.get(15)?,
+        duration_ms: row.get(16)?,
+    })
+}
+
+/// Read a column holding JSON text.
+fn json_column<T: serde::de::DeserializeOwned>(row: &Row<'_>, index: usize) -> rusqlite::Result<T> {
+    let text: String = row.get(index)?;
+    serde_json::from_str(&text).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+// =============================================================================
+// UNIT TESTS
+// =============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(query: &str, answer: &str, started_at: u64) -> RunRecord {
+        let outcome = ResearchOutcome {
+            answer: answer.to_string(),
+            tool_calls: vec![ToolCallRecord {
+                tool: "web_search".to_string(),
+                query: query.to_string(),
+                results: vec![SearchResult {
+                    title: "Rust".to_string(),
+                    url: "https://www.rust-lang.org".to_string(),
+                    snippet: "A language".to_string(),
+                }],
+                error: None,
+            }],
+            rounds: 2,
+            input_tokens: 100,
+            output_tokens: 50,
+            ..Default::default()
+        };
+        let config = Config::default();
+        let mut record =
+            RunRecord::new(&config, query, false, Some("cli"), Instant::now(), &Ok(outcome));
+        record.started_at = started_at;
+        record
+    }
+
+    #[test]
+    fn test_record_and_get_round_trip() {
+        let history = History::in_memory().unwrap();
+        let stored = run("What is Rust?", "A systems language", 1);
+        history.record(&stored).unwrap();
+
+        // A prefix of the id is enough
+        let loaded = history.get(&stored.id[..8]).unwrap().unwrap();
+        assert_eq!(loaded.query, "What is Rust?");
+        assert_eq!(loaded.answer.as_deref(), Some("A systems language"));
+        assert_eq!(loaded.sources.len(), 1);
+        assert_eq!(loaded.tool_calls[0].query, "What is Rust?");
+        assert_eq!(loaded.input_tokens, 100);
+
+        assert!(history.get("nope").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_list_search_and_delete() {
+        let history = History::in_memory().unwrap();
+        let old = run("Rust async", "Tokio and friends", 1);
+        let new = run("Go generics", "Type parameters", 2);
+        history.record(&old).unwrap();
+        history.record(&new).unwrap();
+
+        let all = history.list(None, None, 10, 0).unwrap();
+        let queries: Vec<_> = all.iter().map(|r| r.query.as_str()).collect();
+        assert_eq!(queries, ["Go generics", "Rust async"]);
+
+        // Matches the report too, and treats % literally
+        assert_eq!(history.list(Some("tokio"), None, 10, 0).unwrap().len(), 1);
+        assert!(history.list(Some("%"), None, 10, 0).unwrap().is_empty());
+        assert_eq!(history.list(None, None, 1, 1).unwrap()[0].id, old.id);
+
+        assert!(history.delete(&old.id).unwrap());
+        assert!(!history.delete(&old.id).unwrap());
+        assert_eq!(history.list(None, None, 10, 0).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_exact_lookups_are_scoped_to_the_client() {
+        let history = History::in_memory().unwrap();
+        let mut mine = run("Rust async", "Tokio and friends", 1);
+        mine.client = Some("ci".to_string());
+        let theirs = run("Go generics", "Type parameters", 2);
+        history.record(&mine).unwrap();
+        history.record(&theirs).unwrap();
+
+        let listed = history.list(None, Some("ci"), 10, 0).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, mine.id);
+
+        // Full ids only, and only the client's own runs
+        assert!(history.get_exact(&mine.id, Some("ci")).unwrap().is_some());
+        assert!(history.get_exact(&mine.id[..8], Some("ci")).unwrap().is_none());
+        assert!(history.get_exact(&theirs.id, Some("ci")).unwrap().is_none());
+        assert!(history.get_exact(&theirs.id, None).unwrap().is_some());
+
+        assert!(!history.delete_exact(&theirs.id, Some("ci")).unwrap());
+        assert!(!history.delete_exact(&mine.id[..8], Some("ci")).unwrap());
+        assert!(history.delete_exact(&mine.id, Some("ci")).unwrap());
+        assert_eq!(history.list(None, None, 10, 0).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_failed_run_keeps_error() {
+        let history = History::in_memory().unwrap();
+        let failed = RunRecord::new(
+            &Config::default(),
+            "q",
+            true,
+            None,
+            Instant::now(),
+            &Err(AgentError::Timeout { secs: 5 }),
+        );
+        history.record(&failed).unwrap();
+
+        let loaded = history.get(&failed.id).unwrap().unwrap();
+        assert_eq!(loaded.status, "timeout");
+        assert!(loaded.answer.is_none());
+        assert!(loaded.error.is_some());
+    }
+}
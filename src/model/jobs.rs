@@ -0,0 +1,418 @@
+// Dimensionless Developments Rust Ai
+// # Jobs Module
+// Tracks the research runs in flight on the web server so it can shut
+// down gracefully.
+//
+// On SIGTERM / Ctrl-C the server:
+// 1. stops accepting new queries (new jobs are refused with `shutting_down`)
+// 2. tells every connected WebSocket client it is going away
+// 3. waits up to `shutdown_grace_secs` for the running jobs to finish
+// 4. writes the jobs that are still running to `<data_dir>/pending-jobs.json`
+//
+// On the next start the pending jobs are run again, and their results can
+// be fetched from `GET /api/jobs/{id}`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::task_tracker::TaskTrackerToken;
+use tokio_util::task::TaskTracker;
+use uuid::Uuid;
+
+use crate::agent::ResearchOutcome;
+use crate::error::AgentError;
+use crate::protocol::QueryOptions;
+
+/// File (inside the data directory) that unfinished jobs are written to.
+pub const PENDING_JOBS_FILE: &str = "pending-jobs.json";
+
+// =============================================================================
+// JOB TYPES
+// =============================================================================
+/// A query that has been accepted but not answered yet.
+///
+/// This is what gets persisted on shutdown, so it holds everything needed
+/// to run the query again - and nothing tied to the old process.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingJob {
+    pub id: String,
+    pub query: String,
+
+    /// Mode, model and other settings the query was submitted with
+    #[serde(default)]
+    pub options: QueryOptions,
+
+    /// Job files written before `options` existed only kept this flag;
+    /// `take_pending` folds it into `options`
+    #[serde(default, rename = "quick", skip_serializing)]
+    legacy_quick: bool,
+
+    /// When the query was first submitted (seconds since the Unix epoch)
+    pub submitted_at: u64,
+
+    /// API key name that submitted the job; only it may look the job up
+    #[serde(default)]
+    pub owner: String,
+
+    /// Connection that submitted the job; meaningless after a restart
+    #[serde(skip)]
+    pub client: Option<String>,
+}
+
+impl PendingJob {
+    pub fn new(query: &str, options: &QueryOptions, owner: &str, client: Option<&str>) -> Self {
+        let submitted_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        Self {
+            id: Uuid::new_v4().to_string(),
+            query: query.to_string(),
+            options: options.clone(),
+            legacy_quick: false,
+            submitted_at,
+            owner: owner.to_string(),
+            client: client.map(str::to_string),
+        }
+    }
+}
+
+/// Body of `GET /api/jobs/{id}`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobStatus {
+    Running { query: String },
+    Done { answer: String },
+    Failed { code: &'static str, message: String },
+}
+
+// =============================================================================
+// JOB REGISTRY
+// =============================================================================
+/// Registry of running jobs, shared by every request handler.
+///
+/// # Rust Concept: Cancellation Tokens and Task Trackers
+///
+/// `CancellationToken` is a cheap, cloneable "stop now" flag that tasks can
+/// `await`. `TaskTracker` counts outstanding work and lets us wait for it
+/// to reach zero. Together they give us "refuse new work, then wait for
+/// the old work" without any hand-written counters.
+pub struct JobRegistry {
+    running: Mutex<HashMap<String, PendingJob>>,
+
+    /// Results of resumed jobs, whose original client is gone, with the
+    /// key that owns them
+    finished: Mutex<HashMap<String, (String, JobStatus)>>,
+
+    tracker: TaskTracker,
+    shutdown: CancellationToken,
+}
+
+impl JobRegistry {
+    pub fn new() -> Self {
+        Self {
+            running: Mutex::new(HashMap::new()),
+            finished: Mutex::new(HashMap::new()),
+            tracker: TaskTracker::new(),
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    /// Register a new job for the API key `owner`, or refuse it if the
+    /// server is shutting down.
+    pub fn start(
+        &self,
+        query: &str,
+        options: &QueryOptions,
+        owner: &str,
+        client: Option<&str>,
+    ) -> Result<JobGuard<'_>, AgentError> {
+        self.register(PendingJob::new(query, options, owner, client), false)
+    }
+
+    /// Register a job persisted by a previous run. Its result is kept so
+    /// it can be fetched from `/api/jobs/{id}`.
+    pub fn resume(&self, job: PendingJob) -> Result<JobGuard<'_>, AgentError> {
+        self.register(job, true)
+    }
+
+    fn register(&self, job: PendingJob, keep_result: bool) -> Result<JobGuard<'_>, AgentError> {
+        if self.is_shutting_down() {
+            return Err(AgentError::ShuttingDown);
+        }
+
+        let (id, owner) = (job.id.clone(), job.owner.clone());
+        if let Ok(mut running) = self.running.lock() {
+            running.insert(id.clone(), job);
+        }
+
+        Ok(JobGuard {
+            registry: self,
+            id,
+            owner,
+            keep_result,
+            _token: self.tracker.token(),
+        })
+    }
+
+    /// Stop accepting jobs and wake everyone waiting in `shutdown_requested`.
+    pub fn begin_shutdown(&self) {
+        self.shutdown.cancel();
+        self.tracker.close();
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
+
+    /// Resolves once `begin_shutdown` has been called.
+    pub async fn shutdown_requested(&self) {
+        self.shutdown.cancelled().await
+    }
+
+    /// Ids of the jobs `owner` is currently running on one connection.
+    pub fn jobs_for_client(&self, client: &str, owner: &str) -> Vec<String> {
+        self.running
+            .lock()
+            .map(|running| {
+                running
+                    .values()
+                    .filter(|job| job.owner == owner && job.client.as_deref() == Some(client))
+                    .map(|job| job.id.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Status of a running or resumed job. Other keys' jobs are `None`,
+    /// as if they didn't exist.
+    pub fn status(&self, id: &str, owner: &str) -> Option<JobStatus> {
+        let running = self.running.lock().ok().and_then(|r| r.get(id).cloned());
+        if let Some(job) = running {
+            return (job.owner == owner).then_some(JobStatus::Running { query: job.query });
+        }
+        self.finished
+            .lock()
+            .ok()
+            .and_then(|f| f.get(id).cloned())
+            .filter(|(job_owner, _)| job_owner == owner)
+            .map(|(_, status)| status)
+    }
+
+    /// Wait up to `grace` for the running jobs to finish, and return the
+    /// ones that didn't.
+    pub async fn drain(&self, grace: Duration) -> Vec<PendingJob> {
+        self.begin_shutdown();
+        let _ = tokio::time::timeout(grace, self.tracker.wait()).await;
+
+        self.running
+            .lock()
+            .map(|running| running.values().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+impl Default for JobRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Keeps a job registered while it runs.
+///
+/// # Rust Concept: RAII Guards
+///
+/// Dropping the guard removes the job from the registry and releases its
+/// tracker token, whether the job finished, failed or its task was
+/// aborted because the client disconnected.
+pub struct JobGuard<'a> {
+    registry: &'a JobRegistry,
+    id: String,
+    owner: String,
+    keep_result: bool,
+    _token: TaskTrackerToken,
+}
+
+impl JobGuard<'_> {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Record the outcome of the job (only kept for resumed jobs).
+    pub fn finish(self, result: &Result<ResearchOutcome, AgentError>) {
+        if !self.keep_result {
+            return;
+        }
+
+        let status = match result {
+            Ok(outcome) => JobStatus::Done {
+                answer: outcome.answer.clone(),
+            },
+            Err(e) => JobStatus::Failed {
+                code: e.code(),
+                message: e.to_string(),
+            },
+        };
+        if let Ok(mut finished) = self.registry.finished.lock() {
+            finished.insert(self.id.clone(), (self.owner.clone(), status));
+        }
+    }
+}
+
+impl Drop for JobGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut running) = self.registry.running.lock() {
+            running.remove(&self.id);
+        }
+    }
+}
+
+// =============================================================================
+// PERSISTENCE
+// =============================================================================
+/// Path of the pending jobs file inside `data_dir`.
+pub fn pending_jobs_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(PENDING_JOBS_FILE)
+}
+
+/// Write unfinished jobs to `path` so the next run can resume them.
+pub fn save_pending(path: &Path, jobs: &[PendingJob]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create data directory {}", dir.display()))?;
+    }
+
+    let json = serde_json::to_string_pretty(jobs).context("Failed to serialize pending jobs")?;
+    std::fs::write(path, json)
+        .with_context(|| format!("Failed to write pending jobs to {}", path.display()))
+}
+
+/// Read and remove the pending jobs file, if there is one.
+///
+/// The file is removed before the jobs run, so a job that crashes the
+/// server isn't resumed over and over.
+pub fn take_pending(path: &Path) -> Result<Vec<PendingJob>> {
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read pending jobs from {}", path.display()))?;
+    std::fs::remove_file(path)
+        .with_context(|| format!("Failed to remove {}", path.display()))?;
+
+    let mut jobs: Vec<PendingJob> = serde_json::from_str(&text)
+        .with_context(|| format!("Invalid pending jobs file {}", path.display()))?;
+    for job in &mut jobs {
+        if job.legacy_quick {
+            job.options = QueryOptions::quick(true);
+        }
+    }
+    Ok(jobs)
+}
+
+// =============================================================================
+// UNIT TESTS
+// =============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::ResearchMode;
+
+    #[test]
+    fn test_jobs_refused_after_shutdown() {
+        let registry = JobRegistry::new();
+        let research = QueryOptions::default();
+        let guard = registry.start("rust news", &research, "ci", Some("conn-1")).unwrap();
+        assert_eq!(registry.jobs_for_client("conn-1", "ci"), vec![guard.id().to_string()]);
+
+        registry.begin_shutdown();
+        let err = registry.start("more news", &research, "ci", None).err().unwrap();
+        assert_eq!(err.code(), "shutting_down");
+
+        drop(guard);
+        assert!(registry.jobs_for_client("conn-1", "ci").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_drain_returns_unfinished_jobs() {
+        let registry = JobRegistry::new();
+        let finished = registry.start("done", &QueryOptions::default(), "ci", None).unwrap();
+        let _running = registry.start("still running", &QueryOptions::quick(true), "ci", None).unwrap();
+        drop(finished);
+
+        let pending = registry.drain(Duration::from_millis(10)).await;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].query, "still running");
+        assert!(pending[0].options.is_quick());
+    }
+
+    #[test]
+    fn test_jobs_are_only_visible_to_their_owner() {
+        let registry = JobRegistry::new();
+        let guard = registry.start("rust news", &QueryOptions::default(), "ci", Some("conn-1")).unwrap();
+        let id = guard.id().to_string();
+
+        assert!(matches!(registry.status(&id, "ci"), Some(JobStatus::Running { .. })));
+        assert!(registry.status(&id, "other").is_none());
+        assert!(registry.jobs_for_client("conn-1", "other").is_empty());
+
+        // Results of resumed jobs too
+        drop(guard);
+        let mut job = PendingJob::new("resumed", &QueryOptions::quick(true), "ci", None);
+        job.id = id.clone();
+        let guard = registry.resume(job).unwrap();
+        guard.finish(&Ok(ResearchOutcome::default()));
+        assert!(matches!(registry.status(&id, "ci"), Some(JobStatus::Done { .. })));
+        assert!(registry.status(&id, "other").is_none());
+    }
+
+    #[test]
+    fn test_pending_jobs_round_trip() {
+        let dir = std::env::temp_dir().join(format!("research-agent-test-{}", Uuid::new_v4()));
+        let path = pending_jobs_path(&dir);
+
+        let options = QueryOptions {
+            mode: ResearchMode::Deep,
+            model: Some("qwen2.5".to_string()),
+            max_results: Some(8),
+            temperature: Some(0.3),
+            documents: vec!["doc-1".to_string()],
+        };
+        let jobs = vec![PendingJob::new("what is rust?", &options, "ci", Some("conn-1"))];
+        save_pending(&path, &jobs).unwrap();
+
+        let loaded = take_pending(&path).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].id, jobs[0].id);
+        assert_eq!(loaded[0].owner, "ci");
+        assert_eq!(loaded[0].options, options);
+        assert_eq!(loaded[0].client, None);
+
+        // The file is consumed
+        assert!(take_pending(&path).unwrap().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_old_pending_jobs_keep_their_mode() {
+        let dir = std::env::temp_dir().join(format!("research-agent-test-{}", Uuid::new_v4()));
+        let path = pending_jobs_path(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            &path,
+            r#"[{ "id": "1", "query": "rust news", "quick": true, "submitted_at": 0, "owner": "ci" }]"#,
+        )
+        .unwrap();
+
+        let loaded = take_pending(&path).unwrap();
+        assert!(loaded[0].options.is_quick());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
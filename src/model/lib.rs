@@ -6,6 +6,7 @@
 pub mod app;
 pub mod components;
 pub mod model;
+pub mod protocol;
 
 use leptos::*;
 use wasm_bindgen::prelude::*;
@@ -22,6 +22,10 @@
 #[cfg(not(target_arch = "wasm32"))]
 mod config;
 
+/// API keys, scopes and quotas for the web server
+#[cfg(not(target_arch = "wasm32"))]
+mod auth;
+
 /// Research agent implementation
 #[cfg(not(target_arch = "wasm32"))]
 mod agent;
@@ -34,29 +38,114 @@ mod tools;
 #[cfg(not(target_arch = "wasm32"))]
 mod server;
 
+/// Typed errors, exit codes and diagnostics
+#[cfg(not(target_arch = "wasm32"))]
+mod error;
+
+/// Messages exchanged with the web frontend
+#[cfg(not(target_arch = "wasm32"))]
+mod protocol;
+
+/// Prometheus metrics
+#[cfg(not(target_arch = "wasm32"))]
+mod monitoring;
+
+/// Liveness and readiness checks
+#[cfg(not(target_arch = "wasm32"))]
+mod health;
+
+/// In-flight job tracking for graceful shutdown
+#[cfg(not(target_arch = "wasm32"))]
+mod jobs;
+
+/// Past research runs, stored in SQLite
+#[cfg(not(target_arch = "wasm32"))]
+mod history;
+
+/// Cached favicons for the chat's source cards
+#[cfg(not(target_arch = "wasm32"))]
+mod favicons;
+
+/// Documents attached in the chat, searchable by the agent
+#[cfg(not(target_arch = "wasm32"))]
+mod documents;
+
+/// Per-IP WebSocket connection limits
+#[cfg(not(target_arch = "wasm32"))]
+mod connections;
+
+/// Chat sessions that survive WebSocket reconnects
+#[cfg(not(target_arch = "wasm32"))]
+mod sessions;
+
+/// Model Context Protocol server (stdio and HTTP)
+#[cfg(not(target_arch = "wasm32"))]
+mod mcp;
+
+/// Fair, bounded queue for research runs
+#[cfg(not(target_arch = "wasm32"))]
+mod scheduler;
+
+/// Web UI embedded in the binary (optional)
+#[cfg(all(not(target_arch = "wasm32"), feature = "embed-frontend"))]
+mod assets;
+
+/// OpenTelemetry trace export (optional)
+#[cfg(all(not(target_arch = "wasm32"), feature = "otel"))]
+mod telemetry;
+
 // =============================================================================
 // IMPORTS
 // =============================================================================
+#[cfg(not(target_arch = "wasm32"))]
+use std::future::IntoFuture;
+#[cfg(not(target_arch = "wasm32"))]
+use std::net::{IpAddr, SocketAddr};
+#[cfg(not(target_arch = "wasm32"))]
+use std::process::ExitCode;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
+
 #[cfg(not(target_arch = "wasm32"))]
 use anyhow::Result;
 #[cfg(not(target_arch = "wasm32"))]
-use clap::Parser;
+use clap::{Parser, Subcommand};
+#[cfg(not(target_arch = "wasm32"))]
+use tracing::{error, info};
 #[cfg(not(target_arch = "wasm32"))]
-use tracing::{error, info, Level};
+use tracing_appender::non_blocking::WorkerGuard;
 #[cfg(not(target_arch = "wasm32"))]
-use tracing_subscriber::FmtSubscriber;
+use tracing_subscriber::{filter::LevelFilter, fmt, fmt::writer::BoxMakeWriter, prelude::*, EnvFilter};
 
 #[cfg(not(target_arch = "wasm32"))]
 use crate::agent::ResearchAgent;
 #[cfg(not(target_arch = "wasm32"))]
-use crate::config::Config;
+use crate::config::{Config, LogFormat, ValueSource};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::error::AgentError;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::history::{History, RunRecord};
 
 // =============================================================================
 // MAIN - Only compile for non-WASM targets
 // =============================================================================
+/// # Rust Concept: ExitCode
+///
+/// Returning `ExitCode` from main lets us choose the process exit status.
+/// Scripts can check `$?` to see *why* a run failed (see `AgentError` for
+/// the table of codes) instead of parsing the error message.
 #[cfg(not(target_arch = "wasm32"))]
-fn main() -> Result<()> {
-    tokio::runtime::Runtime::new()?.block_on(async_main())
+fn main() -> ExitCode {
+    let result = tokio::runtime::Runtime::new()
+        .map_err(anyhow::Error::from)
+        .and_then(|runtime| runtime.block_on(async_main()));
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => ExitCode::from(report_error(&e)),
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -105,9 +194,26 @@ EXAMPLES:
   
   # Use a specific model
   ai-research-agent --model deepseek-v3.2 "Machine learning in Rust"
+
+  # Use a profile from research-agent.toml
+  ai-research-agent --profile deep "Machine learning in Rust"
+
+  # Show the resolved configuration and where each value came from
+  ai-research-agent config show --resolved
+
+  # Browse past runs
+  ai-research-agent history list
+  ai-research-agent history show 3f2a9c1e
+
+  # Serve the tools to an MCP client (Claude Desktop, editors) over stdio
+  ai-research-agent mcp
 "#
 )]
 struct Args {
+    /// Subcommand (e.g. `config show`, `history list`); omit to run a research query
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// The research topic or question to investigate
     #[arg(
         help = "The topic to research",
@@ -132,15 +238,47 @@ struct Args {
         default_value = "8080"
     )]
     port: u16,
+
+    /// Address for web server to listen on (overrides BIND_ADDRESS env var)
+    #[arg(
+        long = "bind",
+        help = "Address for web server to listen on (e.g. 127.0.0.1)",
+        value_name = "ADDR"
+    )]
+    bind: Option<String>,
     
     /// The Ollama model to use (overrides OLLAMA_MODEL env var)
     #[arg(
         short = 'm',
         long = "model",
         help = "Ollama model to use",
-        env = "OLLAMA_MODEL"
+        global = true
     )]
     model: Option<String>,
+
+    /// Named profile from the config file (overrides RESEARCH_AGENT_PROFILE)
+    #[arg(
+        long = "profile",
+        help = "Config file profile to use (e.g. fast, deep)",
+        global = true
+    )]
+    profile: Option<String>,
+
+    /// Sampling temperature (overrides TEMPERATURE env var)
+    #[arg(
+        long = "temperature",
+        help = "LLM temperature (0.0 - 2.0)",
+        global = true
+    )]
+    temperature: Option<f32>,
+
+    /// Number of search results to use (overrides MAX_SEARCH_RESULTS env var)
+    #[arg(
+        long = "max-results",
+        help = "Maximum number of search results",
+        global = true
+    )]
+    max_results: Option<usize>,
     
     /// Quick search mode - just search, don't synthesize
     #[arg(
@@ -156,9 +294,88 @@ struct Args {
         short = 'v',
         long = "verbose",
         help = "Enable verbose/debug logging",
-        default_value = "false"
+        default_value = "false",
+        global = true
     )]
     verbose: bool,
+
+    /// Console log format (overrides LOG_FORMAT env var)
+    #[arg(
+        long = "log-format",
+        help = "Log format: text or json",
+        value_name = "FORMAT",
+        global = true
+    )]
+    log_format: Option<LogFormat>,
+
+    /// Directory for rotating log files (overrides LOG_DIR env var)
+    #[arg(
+        long = "log-dir",
+        help = "Write daily-rotated JSON log files to this directory",
+        value_name = "DIR",
+        global = true
+    )]
+    log_dir: Option<std::path::PathBuf>,
+}
+
+/// Subcommands that don't run a research query.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inspect the layered configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Browse, search and delete past research runs
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
+
+    /// Serve the search and research tools over MCP on stdin/stdout
+    Mcp,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// Print the effective configuration
+    Show {
+        /// Also print where each value came from (file, profile, env, flag)
+        #[arg(long = "resolved", default_value = "false")]
+        resolved: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryAction {
+    /// List recent runs, newest first
+    List {
+        /// How many runs to show
+        #[arg(long = "limit", default_value = "20")]
+        limit: usize,
+    },
+
+    /// Print a run's report, sources and tool calls
+    Show {
+        /// Run id, or the first characters of it
+        id: String,
+    },
+
+    /// Find runs whose query or report contains TEXT
+    Search {
+        text: String,
+
+        /// How many runs to show
+        #[arg(long = "limit", default_value = "20")]
+        limit: usize,
+    },
+
+    /// Delete a run
+    Delete {
+        /// Run id, or the first characters of it
+        id: String,
+    },
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -166,18 +383,33 @@ async fn async_main() -> Result<()> {
     // Parse command-line arguments
     let args = Args::parse();
     
-    // Initialize logging
-    init_logging(args.verbose)?;
+    // Load configuration: defaults < config files < profile < env
+    let mut config = Config::load(args.profile.as_deref())
+        .map_err(|e| AgentError::InvalidConfig(format!("{:#}", e)))?;
+    
+    // CLI flags have the final say
+    let overrides = apply_cli_overrides(&mut config, &args);
+    
+    // Initialize logging now that we know the level, format and log directory.
+    // The guard flushes buffered file logs when it is dropped at the end of main.
+    // In MCP mode stdout carries the protocol, so logs go to stderr.
+    let mcp = matches!(args.command, Some(Command::Mcp));
+    let _log_guard = init_logging(&config, args.verbose, mcp)?;
     
     info!("AI Research Agent starting up...");
     
-    // Load configuration from environment/.env file
-    let mut config = Config::from_env()?;
+    // Logged only now: before init_logging there is no subscriber
+    if !overrides.is_empty() {
+        info!(model = %config.model, flags = ?overrides, "Settings from command line");
+    }
     
-    // Override model if specified on command line
-    if let Some(model) = args.model {
-        info!(model = %model, "Using model from command line");
-        config.model = model;
+    if let Some(Command::Config { action: ConfigAction::Show { resolved } }) = &args.command {
+        print_config(&config, *resolved);
+        return Ok(());
+    }
+
+    if let Some(Command::History { action }) = &args.command {
+        return run_history_command(&config, action);
     }
     
     // Validate configuration
@@ -189,10 +421,14 @@ async fn async_main() -> Result<()> {
         "Configuration loaded"
     );
     
+    if mcp {
+        return mcp::serve_stdio(config).await;
+    }
+    
     // Check if web server mode
     if args.web {
         info!(port = args.port, "Starting web server mode");
-        return start_web_server(args.port).await;
+        return start_web_server(config, args.port).await;
     }
     
     // CLI mode - require query
@@ -201,96 +437,523 @@ async fn async_main() -> Result<()> {
     })?;
     
     // Create the research agent
-    let agent = ResearchAgent::new(config);
+    let agent = ResearchAgent::new(config.clone());
     
     // Execute the query
+    let started = Instant::now();
     let result = if args.quick {
         // Quick mode: just search, no synthesis
         info!("Running in quick search mode");
-        agent.quick_search(&query).await
+        agent.quick_search_detailed(&query).await
     } else {
         // Full mode: search + AI synthesis
         info!("Running full research mode");
-        agent.research(&query).await
+        agent.research_detailed(&query).await
     };
+
+    // Failed runs are kept too; a history problem never fails the run
+    if config.save_history {
+        let run = RunRecord::new(&config, &query, args.quick, Some("cli"), started, &result);
+        if let Err(e) = open_history(&config).and_then(|history| history.record(&run)) {
+            tracing::warn!("Could not save run to history: {:#}", e);
+        }
+    }
     
-    // Handle the result
-    match result {
-        Ok(response) => {
-            // Print the result to stdout
-            println!("\n{}", "=".repeat(60));
-            println!("RESEARCH RESULTS");
-            println!("{}\n", "=".repeat(60));
-            println!("{}", response);
-            println!("\n{}", "=".repeat(60));
+    // Handle the result - errors are reported by main() with a tip and exit code
+    let response = result?.answer;
+    
+    // Print the result to stdout
+    println!("\n{}", "=".repeat(60));
+    println!("RESEARCH RESULTS");
+    println!("{}\n", "=".repeat(60));
+    println!("{}", response);
+    println!("\n{}", "=".repeat(60));
+    
+    info!("Research completed successfully");
+    Ok(())
+}
+
+/// Print a user-friendly error message and return the exit code to use.
+///
+/// Typed `AgentError`s carry their own exit code and tip; anything else
+/// (e.g. a failure to bind the web server port) exits with 1.
+#[cfg(not(target_arch = "wasm32"))]
+fn report_error(e: &anyhow::Error) -> u8 {
+    error!(error = %e, "Research agent failed");
+    eprintln!("\n❌ {:#}", e);
+    
+    match e.downcast_ref::<AgentError>() {
+        Some(agent_error) => {
+            if let Some(hint) = agent_error.hint() {
+                eprintln!("\n💡 Tip: {}", hint);
+            }
+            agent_error.exit_code()
+        }
+        None => 1,
+    }
+}
+
+/// Apply command-line flags on top of the loaded configuration, and return
+/// the flags that were given.
+#[cfg(not(target_arch = "wasm32"))]
+fn apply_cli_overrides(config: &mut Config, args: &Args) -> Vec<&'static str> {
+    let mut applied = Vec::new();
+    let mut set = |config: &mut Config, key: &'static str, flag: &'static str| {
+        config.sources.set(key, ValueSource::Cli(flag));
+        applied.push(flag);
+    };
+
+    if let Some(model) = &args.model {
+        config.model = model.clone();
+        set(config, "model", "--model");
+    }
+
+    if let Some(temperature) = args.temperature {
+        config.temperature = temperature;
+        set(config, "temperature", "--temperature");
+    }
+
+    if let Some(max_results) = args.max_results {
+        config.max_search_results = max_results;
+        set(config, "max_search_results", "--max-results");
+    }
+
+    if let Some(log_format) = args.log_format {
+        config.log_format = log_format;
+        set(config, "log_format", "--log-format");
+    }
+
+    if let Some(log_dir) = &args.log_dir {
+        config.log_dir = Some(log_dir.clone());
+        set(config, "log_dir", "--log-dir");
+    }
+
+    if let Some(bind) = &args.bind {
+        config.bind_address = bind.clone();
+        set(config, "bind_address", "--bind");
+    }
+
+    applied
+}
+
+/// Print the effective configuration for `config show`.
+#[cfg(not(target_arch = "wasm32"))]
+fn print_config(config: &Config, resolved: bool) {
+    if let Some(profile) = &config.profile {
+        println!("# profile: {}", profile);
+    }
+
+    for (key, value) in config.entries() {
+        if resolved {
+            println!("{:<20} = {:<30} # {}", key, value, config.sources.get(key));
+        } else {
+            println!("{:<20} = {}", key, value);
+        }
+    }
+}
+
+/// Open the history database in the configured data directory.
+#[cfg(not(target_arch = "wasm32"))]
+fn open_history(config: &Config) -> Result<History> {
+    History::open(&history::history_db_path(&config.data_dir))
+}
+
+/// Run a `history ...` subcommand.
+#[cfg(not(target_arch = "wasm32"))]
+fn run_history_command(config: &Config, action: &HistoryAction) -> Result<()> {
+    let history = open_history(config)?;
+
+    match action {
+        HistoryAction::List { limit } => print_runs(&history.list(None, None, *limit, 0)?),
+        HistoryAction::Search { text, limit } => {
+            print_runs(&history.list(Some(text), None, *limit, 0)?)
+        }
+        HistoryAction::Show { id } => {
+            let run = history
+                .get(id)?
+                .ok_or_else(|| anyhow::anyhow!("No run with id '{}' in the history", id))?;
+            print_run(&run);
         }
-        Err(e) => {
-            // Print a user-friendly error message
-            error!(error = %e, "Research failed");
-            
-            // Give helpful suggestions based on common errors
-            eprintln!("\n❌ Research failed: {}", e);
-            
-            if e.to_string().contains("connection refused") {
-                eprintln!("\n💡 Tip: Make sure Ollama is running:");
-                eprintln!("   ollama serve");
-            } else if e.to_string().contains("model") {
-                eprintln!("\n💡 Tip: Make sure the model is installed:");
-                eprintln!("   ollama pull llama3.2");
+        HistoryAction::Delete { id } => {
+            if !history.delete(id)? {
+                anyhow::bail!("No run with id '{}' in the history", id);
             }
-            
-            // Return the error to set non-zero exit code
-            return Err(e);
+            println!("Deleted {}", id);
         }
     }
-    
-    info!("Research completed successfully");
     Ok(())
 }
 
+/// Print one line per run for `history list` / `history search`.
+#[cfg(not(target_arch = "wasm32"))]
+fn print_runs(runs: &[history::RunSummary]) {
+    if runs.is_empty() {
+        println!("No runs found.");
+        return;
+    }
+
+    for run in runs {
+        let mode = if run.quick { "quick" } else { "research" };
+        println!(
+            "{}  {}  {:<8}  {:<10}  {}",
+            &run.id[..8],
+            format_timestamp(run.started_at),
+            mode,
+            run.status,
+            run.query
+        );
+    }
+}
+
+/// Print a whole run for `history show`.
+#[cfg(not(target_arch = "wasm32"))]
+fn print_run(run: &RunRecord) {
+    println!("id:       {}", run.id);
+    println!("query:    {}", run.query);
+    println!("started:  {} ({} ms)", format_timestamp(run.started_at), run.duration_ms);
+    println!(
+        "options:  model={} temperature={} max_results={}{}",
+        run.model,
+        run.temperature,
+        run.max_results,
+        if run.quick { " --quick" } else { "" }
+    );
+    println!("status:   {}", run.status);
+    if !run.quick {
+        println!(
+            "usage:    {} rounds, {} input / {} output tokens",
+            run.rounds, run.input_tokens, run.output_tokens
+        );
+    }
+
+    if !run.tool_calls.is_empty() {
+        println!("\nTool calls:");
+        for call in &run.tool_calls {
+            match &call.error {
+                Some(error) => println!("  {}(\"{}\") failed: {}", call.tool, call.query, error),
+                None => println!(
+                    "  {}(\"{}\") -> {} results",
+                    call.tool,
+                    call.query,
+                    call.results.len()
+                ),
+            }
+        }
+    }
+
+    if !run.sources.is_empty() {
+        println!("\nSources:");
+        for (i, source) in run.sources.iter().enumerate() {
+            println!("  [{}] {} - {}", i + 1, source.title, source.url);
+        }
+    }
+
+    println!("\n{}", "=".repeat(60));
+    match (&run.answer, &run.error) {
+        (Some(answer), _) => println!("{}", answer),
+        (None, Some(error)) => println!("❌ {}", error),
+        (None, None) => {}
+    }
+}
+
+/// `2024-05-01 14:03 UTC` from seconds since the Unix epoch.
+#[cfg(not(target_arch = "wasm32"))]
+fn format_timestamp(secs: u64) -> String {
+    chrono::DateTime::from_timestamp(secs as i64, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default()
+}
+
 /// Start the web server
+///
+/// On SIGTERM or Ctrl-C the server stops taking new queries, lets running
+/// research finish for up to `shutdown_grace_secs`, and saves whatever is
+/// still running so the next start can resume it.
 #[cfg(not(target_arch = "wasm32"))]
-async fn start_web_server(port: u16) -> Result<()> {
-    let app = server::create_router();
-    let addr = format!("0.0.0.0:{}", port);
-    
-    info!("🚀 Server starting at http://localhost:{}", port);
-    info!("📱 Open http://localhost:{} in your browser", port);
-    
-    let listener = tokio::net::TcpListener::bind(&addr)
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to bind to {}: {}", addr, e))?;
-    
-    axum::serve(listener, app)
-        .await
-        .map_err(|e| anyhow::anyhow!("Server error: {}", e))?;
+async fn start_web_server(config: Config, port: u16) -> Result<()> {
+    let grace = Duration::from_secs(config.shutdown_grace_secs);
+    let pending_path = jobs::pending_jobs_path(&config.data_dir);
+
+    // Validated by Config::validate
+    let ip: IpAddr = config.bind_address.parse().map_err(|_| {
+        AgentError::InvalidConfig(format!("Bad bind address {}", config.bind_address))
+    })?;
+    let addr = SocketAddr::new(ip, port);
+    let tls = config.tls_cert.clone().zip(config.tls_key.clone());
+
+    let state = Arc::new(server::AppState::new(config)?);
+
+    if !state.auth.is_enabled() {
+        tracing::warn!("No api_keys configured: anyone who can reach this server can run queries");
+    }
+
+    // Pick up the jobs the previous run didn't get to finish
+    match jobs::take_pending(&pending_path) {
+        Ok(pending) if !pending.is_empty() => {
+            info!(count = pending.len(), "Resuming unfinished jobs from the last run");
+            server::resume_jobs(state.clone(), pending);
+        }
+        Ok(_) => {}
+        Err(e) => error!("Could not load unfinished jobs: {:#}", e),
+    }
+
+    let app = server::create_router(state.clone());
+    let scheme = if tls.is_some() { "https" } else { "http" };
     
+    info!("🚀 Server starting at {}://{}", scheme, addr);
+    info!("📱 Open {}://localhost:{} in your browser", scheme, port);
+
+    let signal_state = state.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutdown requested, draining in-flight research");
+        signal_state.jobs.begin_shutdown();
+    });
+
+    // Stop accepting connections as soon as shutdown starts
+    let mut server = match tls {
+        Some((cert, key)) => serve_tls(app, addr, &cert, &key, state.clone()).await?,
+        None => {
+            let listener = tokio::net::TcpListener::bind(addr)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to bind to {}: {}", addr, e))?;
+
+            let serve_state = state.clone();
+            // Handlers see the client address (for per-IP connection caps)
+            let app = app.into_make_service_with_connect_info::<SocketAddr>();
+            tokio::spawn(
+                axum::serve(listener, app)
+                    .with_graceful_shutdown(async move {
+                        serve_state.jobs.shutdown_requested().await
+                    })
+                    .into_future(),
+            )
+        }
+    };
+
+    tokio::select! {
+        result = &mut server => {
+            result
+                .map_err(|e| anyhow::anyhow!("Server task failed: {}", e))?
+                .map_err(|e| anyhow::anyhow!("Server error: {}", e))?;
+            return Ok(());
+        }
+        _ = state.jobs.shutdown_requested() => {}
+    }
+
+    let unfinished = state.jobs.drain(grace).await;
+    if unfinished.is_empty() {
+        info!("All running jobs finished");
+    } else {
+        tracing::warn!(
+            count = unfinished.len(),
+            path = %pending_path.display(),
+            "Grace period over, saving unfinished jobs to resume on next start"
+        );
+        if let Err(e) = jobs::save_pending(&pending_path, &unfinished) {
+            error!("Could not save unfinished jobs: {:#}", e);
+        }
+    }
+
+    // WebSocket connections outlive graceful shutdown; close them now
+    server.abort();
+
     Ok(())
 }
 
+/// Serve HTTPS with rustls, using PEM `cert` and `key` files.
+#[cfg(all(not(target_arch = "wasm32"), feature = "tls"))]
+async fn serve_tls(
+    app: axum::Router,
+    addr: SocketAddr,
+    cert: &std::path::Path,
+    key: &std::path::Path,
+    state: Arc<server::AppState>,
+) -> Result<tokio::task::JoinHandle<std::io::Result<()>>> {
+    let tls = axum_server::tls_rustls::RustlsConfig::from_pem_file(cert, key)
+        .await
+        .map_err(|e| {
+            AgentError::InvalidConfig(format!(
+                "Cannot load TLS certificate {} / key {}: {}",
+                cert.display(),
+                key.display(),
+                e
+            ))
+        })?;
+
+    // axum-server shuts down through a handle instead of a future
+    let handle = axum_server::Handle::new();
+    let shutdown_handle = handle.clone();
+    tokio::spawn(async move {
+        state.jobs.shutdown_requested().await;
+        shutdown_handle.graceful_shutdown(None);
+    });
+
+    Ok(tokio::spawn(
+        axum_server::bind_rustls(addr, tls)
+            .handle(handle)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>()),
+    ))
+}
+
+/// Without the `tls` feature there is no rustls to serve HTTPS with.
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "tls")))]
+async fn serve_tls(
+    _app: axum::Router,
+    _addr: SocketAddr,
+    _cert: &std::path::Path,
+    _key: &std::path::Path,
+    _state: Arc<server::AppState>,
+) -> Result<tokio::task::JoinHandle<std::io::Result<()>>> {
+    Err(AgentError::InvalidConfig(
+        "tls_cert/tls_key are set but this binary was built without the `tls` feature \
+         (rebuild with `cargo build --features tls`)"
+            .to_string(),
+    )
+    .into())
+}
+
+/// Resolve when the process is asked to stop: Ctrl-C, or SIGTERM on Unix
+/// (what Docker, systemd and Kubernetes send).
 #[cfg(not(target_arch = "wasm32"))]
-fn init_logging(verbose: bool) -> Result<()> {
-    // Set log level based on verbose flag
-    let level = if verbose { Level::DEBUG } else { Level::INFO };
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+/// Initialize the global tracing subscriber.
+///
+/// - The filter comes from `Config::log_level` (i.e. `RUST_LOG`), so per-module
+///   directives like `info,ai_research_agent::tools=debug` work.
+/// - `--verbose` raises the default level to DEBUG but keeps those directives.
+/// - Console output is text or JSON (`--log-format`), on stdout or, with
+///   `stderr` (MCP mode, where stdout carries the protocol), on stderr.
+/// - With `--log-dir`, JSON lines are also written to a daily-rotated file.
+///
+/// - With the `otel` feature and `OTEL_EXPORTER_OTLP_ENDPOINT`, spans are
+///   also exported to an OpenTelemetry collector.
+///
+/// Returns guards which must be kept alive until exit.
+#[cfg(not(target_arch = "wasm32"))]
+fn init_logging(config: &Config, verbose: bool, stderr: bool) -> Result<LogGuards> {
+    let mut filter = EnvFilter::try_new(&config.log_level).map_err(|e| {
+        AgentError::InvalidConfig(format!("Invalid log level '{}': {}", config.log_level, e))
+    })?;
+    if verbose {
+        filter = filter.add_directive(LevelFilter::DEBUG.into());
+    }
+    
+    // # Rust Concept: Option as a Layer
+    // tracing-subscriber implements Layer for Option<L>, so a `None` layer
+    // is simply skipped. This avoids boxing differently-typed layers.
+    let json = config.log_format == LogFormat::Json;
+    let console = || {
+        if stderr {
+            BoxMakeWriter::new(std::io::stderr)
+        } else {
+            BoxMakeWriter::new(std::io::stdout)
+        }
+    };
+    let text_layer = (!json).then(|| {
+        fmt::layer()
+            .with_writer(console())
+            .with_target(true)  // Show the module that logged
+            .with_thread_names(false)
+            .with_file(false)
+            .with_line_number(false)
+    });
+    let json_layer = json.then(|| {
+        fmt::layer()
+            .with_writer(console())
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+    });
+    
+    let (file_layer, guard) = match &config.log_dir {
+        Some(dir) => {
+            let appender = tracing_appender::rolling::daily(dir, "ai-research-agent.log");
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let layer = fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(true)
+                .with_writer(writer);
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+    
+    #[cfg(feature = "otel")]
+    let (otel_layer, otel_guard) = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            let (layer, guard) = telemetry::otlp_layer(endpoint)?;
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+    #[cfg(not(feature = "otel"))]
+    let otel_layer: Option<tracing_subscriber::layer::Identity> = None;
     
     // Build the subscriber
     // 
     // # Rust Concept: Builder Pattern
     // Many Rust libraries use builders for configuration.
     // Each method modifies the builder and returns it for chaining.
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(level)
-        .with_target(true)  // Show the module that logged
-        .with_thread_names(false)
-        .with_file(false)
-        .with_line_number(false)
-        .finish();
-    
-    // Set as the global default
-    tracing::subscriber::set_global_default(subscriber)
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(text_layer)
+        .with(json_layer)
+        .with(file_layer)
+        .with(otel_layer)
+        .try_init()
         .map_err(|e| anyhow::anyhow!("Failed to set logging subscriber: {}", e))?;
     
-    Ok(())
+    #[cfg(not(feature = "otel"))]
+    if config.otlp_endpoint.is_some() {
+        tracing::warn!("otlp_endpoint is set but this binary was built without the `otel` feature");
+    }
+    
+    Ok(LogGuards {
+        _file: guard,
+        #[cfg(feature = "otel")]
+        _otel: otel_guard,
+    })
+}
+
+/// Keeps background log writers alive; dropping it flushes them.
+#[cfg(not(target_arch = "wasm32"))]
+struct LogGuards {
+    _file: Option<WorkerGuard>,
+    #[cfg(feature = "otel")]
+    _otel: Option<telemetry::TelemetryGuard>,
 }
 
 // =============================================================================
@@ -328,4 +991,64 @@ mod tests {
         assert!(args.verbose);
         assert_eq!(args.model, Some("llama3.2".to_string()));
     }
+    
+    #[test]
+    fn test_log_flags() {
+        let args = Args::parse_from(["test", "--log-format", "json", "--log-dir", "logs", "q"]);
+        let mut config = Config::default();
+        apply_cli_overrides(&mut config, &args);
+        
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.log_dir, Some(std::path::PathBuf::from("logs")));
+    }
+    
+    #[test]
+    fn test_config_show_subcommand() {
+        let args = Args::parse_from(["test", "--profile", "deep", "config", "show", "--resolved"]);
+        
+        assert_eq!(args.profile, Some("deep".to_string()));
+        assert!(matches!(
+            args.command,
+            Some(Command::Config { action: ConfigAction::Show { resolved: true } })
+        ));
+    }
+    
+    #[test]
+    fn test_cli_overrides_record_source() {
+        let args = Args::parse_from(["test", "--model", "mistral", "--max-results", "8", "q"]);
+        let mut config = Config::default();
+        let applied = apply_cli_overrides(&mut config, &args);
+        
+        assert_eq!(applied, vec!["--model", "--max-results"]);
+        assert_eq!(config.model, "mistral");
+        assert_eq!(config.max_search_results, 8);
+        assert_eq!(config.sources.get("model"), ValueSource::Cli("--model"));
+    }
+
+    #[test]
+    fn test_history_subcommand() {
+        let args = Args::parse_from(["test", "history", "search", "tokio", "--limit", "5"]);
+
+        assert!(matches!(
+            args.command,
+            Some(Command::History { action: HistoryAction::Search { ref text, limit: 5 } })
+                if text == "tokio"
+        ));
+    }
+
+    #[test]
+    fn test_mcp_subcommand() {
+        let args = Args::parse_from(["test", "--profile", "fast", "mcp"]);
+        assert!(matches!(args.command, Some(Command::Mcp)));
+    }
+
+    #[test]
+    fn test_bind_flag() {
+        let args = Args::parse_from(["test", "--web", "--bind", "127.0.0.1"]);
+        let mut config = Config::default();
+        apply_cli_overrides(&mut config, &args);
+
+        assert_eq!(config.bind_address, "127.0.0.1");
+        assert_eq!(config.sources.get("bind_address"), ValueSource::Cli("--bind"));
+    }
 }
\ No newline at end of file
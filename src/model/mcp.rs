@@ -0,0 +1,422 @@
+// Dimensionless Developments Rust Ai
+// # MCP Module
+// Exposes the agent's tools over the Model Context Protocol, so editors and
+// other assistants can call this agent as a tool.
+//
+// MCP is JSON-RPC 2.0. Two transports are supported:
+// - stdio: `ai-research-agent mcp` reads one request per line on stdin and
+//   writes one response per line on stdout (logs go to stderr)
+// - HTTP: in web mode, `POST /mcp` takes one request and returns its response
+//
+// Tools offered:
+//
+// | Tool           | What it does                                  | Scope    |
+// |----------------|-----------------------------------------------|----------|
+// | `web_search`   | DuckDuckGo search, formatted results          | search   |
+// | `fetch_page`   | Readable text of a web page                   | search   |
+// | `quick_search` | Same as the CLI's `--quick`                   | search   |
+// | `research`     | Full research run with a synthesized report   | research |
+//
+// The input schemas come from each tool's `Tool::definition`, the same
+// definitions the LLM sees.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::agent::ResearchAgent;
+use crate::auth::{Principal, Scope};
+use crate::config::Config;
+use crate::protocol::QueryOptions;
+use crate::server::AppState;
+use crate::tools::{FetchArgs, FetchPageTool, SearchArgs, WebSearchTool};
+
+/// MCP protocol revision we implement.
+pub const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// Longest page text `fetch_page` returns, in characters.
+const FETCH_MAX_CHARS: usize = 20_000;
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// A JSON-RPC request or notification (notifications have no `id`).
+#[derive(Debug, Deserialize)]
+struct Request {
+    jsonrpc: String,
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// A JSON-RPC error, before it is wrapped in a response.
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+// =============================================================================
+// SERVER
+// =============================================================================
+/// How `quick_search` and `research` run.
+enum Backend {
+    /// Standalone (stdio): run the agent directly
+    Local,
+
+    /// Inside the web server: go through its queue, history and API keys
+    Web {
+        state: Arc<AppState>,
+        principal: Principal,
+    },
+}
+
+/// Answers MCP requests.
+pub struct McpServer {
+    config: Config,
+    search: WebSearchTool,
+    fetch: FetchPageTool,
+    backend: Backend,
+}
+
+impl McpServer {
+    /// A standalone server, for the stdio transport.
+    pub fn new(config: Config) -> Self {
+        Self::with_backend(config, Backend::Local)
+    }
+
+    /// A server answering for `principal` inside the web server.
+    pub fn for_web(state: Arc<AppState>, principal: Principal) -> Self {
+        let config = state.config.clone();
+        Self::with_backend(config, Backend::Web { state, principal })
+    }
+
+    fn with_backend(config: Config, backend: Backend) -> Self {
+        Self {
+            search: WebSearchTool::new(config.max_search_results),
+            fetch: FetchPageTool::new(FETCH_MAX_CHARS),
+            config,
+            backend,
+        }
+    }
+
+    /// Handle one JSON-RPC message. Returns the response to send, or
+    /// `None` for notifications.
+    pub async fn handle(&self, message: &str) -> Option<String> {
+        // Malformed JSON and well-formed JSON that isn't a request are
+        // different errors
+        let request: Request = match serde_json::from_str::<Value>(message) {
+            Err(e) => {
+                let error = RpcError::new(PARSE_ERROR, e.to_string());
+                return Some(error_response(Value::Null, error));
+            }
+            Ok(value) => match serde_json::from_value(value) {
+                Ok(request) => request,
+                Err(e) => {
+                    let error = RpcError::new(INVALID_REQUEST, e.to_string());
+                    return Some(error_response(Value::Null, error));
+                }
+            },
+        };
+
+        if request.jsonrpc != "2.0" {
+            let id = request.id.unwrap_or(Value::Null);
+            let error = RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\"");
+            return Some(error_response(id, error));
+        }
+
+        // Notifications (`notifications/initialized`, cancellations) need no answer
+        let Some(id) = request.id else {
+            debug!(method = %request.method, "MCP notification");
+            return None;
+        };
+
+        let response = match self.dispatch(&request.method, request.params).await {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string(),
+            Err(error) => error_response(id, error),
+        };
+        Some(response)
+    }
+
+    async fn dispatch(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "initialize" => Ok(json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": { "tools": {} },
+                "serverInfo": {
+                    "name": "ai-research-agent",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            })),
+            "ping" => Ok(json!({})),
+            "tools/list" => {
+                let tools: Vec<Value> = self
+                    .definitions()
+                    .await
+                    .into_iter()
+                    .map(|def| {
+                        json!({
+                            "name": def.name,
+                            "description": def.description,
+                            "inputSchema": def.parameters,
+                        })
+                    })
+                    .collect();
+                Ok(json!({ "tools": tools }))
+            }
+            "tools/call" => {
+                let name = params.get("name").and_then(Value::as_str).unwrap_or_default();
+                let arguments = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+
+                // Tool failures are results the calling model should see,
+                // not protocol errors
+                let (text, is_error) = match self.call_tool(name, arguments).await? {
+                    Ok(text) => (text, false),
+                    Err(message) => (message, true),
+                };
+                Ok(json!({
+                    "content": [{ "type": "text", "text": text }],
+                    "isError": is_error,
+                }))
+            }
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        }
+    }
+
+    /// Definitions of every tool we offer.
+    ///
+    /// `quick_search` and `research` take the same `{query}` argument as
+    /// `web_search`, so they reuse its schema.
+    async fn definitions(&self) -> Vec<ToolDefinition> {
+        let search = self.search.definition(String::new()).await;
+        let quick = ToolDefinition {
+            name: "quick_search".to_string(),
+            description: "Search the web and return the top results as a markdown list, \
+                          without LLM synthesis."
+                .to_string(),
+            parameters: search.parameters.clone(),
+        };
+        let research = ToolDefinition {
+            name: "research".to_string(),
+            description: "Research a topic: search the web and have a local LLM write a \
+                          summary report with sources. Takes up to a few minutes."
+                .to_string(),
+            parameters: search.parameters.clone(),
+        };
+
+        vec![search, self.fetch.definition(String::new()).await, quick, research]
+    }
+
+    /// Run a tool. The outer error is a protocol error (bad name or
+    /// arguments), the inner one a tool failure.
+    async fn call_tool(&self, name: &str, arguments: Value) -> Result<Result<String, String>, RpcError> {
+        let invalid = |e: serde_json::Error| RpcError::new(INVALID_PARAMS, e.to_string());
+        info!(tool = name, "MCP tool call");
+
+        match name {
+            WebSearchTool::NAME => {
+                let args: SearchArgs = serde_json::from_value(arguments).map_err(invalid)?;
+                if let Err(message) = self.authorize(Scope::Search) {
+                    return Ok(Err(message));
+                }
+                Ok(self.search.call(args).await.map_err(|e| e.to_string()))
+            }
+            FetchPageTool::NAME => {
+                let args: FetchArgs = serde_json::from_value(arguments).map_err(invalid)?;
+                if let Err(message) = self.authorize(Scope::Search) {
+                    return Ok(Err(message));
+                }
+                Ok(self.fetch.call(args).await.map_err(|e| e.to_string()))
+            }
+            "quick_search" | "research" => {
+                let args: SearchArgs = serde_json::from_value(arguments).map_err(invalid)?;
+                Ok(self.run_query(&args.query, name == "quick_search").await)
+            }
+            _ => Err(RpcError::new(INVALID_PARAMS, format!("Unknown tool {}", name))),
+        }
+    }
+
+    /// Run a quick search or research query on the backend.
+    async fn run_query(&self, query: &str, quick: bool) -> Result<String, String> {
+        let scope = if quick { Scope::Search } else { Scope::Research };
+        self.authorize(scope)?;
+
+        let result = match &self.backend {
+            Backend::Local => {
+                let agent = ResearchAgent::new(self.config.clone());
+                if quick {
+                    agent.quick_search(query).await
+                } else {
+                    agent.research(query).await
+                }
+            }
+            Backend::Web { state, principal } => {
+                let options = QueryOptions::quick(quick);
+                match state.jobs.start(query, &options, &principal.name, None) {
+                    // Registered as a job so shutdown waits for it
+                    Ok(job) => {
+                        let result = state
+                            .run_query(&principal.name, None, query, &options, |_| {}, None)
+                            .await;
+                        job.finish(&result);
+                        result.map(|outcome| outcome.answer)
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+        };
+
+        result.map_err(|e| match e.hint() {
+            Some(hint) => format!("{} ({})", e, hint),
+            None => e.to_string(),
+        })
+    }
+
+    /// Check the caller's API key (web only; stdio is the local user).
+    fn authorize(&self, scope: Scope) -> Result<(), String> {
+        match &self.backend {
+            Backend::Local => Ok(()),
+            Backend::Web { state, principal } => {
+                state.auth.authorize(principal, scope).map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+fn error_response(id: Value, error: RpcError) -> String {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": error.code, "message": error.message },
+    })
+    .to_string()
+}
+
+// =============================================================================
+// STDIO TRANSPORT
+// =============================================================================
+/// Serve MCP on stdin/stdout until stdin closes.
+///
+/// Requests are handled concurrently - a client can still `ping` while a
+/// `research` call runs - and responses are written whole, one per line,
+/// by a single writer task.
+pub async fn serve_stdio(config: Config) -> Result<()> {
+    info!("MCP server listening on stdio");
+    let server = Arc::new(McpServer::new(config));
+
+    let (tx, mut rx) = mpsc::channel::<String>(32);
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(response) = rx.recv().await {
+            let written = async {
+                stdout.write_all(response.as_bytes()).await?;
+                stdout.write_all(b"\n").await?;
+                stdout.flush().await
+            };
+            if let Err(e) = written.await {
+                warn!("Failed to write MCP response: {}", e);
+                break;
+            }
+        }
+    });
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let server = server.clone();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            if let Some(response) = server.handle(&line).await {
+                let _ = tx.send(response).await;
+            }
+        });
+    }
+
+    // Let in-flight calls finish and their responses go out
+    drop(tx);
+    let _ = writer.await;
+    info!("stdin closed, MCP server exiting");
+    Ok(())
+}
+
+// =============================================================================
+// UNIT TESTS
+// =============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server() -> McpServer {
+        McpServer::new(Config::default())
+    }
+
+    async fn call(message: Value) -> Value {
+        let response = server().handle(&message.to_string()).await.unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_initialize_and_notifications() {
+        let response = call(json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}})).await;
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["protocolVersion"], PROTOCOL_VERSION);
+
+        let notification = json!({"jsonrpc": "2.0", "method": "notifications/initialized"});
+        assert!(server().handle(&notification.to_string()).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tools_list_uses_tool_definitions() {
+        let response = call(json!({"jsonrpc": "2.0", "id": "a", "method": "tools/list"})).await;
+        let tools = response["result"]["tools"].as_array().unwrap();
+
+        let names: Vec<&str> = tools.iter().map(|t| t["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["web_search", "fetch_page", "quick_search", "research"]);
+        assert_eq!(tools[0]["inputSchema"]["required"][0], "query");
+        assert_eq!(tools[1]["inputSchema"]["required"][0], "url");
+    }
+
+    #[tokio::test]
+    async fn test_errors() {
+        let response = server().handle("{not json").await.unwrap();
+        assert!(response.contains("-32700"));
+
+        let response = call(json!({"jsonrpc": "2.0", "id": 2, "method": "resources/list"})).await;
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+
+        let unknown = json!({
+            "jsonrpc": "2.0", "id": 3, "method": "tools/call",
+            "params": {"name": "delete_everything", "arguments": {}}
+        });
+        assert_eq!(call(unknown).await["error"]["code"], INVALID_PARAMS);
+
+        // Tool failures come back as results flagged isError
+        let private = json!({
+            "jsonrpc": "2.0", "id": 4, "method": "tools/call",
+            "params": {"name": "fetch_page", "arguments": {"url": "http://localhost:8080/"}}
+        });
+        assert_eq!(call(private).await["result"]["isError"], true);
+    }
+}
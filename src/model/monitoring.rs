@@ -0,0 +1,118 @@
+// Dimensionless Developments Rust Ai
+// # Monitoring Module
+// Prometheus metrics for the research agent, served at `/metrics` in web mode.
+//
+// We use the `metrics` facade: code anywhere in the crate records values with
+// `counter!`/`histogram!`/`gauge!`, and the Prometheus recorder installed at
+// server startup aggregates them. In CLI mode no recorder is installed and
+// the macros are no-ops, so recording is free.
+//
+// (The module isn't called `metrics` so it doesn't shadow the crate.)
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+// =============================================================================
+// METRIC NAMES
+// =============================================================================
+/// Research requests, labelled by `mode` (research/quick) and `outcome`
+/// (`success` or an `AgentError` code such as `provider_unreachable`).
+pub const RESEARCH_REQUESTS: &str = "research_requests_total";
+
+/// Research latency in seconds, labelled by `mode`.
+pub const RESEARCH_DURATION: &str = "research_duration_seconds";
+
+/// Web search calls, labelled by `provider`.
+pub const SEARCH_REQUESTS: &str = "search_requests_total";
+
+/// Failed web searches, labelled by `provider` and `reason`.
+pub const SEARCH_FAILURES: &str = "search_failures_total";
+
+/// Rate-limit responses, labelled by `provider`: a search provider, or
+/// `api_key` for requests refused by a key's quota or rate limit.
+pub const RATE_LIMIT_HITS: &str = "rate_limit_hits_total";
+
+/// Currently open WebSocket connections.
+pub const ACTIVE_WEBSOCKETS: &str = "websocket_connections_active";
+
+/// LLM tokens, labelled by `model` and `direction` (input/output).
+pub const LLM_TOKENS: &str = "llm_tokens_total";
+
+/// Research runs currently holding a scheduler slot.
+pub const RUNNING_JOBS: &str = "research_jobs_running";
+
+/// Research runs waiting in the scheduler queue.
+pub const QUEUED_JOBS: &str = "research_jobs_queued";
+
+/// Histogram buckets for research latency. Local models are slow, so the
+/// buckets go up to the default five minute timeout.
+const RESEARCH_BUCKETS: &[f64] = &[1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 300.0];
+
+// =============================================================================
+// SETUP
+// =============================================================================
+/// Install the global Prometheus recorder and return a handle for `/metrics`.
+pub fn install() -> Result<PrometheusHandle> {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Full(RESEARCH_DURATION.to_string()), RESEARCH_BUCKETS)
+        .context("Invalid histogram buckets")?
+        .install_recorder()
+        .context("Failed to install Prometheus metrics recorder")
+}
+
+// =============================================================================
+// RECORDING HELPERS
+// =============================================================================
+/// Record one finished research request.
+pub fn record_research(mode: &'static str, outcome: &'static str, elapsed: Duration) {
+    counter!(RESEARCH_REQUESTS, "mode" => mode, "outcome" => outcome).increment(1);
+    histogram!(RESEARCH_DURATION, "mode" => mode).record(elapsed.as_secs_f64());
+}
+
+/// Record one web search call and, if it failed, why.
+pub fn record_search(provider: &'static str, failure: Option<&'static str>) {
+    counter!(SEARCH_REQUESTS, "provider" => provider).increment(1);
+
+    if let Some(reason) = failure {
+        counter!(SEARCH_FAILURES, "provider" => provider, "reason" => reason).increment(1);
+        if reason == "rate_limited" {
+            record_rate_limit(provider);
+        }
+    }
+}
+
+/// Record one request refused (or answered) with a rate limit.
+pub fn record_rate_limit(provider: &'static str) {
+    counter!(RATE_LIMIT_HITS, "provider" => provider).increment(1);
+}
+
+/// Record the tokens used by a research run.
+pub fn record_tokens(model: &str, input: u64, output: u64) {
+    counter!(LLM_TOKENS, "model" => model.to_string(), "direction" => "input").increment(input);
+    counter!(LLM_TOKENS, "model" => model.to_string(), "direction" => "output").increment(output);
+}
+
+/// Counts an open WebSocket connection for as long as it is alive.
+///
+/// # Rust Concept: RAII Guards
+///
+/// The gauge is incremented in `track()` and decremented in `Drop`, so the
+/// count stays right however the connection handler exits (return, error,
+/// or task abort).
+pub struct ConnectionGauge;
+
+impl ConnectionGauge {
+    pub fn track() -> Self {
+        gauge!(ACTIVE_WEBSOCKETS).increment(1.0);
+        ConnectionGauge
+    }
+}
+
+impl Drop for ConnectionGauge {
+    fn drop(&mut self) {
+        gauge!(ACTIVE_WEBSOCKETS).decrement(1.0);
+    }
+}
@@ -0,0 +1,282 @@
+// Dimensionless Developments Rust Ai
+// # WebSocket Protocol
+// Message types exchanged between the Leptos frontend and the Axum backend.
+//
+// This module is compiled into BOTH the server binary and the WASM frontend,
+// so it may only depend on serde - no tokio, axum or web-sys here.
+
+use serde::{Deserialize, Serialize};
+
+// =============================================================================
+// SERVER -> CLIENT
+// =============================================================================
+/// An event sent from the server to the browser, encoded as JSON.
+///
+/// # Rust Concept: Internally Tagged Enums
+///
+/// `#[serde(tag = "type")]` stores the variant name inside the JSON object:
+/// `{"type": "answer", "text": "..."}`. The frontend can match on the
+/// variant instead of guessing what a raw string means.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    /// First message on every connection: the chat's session id, to pass
+    /// back as `/ws?session=<id>&last_seq=<n>` after a reconnect.
+    /// `resumed` is false when the old session was gone and a new one started.
+    Session { session_id: String, resumed: bool },
+
+    /// The query is waiting for a free slot; `position` 1 is next in line.
+    /// Sent again whenever the position changes.
+    Queued { position: usize },
+
+    /// Something the agent did while working on the query (a search, a
+    /// page read, a model round), for the activity panel under the answer
+    Activity { step: ActivityStep },
+
+    /// The search results the coming answer is based on, numbered from 1
+    /// in order; `[n]` in the answer cites `sources[n - 1]`
+    Sources { sources: Vec<Source> },
+
+    /// The final answer to a query. `run_id` is its id in the server's
+    /// history (`/api/history/{id}`), when the server keeps one; `meta`
+    /// says how it was made.
+    Answer {
+        text: String,
+        #[serde(default)]
+        run_id: Option<String>,
+        #[serde(default)]
+        meta: Option<AnswerMeta>,
+    },
+
+    /// The query failed; `code` is one of the stable codes of `AgentError`
+    /// (e.g. `provider_unreachable`, `model_missing`, `rate_limited`)
+    Error {
+        code: String,
+        message: String,
+        hint: Option<String>,
+    },
+
+    /// The query was stopped by the client's `cancel`
+    Cancelled,
+
+    /// The server is restarting. No new queries are accepted; a running
+    /// query (`job_id`) still finishes if it can before the deadline, and
+    /// otherwise is resumed after the restart.
+    ShuttingDown {
+        message: String,
+        job_id: Option<String>,
+    },
+}
+
+/// A web page an answer is based on, shown as a card under the answer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Source {
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+}
+
+/// How an answer was made, shown when hovering over it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AnswerMeta {
+    /// The model that wrote it; `None` for quick searches
+    pub model: Option<String>,
+
+    /// Time the run took, not counting the queue
+    pub duration_ms: u64,
+
+    /// Web and document searches made
+    pub searches: usize,
+
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// Largest file that can be attached (`POST /api/documents`), in bytes.
+pub const MAX_UPLOAD_BYTES: usize = 10 * 1024 * 1024;
+
+/// Source URLs of passages from attached documents start with this, e.g.
+/// `attachment:<document id>#3`.
+pub const ATTACHMENT_URL_PREFIX: &str = "attachment:";
+
+/// A document attached to a conversation, as `POST /api/documents`
+/// returns it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentInfo {
+    /// What queries pass in `QueryOptions::documents`
+    pub id: String,
+    pub name: String,
+
+    /// Length of the extracted text
+    pub chars: usize,
+}
+
+/// One step of a running query.
+///
+/// Nested inside `ServerEvent::Activity` with its own tag:
+/// `{"type": "activity", "step": {"kind": "searching", "query": "..."}}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ActivityStep {
+    /// The model started round `round` of at most `max_rounds`
+    Thinking { round: usize, max_rounds: usize },
+
+    /// A web search was sent
+    Searching { query: String },
+
+    /// A web search came back with `results` hits, or failed with `error`
+    Searched {
+        query: String,
+        results: usize,
+        error: Option<String>,
+    },
+
+    /// The attached documents were searched; `results` passages matched
+    SearchedDocuments { query: String, results: usize },
+
+    /// A page is being downloaded
+    Fetching { url: String },
+
+    /// A page was read (`chars` of text), or could not be (`error`)
+    Fetched {
+        url: String,
+        chars: usize,
+        error: Option<String>,
+    },
+}
+
+impl ServerEvent {
+    /// Encode the event as a JSON text frame.
+    pub fn to_json(&self) -> String {
+        // Serializing these plain structs can't fail
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// An event as it goes over the wire.
+///
+/// Events belonging to a session carry a `seq` number, which the client
+/// acknowledges and reports back when it reconnects; connection-level
+/// events (`session`, `shutting_down`) have none.
+///
+/// # Rust Concept: Flattening
+///
+/// `#[serde(flatten)]` inlines the event's fields next to `seq`, so the
+/// JSON stays flat: `{"seq": 3, "type": "answer", "text": "..."}`. A
+/// message without `seq` parses too, with `seq: None`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+
+    #[serde(flatten)]
+    pub event: ServerEvent,
+}
+
+impl Envelope {
+    pub fn sequenced(seq: u64, event: ServerEvent) -> Self {
+        Self {
+            seq: Some(seq),
+            event,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+// =============================================================================
+// CLIENT -> SERVER
+// =============================================================================
+/// A message sent from the browser to the server.
+///
+/// A text frame that isn't one of these JSON objects is treated as a
+/// query, so plain-text clients (and `websocat`) keep working.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Research this, with the chat's settings
+    Query {
+        text: String,
+        #[serde(default)]
+        options: QueryOptions,
+    },
+
+    /// Every event up to and including `seq` has been processed
+    Ack { seq: u64 },
+
+    /// Stop the running query, and any waiting behind it
+    Cancel,
+}
+
+impl ClientMessage {
+    /// Parse a text frame, falling back to a plain-text query.
+    pub fn parse(frame: &str) -> Self {
+        serde_json::from_str(frame).unwrap_or_else(|_| ClientMessage::Query {
+            text: frame.to_string(),
+            options: QueryOptions::default(),
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// Most search results a query may ask for (`QueryOptions::max_results`).
+pub const MAX_RESULTS_LIMIT: usize = 20;
+
+/// How much work a query gets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResearchMode {
+    /// Search only, no model (the CLI's `--quick`)
+    Quick,
+
+    /// Search, read and summarize
+    #[default]
+    Research,
+
+    /// Like research, but searching several times, with more tool rounds
+    Deep,
+}
+
+/// Settings sent along with a query. `None` fields use the server's
+/// configuration.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryOptions {
+    pub mode: ResearchMode,
+
+    /// One of the models listed by `GET /api/models`
+    pub model: Option<String>,
+
+    /// Search results per search
+    pub max_results: Option<usize>,
+
+    pub temperature: Option<f32>,
+
+    /// Attached documents (`DocumentInfo::id`) the agent may search
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub documents: Vec<String>,
+}
+
+impl QueryOptions {
+    /// The server's settings, as a quick search or a research run.
+    pub fn quick(quick: bool) -> Self {
+        let mode = if quick {
+            ResearchMode::Quick
+        } else {
+            ResearchMode::Research
+        };
+        Self {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_quick(&self) -> bool {
+        self.mode == ResearchMode::Quick
+    }
+}
@@ -0,0 +1,352 @@
+// Dimensionless Developments Rust Ai
+// # Scheduler Module
+// Limits how many research runs hit the LLM at once.
+//
+// Ollama on a single GPU answers one prompt at a time; piling more runs on
+// top only makes every one of them slower (and eventually time out). So
+// research runs queue here for one of `max_concurrent_jobs` slots:
+//
+// - The queue is fair across clients: slots are handed out round-robin,
+//   one job per client per turn, so one busy client can't starve the rest.
+// - Waiting jobs are told their position whenever it changes.
+// - The queue is bounded (`max_queued_jobs`); beyond that new jobs are
+//   refused with `server_busy` instead of piling up forever.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use metrics::gauge;
+use tokio::sync::{oneshot, watch};
+
+use crate::error::AgentError;
+use crate::monitoring::{QUEUED_JOBS, RUNNING_JOBS};
+
+// =============================================================================
+// QUEUE STATE
+// =============================================================================
+/// A job waiting for a slot.
+struct Waiter {
+    id: u64,
+    ready: oneshot::Sender<()>,
+    position: watch::Sender<usize>,
+}
+
+/// Everything behind the scheduler's lock.
+#[derive(Default)]
+struct Queue {
+    next_id: u64,
+    running: usize,
+
+    /// Clients with waiting jobs, in round-robin order
+    rotation: VecDeque<String>,
+
+    /// Waiting jobs per client, oldest first
+    waiting: HashMap<String, VecDeque<Waiter>>,
+}
+
+impl Queue {
+    fn waiting_len(&self) -> usize {
+        self.waiting.values().map(VecDeque::len).sum()
+    }
+
+    /// Remove a waiter that gave up. Returns false if it was already dispatched.
+    fn remove(&mut self, client: &str, id: u64) -> bool {
+        let Some(jobs) = self.waiting.get_mut(client) else {
+            return false;
+        };
+        let Some(index) = jobs.iter().position(|w| w.id == id) else {
+            return false;
+        };
+
+        jobs.remove(index);
+        if jobs.is_empty() {
+            self.waiting.remove(client);
+            self.rotation.retain(|c| c != client);
+        }
+        true
+    }
+
+    /// Pop the next waiter in round-robin order.
+    fn pop_next(&mut self) -> Option<Waiter> {
+        let client = self.rotation.pop_front()?;
+        let jobs = self.waiting.get_mut(&client)?;
+        let waiter = jobs.pop_front();
+
+        if jobs.is_empty() {
+            self.waiting.remove(&client);
+        } else {
+            // Back of the line until every other client had a turn
+            self.rotation.push_back(client);
+        }
+        waiter
+    }
+
+    /// Tell every waiter its (1-based) position in dispatch order.
+    ///
+    /// Dispatch order is the round-robin interleaving: the first job of
+    /// every client in rotation order, then the second job of each, etc.
+    fn publish_positions(&self) {
+        let mut position = 0;
+        let mut round = 0;
+
+        loop {
+            let mut any = false;
+            for client in &self.rotation {
+                if let Some(waiter) = self.waiting.get(client).and_then(|jobs| jobs.get(round)) {
+                    position += 1;
+                    any = true;
+                    waiter.position.send_if_modified(|p| {
+                        let changed = *p != position;
+                        *p = position;
+                        changed
+                    });
+                }
+            }
+            if !any {
+                break;
+            }
+            round += 1;
+        }
+    }
+}
+
+// =============================================================================
+// SCHEDULER
+// =============================================================================
+/// Server-wide research scheduler.
+///
+/// # Rust Concept: Interior Mutability with Mutex
+///
+/// Handlers share the scheduler through `&self`, so the queue lives inside
+/// a `Mutex`. We never hold the lock across an `.await`, which is why a
+/// plain `std::sync::Mutex` is fine here (and cheaper than tokio's).
+pub struct Scheduler {
+    queue: Mutex<Queue>,
+    max_concurrent: usize,
+    max_queued: usize,
+}
+
+impl Scheduler {
+    pub fn new(max_concurrent: usize, max_queued: usize) -> Self {
+        Self {
+            queue: Mutex::new(Queue::default()),
+            max_concurrent: max_concurrent.max(1),
+            max_queued,
+        }
+    }
+
+    /// Put a job for `client` in the queue.
+    ///
+    /// Fails with `ServerBusy` when every slot is taken and the queue is
+    /// full. A job that gets a free slot right away never waits, so it
+    /// isn't held to `max_queued` (which may be 0: no waiting at all).
+    pub fn enqueue(&self, client: &str) -> Result<Ticket<'_>, AgentError> {
+        let mut queue = self.lock();
+
+        let queued = queue.waiting_len();
+        let slot_free = queue.running < self.max_concurrent;
+        if !slot_free && queued >= self.max_queued {
+            return Err(AgentError::ServerBusy { queued });
+        }
+
+        queue.next_id += 1;
+        let id = queue.next_id;
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let (position_tx, position_rx) = watch::channel(0);
+
+        if !queue.waiting.contains_key(client) {
+            queue.rotation.push_back(client.to_string());
+        }
+        queue.waiting.entry(client.to_string()).or_default().push_back(Waiter {
+            id,
+            ready: ready_tx,
+            position: position_tx,
+        });
+
+        self.dispatch(&mut queue);
+
+        Ok(Ticket {
+            scheduler: self,
+            client: client.to_string(),
+            id,
+            ready: ready_rx,
+            position: position_rx,
+            done: false,
+        })
+    }
+
+    /// Hand free slots to waiting jobs, then refresh everyone's position.
+    fn dispatch(&self, queue: &mut Queue) {
+        while queue.running < self.max_concurrent {
+            let Some(waiter) = queue.pop_next() else {
+                break;
+            };
+            // A waiter whose ticket was dropped can't take the slot
+            if waiter.ready.send(()).is_ok() {
+                queue.running += 1;
+            }
+        }
+
+        queue.publish_positions();
+        gauge!(RUNNING_JOBS).set(queue.running as f64);
+        gauge!(QUEUED_JOBS).set(queue.waiting_len() as f64);
+    }
+
+    /// Give back a slot.
+    fn release(&self) {
+        let mut queue = self.lock();
+        queue.running = queue.running.saturating_sub(1);
+        self.dispatch(&mut queue);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Queue> {
+        // A panic while holding the lock leaves the queue consistent enough
+        // to keep serving, so recover from poisoning instead of propagating it
+        self.queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A place in the queue.
+///
+/// Dropping a ticket (e.g. because the client disconnected) leaves the
+/// queue, or gives the slot back if it had just been granted.
+pub struct Ticket<'a> {
+    scheduler: &'a Scheduler,
+    client: String,
+    id: u64,
+    ready: oneshot::Receiver<()>,
+    position: watch::Receiver<usize>,
+    done: bool,
+}
+
+impl<'a> Ticket<'a> {
+    /// Wait for a slot, calling `on_position` with the queue position
+    /// (1 = next in line) every time it changes.
+    pub async fn wait(mut self, mut on_position: impl FnMut(usize)) -> Permit<'a> {
+        loop {
+            tokio::select! {
+                biased;
+                result = &mut self.ready => {
+                    self.done = true;
+                    if result.is_err() {
+                        // The sender is only dropped after being removed from
+                        // the queue, which this ticket does itself - so this
+                        // can't happen, but don't hang if it ever does.
+                        tracing::warn!("Scheduler dropped a queued job, running it anyway");
+                        self.scheduler.lock().running += 1;
+                    }
+                    return Permit { scheduler: self.scheduler };
+                }
+                changed = self.position.changed() => {
+                    if changed.is_ok() {
+                        on_position(*self.position.borrow_and_update());
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Ticket<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+
+        let mut queue = self.scheduler.lock();
+        if !queue.remove(&self.client, self.id) && self.ready.try_recv().is_ok() {
+            // Granted a slot we'll never use
+            queue.running = queue.running.saturating_sub(1);
+        }
+        self.scheduler.dispatch(&mut queue);
+    }
+}
+
+/// A running slot, released when dropped.
+pub struct Permit<'a> {
+    scheduler: &'a Scheduler,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.scheduler.release();
+    }
+}
+
+// =============================================================================
+// UNIT TESTS
+// =============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(ticket: &Ticket<'_>) -> usize {
+        *ticket.position.borrow()
+    }
+
+    #[tokio::test]
+    async fn test_queue_is_fair_across_clients() {
+        let scheduler = Scheduler::new(1, 10);
+
+        // "a" takes the only slot, then queues two more; "b" queues one
+        let running = scheduler.enqueue("a").unwrap().wait(|_| {}).await;
+        let a2 = scheduler.enqueue("a").unwrap();
+        let a3 = scheduler.enqueue("a").unwrap();
+        let b1 = scheduler.enqueue("b").unwrap();
+
+        // b1 is served before a's second queued job
+        assert_eq!(position(&a2), 1);
+        assert_eq!(position(&b1), 2);
+        assert_eq!(position(&a3), 3);
+
+        drop(running);
+        let permit = a2.wait(|_| {}).await;
+        assert_eq!(position(&b1), 1);
+        assert_eq!(position(&a3), 2);
+
+        drop(permit);
+        let _permit = b1.wait(|_| {}).await;
+        assert_eq!(position(&a3), 1);
+    }
+
+    #[tokio::test]
+    async fn test_queue_is_bounded() {
+        let scheduler = Scheduler::new(1, 1);
+
+        let _running = scheduler.enqueue("a").unwrap().wait(|_| {}).await;
+        let _waiting = scheduler.enqueue("a").unwrap();
+
+        let err = scheduler.enqueue("b").err().unwrap();
+        assert_eq!(err.code(), "server_busy");
+    }
+
+    #[tokio::test]
+    async fn test_no_queue_still_runs_with_a_free_slot() {
+        let scheduler = Scheduler::new(1, 0);
+
+        let running = scheduler.enqueue("a").unwrap().wait(|_| {}).await;
+        let err = scheduler.enqueue("b").err().unwrap();
+        assert_eq!(err.code(), "server_busy");
+
+        drop(running);
+        let _permit = scheduler.enqueue("b").unwrap().wait(|_| {}).await;
+        assert_eq!(scheduler.lock().running, 1);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_ticket_leaves_queue() {
+        let scheduler = Scheduler::new(1, 10);
+
+        let running = scheduler.enqueue("a").unwrap().wait(|_| {}).await;
+        let gone = scheduler.enqueue("b").unwrap();
+        let c1 = scheduler.enqueue("c").unwrap();
+        assert_eq!(position(&c1), 2);
+
+        drop(gone);
+        assert_eq!(position(&c1), 1);
+
+        drop(running);
+        let _permit = c1.wait(|_| {}).await;
+        assert_eq!(scheduler.lock().running, 1);
+    }
+}
@@ -3,108 +3,1126 @@
 // Provides WebSocket server functionality for the frontend to communicate
 // with the AI research agent backend.
 
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use axum::{
-    extract::ws::{Message, WebSocket, WebSocketUpgrade},
-    response::IntoResponse,
-    routing::get,
-    Router,
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, DefaultBodyLimit, Multipart, Path, Query, Request, State,
+    },
+    http::{header, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
 };
+use serde::{Deserialize, Serialize};
 use futures::{sink::SinkExt, stream::StreamExt};
+use metrics_exporter_prometheus::PrometheusHandle;
 use tokio::sync::mpsc;
 use tower_http::{
-    cors::{Any, CorsLayer},
+    compression::CompressionLayer,
+    cors::{AllowOrigin, Any, CorsLayer},
     services::ServeDir,
 };
-use tracing::{error, info};
+use tracing::{error, info, info_span, Instrument, Span};
+use uuid::Uuid;
 
-use crate::agent::ResearchAgent;
+use crate::agent::{ResearchAgent, ResearchOutcome};
+use crate::auth::{Authenticator, Principal, Scope};
 use crate::config::Config;
+use crate::connections::{ConnectionLimiter, ConnectionSlot};
+use crate::documents::{Document, DocumentStore};
+use crate::error::AgentError;
+use crate::favicons::FaviconCache;
+use crate::health::{self, HealthChecker};
+use crate::history::{self, History, RunRecord};
+use crate::jobs::{JobRegistry, PendingJob};
+use crate::mcp::McpServer;
+use crate::monitoring::{self, ConnectionGauge};
+use crate::protocol::{
+    ClientMessage, QueryOptions, ResearchMode, ServerEvent, Source, MAX_UPLOAD_BYTES,
+};
+use crate::scheduler::Scheduler;
+use crate::sessions::{Session, SessionStore};
+use crate::tools::ActivitySink;
 
-/// Create the web server router with WebSocket and static file serving
-pub fn create_router() -> Router {
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
+/// Outgoing messages buffered per WebSocket connection. When a client
+/// reads slower than we write, senders wait instead of growing memory.
+const WS_SEND_BUFFER: usize = 32;
 
-    let serve_dir = ServeDir::new("dist")
-        .append_index_html_on_directories(true)
-        .not_found_service(ServeDir::new("dist"));
+/// Shared state handed to every request handler.
+///
+/// The configuration is resolved once at startup (files, profile, env and
+/// CLI flags) so every connection uses the same settings.
+pub struct AppState {
+    pub config: Config,
+
+    /// Renders the Prometheus metrics for `/metrics`
+    pub metrics: PrometheusHandle,
+
+    /// Liveness/readiness checks for `/healthz` and `/readyz`
+    pub health: HealthChecker,
+
+    /// Running jobs, drained on shutdown
+    pub jobs: JobRegistry,
+
+    /// Limits how many research runs use the LLM at once
+    pub scheduler: Scheduler,
+
+    /// API keys, scopes and quotas
+    pub auth: Authenticator,
+
+    /// Past runs, unless `save_history` is off
+    pub history: Option<Arc<History>>,
+
+    /// Chat sessions, which outlive their WebSocket connections
+    pub sessions: SessionStore,
+
+    /// Open WebSockets per client IP
+    pub connections: Arc<ConnectionLimiter>,
+
+    /// Icons for the chat's source cards
+    pub favicons: FaviconCache,
+
+    /// Files attached in the chat
+    pub documents: DocumentStore,
+}
+
+impl AppState {
+    /// Build the shared state, installing the global metrics recorder.
+    pub fn new(config: Config) -> anyhow::Result<Self> {
+        let scheduler = Scheduler::new(config.max_concurrent_jobs, config.max_queued_jobs);
+        let sessions = SessionStore::new(Duration::from_secs(config.session_ttl_secs));
+        let connections = Arc::new(ConnectionLimiter::new(config.ws_max_connections_per_ip));
+        let auth = Authenticator::from_config(&config)?;
+        let history = if config.save_history {
+            let path = history::history_db_path(&config.data_dir);
+            Some(Arc::new(History::open(&path)?))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            config,
+            metrics: monitoring::install()?,
+            health: HealthChecker::new(),
+            jobs: JobRegistry::new(),
+            scheduler,
+            auth,
+            history,
+            sessions,
+            connections,
+            favicons: FaviconCache::new(),
+            documents: DocumentStore::new(),
+        })
+    }
+
+    /// Run a query for `client` (the API key name), with its `options`
+    /// applied to the server's configuration.
+    ///
+    /// Research waits for a scheduler slot first, reporting its queue
+    /// position through `on_position`. Chat queries (with a `session`) are
+    /// queued per session, the others per key. Quick searches don't use
+    /// the LLM, so they skip the queue.
+    ///
+    /// Research runs may also search the attached documents the options
+    /// name, if they belong to `client` and `session`. Every run that gets to execute is stored in the history, under
+    /// the outcome's `run_id`. With an `activity` sink, its searches and model
+    /// rounds are reported live. Returns the whole outcome, so callers can
+    /// show its sources.
+    pub async fn run_query(
+        &self,
+        client: &str,
+        session: Option<&str>,
+        query: &str,
+        options: &QueryOptions,
+        on_position: impl FnMut(usize),
+        activity: Option<ActivitySink>,
+    ) -> Result<ResearchOutcome, AgentError> {
+        let config = self.config.with_query_options(options)?;
+        let quick = options.is_quick();
+
+        // Only models the server has may be picked
+        if !quick && config.model != self.config.model {
+            let installed = health::list_models(&config.ollama_host).await?;
+            if !health::model_installed(&installed, &config.model) {
+                return Err(AgentError::ModelMissing { model: config.model });
+            }
+        }
+
+        let documents = self.documents.get(&options.documents, client, session).map_err(|id| {
+            AgentError::InvalidOptions(format!(
+                "attached document {} is unknown or has expired; attach it again",
+                id
+            ))
+        })?;
+
+        let mut agent = ResearchAgent::new(config.clone());
+        if !documents.is_empty() {
+            agent = agent.with_documents(documents);
+        }
+        if let Some(activity) = activity {
+            agent = agent.with_activity(activity);
+        }
+        if options.mode == ResearchMode::Deep {
+            agent = agent.deep();
+        }
+
+        let (started, mut result) = if quick {
+            let started = Instant::now();
+            (started, agent.quick_search_detailed(query).await)
+        } else {
+            // Without API keys every tab is the same anonymous client, and
+            // tabs should still take turns
+            let lane = match session {
+                Some(session) => format!("{}/{}", client, session),
+                None => client.to_string(),
+            };
+            let ticket = self.scheduler.enqueue(&lane)?;
+            let _permit = ticket.wait(on_position).await;
+            let started = Instant::now();
+            (started, agent.research_detailed(query).await)
+        };
 
-    Router::new()
+        if let Some(history) = &self.history {
+            let run = RunRecord::new(&config, query, quick, Some(client), started, &result);
+            if let Ok(outcome) = &mut result {
+                outcome.run_id = Some(run.id.clone());
+            }
+            let history = history.clone();
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = history.record(&run) {
+                    error!("Could not save run to history: {:#}", e);
+                }
+            });
+        }
+
+        result
+    }
+}
+
+/// Create the web server router with WebSocket and static file serving
+pub fn create_router(state: Arc<AppState>) -> Router {
+    let cors = cors_layer(&state.config.cors_origins);
+
+    let router = Router::new()
         .route("/ws", get(ws_handler))
-        .fallback_service(serve_dir)
+        .route("/api/research", post(research_handler))
+        .route("/api/jobs/:id", get(job_handler))
+        .route("/api/models", get(models_handler))
+        .route("/api/favicon/:domain", get(favicon_handler))
+        .route(
+            "/api/documents",
+            // Room for the multipart framing around the file
+            post(documents_handler).layer(DefaultBodyLimit::max(MAX_UPLOAD_BYTES + 64 * 1024)),
+        )
+        .route("/mcp", post(mcp_handler))
+        .route("/api/history", get(history_list_handler))
+        .route(
+            "/api/history/:id",
+            get(history_run_handler).delete(history_delete_handler),
+        )
+        .route("/metrics", get(metrics_handler))
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler));
+
+    with_frontend(router, &state.config)
+        .layer(middleware::from_fn(trace_request))
+        .layer(CompressionLayer::new())
         .layer(cors)
+        .with_state(state)
+}
+
+/// Serve the web UI from `static_dir`.
+fn serve_static_dir(router: Router<Arc<AppState>>, config: &Config) -> Router<Arc<AppState>> {
+    let static_dir = &config.static_dir;
+    let serve_dir = ServeDir::new(static_dir)
+        .append_index_html_on_directories(true)
+        .not_found_service(ServeDir::new(static_dir));
+
+    router.fallback_service(serve_dir)
+}
+
+/// Serve the web UI from the files embedded in the binary, unless
+/// `static_dir` was set explicitly (handy to try a new UI build without
+/// rebuilding the server).
+#[cfg(feature = "embed-frontend")]
+fn with_frontend(router: Router<Arc<AppState>>, config: &Config) -> Router<Arc<AppState>> {
+    if config.sources.get("static_dir") == crate::config::ValueSource::Default {
+        router.fallback(crate::assets::serve_embedded)
+    } else {
+        serve_static_dir(router, config)
+    }
+}
+
+/// Serve the web UI from `static_dir` (nothing is embedded in this build).
+#[cfg(not(feature = "embed-frontend"))]
+fn with_frontend(router: Router<Arc<AppState>>, config: &Config) -> Router<Arc<AppState>> {
+    serve_static_dir(router, config)
+}
+
+/// Build the CORS layer for the configured origins.
+///
+/// `*` allows any origin; otherwise only the listed origins may call us
+/// from a browser. With no origins at all, only same-origin pages can.
+fn cors_layer(origins: &[String]) -> CorsLayer {
+    let cors = CorsLayer::new().allow_methods(Any).allow_headers(Any);
+
+    if origins.iter().any(|origin| origin == "*") {
+        return cors.allow_origin(Any);
+    }
+
+    // Origins were checked by Config::validate, so parsing can't fail
+    let allowed: Vec<HeaderValue> = origins
+        .iter()
+        .filter_map(|origin| HeaderValue::from_str(origin).ok())
+        .collect();
+    cors.allow_origin(AllowOrigin::list(allowed))
+}
+
+/// Wrap every request in an `http_request` span.
+///
+/// With the `otel` feature the span continues the caller's trace when the
+/// request carries a W3C `traceparent` header, so a research run shows up
+/// under the reverse proxy's or client's trace in the collector.
+async fn trace_request(request: Request, next: Next) -> Response {
+    let span = info_span!(
+        "http_request",
+        method = %request.method(),
+        path = %request.uri().path(),
+    );
+
+    #[cfg(feature = "otel")]
+    crate::telemetry::set_remote_parent(&span, request.headers());
+
+    next.run(request).instrument(span).await
+}
+
+/// Prometheus scrape endpoint.
+async fn metrics_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
+
+/// Liveness probe: 200 while the process is running.
+async fn healthz_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    Json(state.health.liveness())
+}
+
+/// Readiness probe: 200 when queries can be answered, 503 otherwise,
+/// with the result of each check in the body.
+async fn readyz_handler(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let mut readiness = state.health.readiness(&state.config).await;
+
+    // Tell the load balancer to stop sending traffic while we drain
+    if state.jobs.is_shutting_down() {
+        readiness.draining = true;
+        readiness.ready = false;
+    }
+
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+// =============================================================================
+// REST API
+// =============================================================================
+/// Body of `POST /api/research`.
+#[derive(Debug, Deserialize)]
+pub struct ResearchRequest {
+    pub query: String,
+
+    /// Just search, don't synthesize (same as the CLI's --quick, or
+    /// `"mode": "quick"`)
+    #[serde(default)]
+    pub quick: bool,
+
+    /// `mode`, `model`, `max_results` and `temperature`, all optional
+    #[serde(flatten)]
+    pub options: QueryOptions,
+}
+
+/// Response of `GET /api/models`.
+#[derive(Debug, Serialize)]
+pub struct ModelsResponse {
+    /// Models installed on the server's Ollama
+    pub models: Vec<String>,
+
+    /// What queries use unless they pick something else
+    pub default_model: String,
+    pub default_max_results: usize,
+    pub default_temperature: f32,
+}
+
+/// Successful response of `POST /api/research`.
+#[derive(Debug, Serialize)]
+pub struct ResearchResponse {
+    pub answer: String,
+
+    /// Search results the answer is based on; `[n]` in it cites `sources[n - 1]`
+    pub sources: Vec<Source>,
+}
+
+/// Error body returned by every REST endpoint.
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub code: &'static str,
+    pub message: String,
+    pub hint: Option<String>,
+}
+
+/// # Rust Concept: Implementing Foreign Traits for Local Types
+///
+/// `IntoResponse` comes from axum, `AgentError` is ours, so we're allowed
+/// to connect them. Handlers can now return `Result<_, AgentError>` and
+/// axum turns errors into the right HTTP status with a JSON body.
+impl IntoResponse for AgentError {
+    fn into_response(self) -> Response {
+        let body = ErrorResponse {
+            code: self.code(),
+            message: self.to_string(),
+            hint: self.hint(),
+        };
+        (self.http_status(), Json(body)).into_response()
+    }
+}
+
+/// Run a research (or quick search) query over plain HTTP.
+async fn research_handler(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Json(mut request): Json<ResearchRequest>,
+) -> Response {
+    if request.quick {
+        request.options.mode = ResearchMode::Quick;
+    }
+    let quick = request.options.is_quick();
+
+    if request.query.trim().is_empty() {
+        let body = ErrorResponse {
+            code: "invalid_request",
+            message: "query must not be empty".to_string(),
+            hint: None,
+        };
+        return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+    }
+
+    let scope = if quick { Scope::Search } else { Scope::Research };
+    if let Err(e) = state.auth.authorize(&principal, scope) {
+        info!(api_key = %principal.name, code = e.code(), "Request refused: {}", e);
+        return e.into_response();
+    }
+
+    let job = match state.jobs.start(&request.query, &request.options, &principal.name, None) {
+        Ok(job) => job,
+        Err(e) => return e.into_response(),
+    };
+
+    // Queue fairly per API key
+    let result = state
+        .run_query(&principal.name, None, &request.query, &request.options, |_| {}, None)
+        .await;
+    job.finish(&result);
+
+    match result {
+        Ok(outcome) => {
+            let sources = outcome.sources().into_iter().map(Source::from).collect();
+            Json(ResearchResponse {
+                answer: outcome.answer,
+                sources,
+            })
+            .into_response()
+        }
+        Err(e) => {
+            error!(code = e.code(), "Research failed: {}", e);
+            e.into_response()
+        }
+    }
+}
+
+/// Models a query may pick, and the server's defaults for the chat's
+/// settings drawer.
+async fn models_handler(State(state): State<Arc<AppState>>, _principal: Principal) -> Response {
+    match health::list_models(&state.config.ollama_host).await {
+        Ok(models) => Json(ModelsResponse {
+            models,
+            default_model: state.config.model.clone(),
+            default_max_results: state.config.max_search_results,
+            default_temperature: state.config.temperature,
+        })
+        .into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Icon of a source's domain, fetched once and cached (see `favicons`).
+async fn favicon_handler(
+    State(state): State<Arc<AppState>>,
+    _principal: Principal,
+    Path(domain): Path<String>,
+) -> Response {
+    match state.favicons.get(&domain).await {
+        Some(icon) => (
+            [
+                (header::CONTENT_TYPE, icon.content_type),
+                (header::CACHE_CONTROL, "public, max-age=86400".to_string()),
+                // Never sniffed or run as a document, whatever the bytes are
+                (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+                (header::CONTENT_SECURITY_POLICY, "default-src 'none'".to_string()),
+            ],
+            icon.bytes,
+        )
+            .into_response(),
+        None => not_found(format!("{} is not a public domain", domain)),
+    }
+}
+
+/// Query string of `POST /api/documents`.
+#[derive(Debug, Deserialize)]
+pub struct UploadParams {
+    /// Chat session the document is attached in; only that chat's
+    /// queries may search it
+    pub session: Option<String>,
+}
+
+/// Attach a document (multipart field `file`): extract its text and keep
+/// it for the chat's queries, which name it in `QueryOptions::documents`.
+///
+/// Extracting text (PDFs especially) is real work, so an upload counts
+/// against the key's quota like a research query.
+async fn documents_handler(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Query(params): Query<UploadParams>,
+    mut multipart: Multipart,
+) -> Response {
+    // Only research runs search documents
+    if let Err(e) = state.auth.authorize(&principal, Scope::Research) {
+        info!(api_key = %principal.name, code = e.code(), "Upload refused: {}", e);
+        return e.into_response();
+    }
+
+    let session = params.session;
+    if let Some(id) = &session {
+        if !state.sessions.is_owned_by(id, &principal.name) {
+            let body = ErrorResponse {
+                code: "invalid_request",
+                message: "unknown chat session".to_string(),
+                hint: Some("Reconnect the chat, then attach the file again".to_string()),
+            };
+            return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+        }
+    }
+
+    let field = loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) if field.name() == Some("file") => break field,
+            Ok(Some(_)) => continue,
+            Ok(None) => return invalid_document("expected a multipart field named \"file\"".to_string()),
+            Err(e) => return invalid_document(e.body_text()),
+        }
+    };
+    let name = field.file_name().unwrap_or("document").to_string();
+    let content_type = field.content_type().map(str::to_string);
+    let bytes = match field.bytes().await {
+        Ok(bytes) => bytes,
+        Err(e) => return invalid_document(e.body_text()),
+    };
+
+    // PDF parsing is CPU-bound; keep it off the async workers
+    let extracted = tokio::task::spawn_blocking(move || {
+        Document::from_upload(&name, content_type.as_deref(), &bytes)
+    })
+    .await;
+    match extracted {
+        Ok(Ok(document)) => {
+            let info = state.documents.insert(document, &principal.name, session.as_deref());
+            info!(document = %info.id, chars = info.chars, "Document attached");
+            Json(info).into_response()
+        }
+        Ok(Err(e)) => invalid_document(e.to_string()),
+        Err(e) => {
+            error!("Document extraction panicked: {}", e);
+            invalid_document("the file could not be read".to_string())
+        }
+    }
+}
+
+/// 400 for a file that can't be attached.
+fn invalid_document(message: String) -> Response {
+    let body = ErrorResponse {
+        code: "invalid_document",
+        message,
+        hint: Some("Attach text, Markdown, HTML or PDF files of up to 10 MB".to_string()),
+    };
+    (StatusCode::BAD_REQUEST, Json(body)).into_response()
+}
+
+/// Answer one MCP (JSON-RPC) message.
+///
+/// Tool calls are checked against the caller's API key scopes and quota
+/// like `/api/research`. Notifications get `202 Accepted` with no body.
+async fn mcp_handler(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    body: String,
+) -> Response {
+    let server = McpServer::for_web(state, principal);
+    match server.handle(&body).await {
+        Some(response) => ([(header::CONTENT_TYPE, "application/json")], response).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    }
+}
+
+/// Status of a running job, or the result of one resumed after a restart.
+///
+/// Only the key that submitted the job sees it; for others it doesn't exist.
+async fn job_handler(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<String>,
+) -> Response {
+    match state.jobs.status(&id, &principal.name) {
+        Some(status) => Json(status).into_response(),
+        None => not_found(format!("no job with id {}", id)),
+    }
+}
+
+/// 404 with the usual error body.
+fn not_found(message: String) -> Response {
+    let body = ErrorResponse {
+        code: "not_found",
+        message,
+        hint: None,
+    };
+    (StatusCode::NOT_FOUND, Json(body)).into_response()
+}
+
+// =============================================================================
+// HISTORY API
+// =============================================================================
+/// Query string of `GET /api/history`.
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    /// Only runs whose query or report contains this text
+    pub q: Option<String>,
+
+    #[serde(default = "default_history_limit")]
+    pub limit: usize,
+
+    #[serde(default)]
+    pub offset: usize,
+}
+
+fn default_history_limit() -> usize {
+    50
+}
+
+/// Run a history lookup off the async runtime, mapping failures to a response.
+async fn with_history<T, F>(state: &AppState, f: F) -> Result<T, Response>
+where
+    T: Send + 'static,
+    F: FnOnce(&History) -> anyhow::Result<T> + Send + 'static,
+{
+    let Some(history) = state.history.clone() else {
+        return Err(not_found(
+            "history is disabled on this server (save_history = false)".to_string(),
+        ));
+    };
+
+    let result = tokio::task::spawn_blocking(move || f(&history))
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|result| result);
+
+    result.map_err(|e| {
+        error!("History lookup failed: {:#}", e);
+        let body = ErrorResponse {
+            code: "history_error",
+            message: format!("{:#}", e),
+            hint: None,
+        };
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+    })
+}
+
+/// Whose runs `principal` may see: its own, or everyone's when the server
+/// has no API keys (a single-user setup, where CLI runs show up too).
+fn history_owner(state: &AppState, principal: &Principal) -> Option<String> {
+    state.auth.is_enabled().then(|| principal.name.clone())
+}
+
+/// Refuse a history request without `scope`.
+fn require_history_scope(principal: &Principal, scope: Scope) -> Result<(), Response> {
+    principal.require(scope).map_err(|e| {
+        info!(api_key = %principal.name, code = e.code(), "History request refused: {}", e);
+        e.into_response()
+    })
+}
+
+/// The caller's past runs, most recent first:
+/// `GET /api/history?q=rust&limit=20&offset=0`.
+async fn history_list_handler(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Query(query): Query<HistoryQuery>,
+) -> Response {
+    if let Err(response) = require_history_scope(&principal, Scope::Research) {
+        return response;
+    }
+
+    let limit = query.limit.min(500);
+    let owner = history_owner(&state, &principal);
+    let result = with_history(&state, move |history| {
+        history.list(query.q.as_deref(), owner.as_deref(), limit, query.offset)
+    })
+    .await;
+
+    match result {
+        Ok(runs) => Json(runs).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// One of the caller's past runs with its report, sources and transcript.
+/// Takes the full id; other keys' runs are 404.
+async fn history_run_handler(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<String>,
+) -> Response {
+    if let Err(response) = require_history_scope(&principal, Scope::Research) {
+        return response;
+    }
+
+    let (lookup, owner) = (id.clone(), history_owner(&state, &principal));
+    match with_history(&state, move |history| history.get_exact(&lookup, owner.as_deref())).await {
+        Ok(Some(run)) => Json(run).into_response(),
+        Ok(None) => not_found(format!("no run with id {}", id)),
+        Err(response) => response,
+    }
+}
+
+/// Delete one of the caller's past runs, by full id. Needs the `history`
+/// scope.
+async fn history_delete_handler(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<String>,
+) -> Response {
+    if let Err(response) = require_history_scope(&principal, Scope::History) {
+        return response;
+    }
+
+    let (lookup, owner) = (id.clone(), history_owner(&state, &principal));
+    match with_history(&state, move |history| history.delete_exact(&lookup, owner.as_deref())).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => not_found(format!("no run with id {}", id)),
+        Err(response) => response,
+    }
+}
+
+/// Run the jobs left unfinished by the previous run in the background.
+///
+/// Their clients are gone, so the results are kept for `/api/jobs/{id}`.
+pub fn resume_jobs(state: Arc<AppState>, jobs: Vec<PendingJob>) {
+    for job in jobs {
+        let state = state.clone();
+        let span = info_span!("resumed_job", job_id = %job.id);
+
+        tokio::spawn(
+            async move {
+                let (query, options, owner) = (job.query.clone(), job.options.clone(), job.owner.clone());
+                let Ok(guard) = state.jobs.resume(job) else {
+                    return;
+                };
+
+                // Run with the options it was submitted with, queued and
+                // recorded under the key that submitted it
+                let result = state.run_query(&owner, None, &query, &options, |_| {}, None).await;
+
+                if let Err(e) = &result {
+                    error!(code = e.code(), "Resumed job failed: {}", e);
+                }
+                guard.finish(&result);
+            }
+            .instrument(span),
+        );
+    }
+}
+
+/// Build the WebSocket error event for an `AgentError`.
+fn error_event(err: &AgentError) -> ServerEvent {
+    ServerEvent::Error {
+        code: err.code().to_string(),
+        message: err.to_string(),
+        hint: err.hint(),
+    }
+}
+
+// =============================================================================
+// WEBSOCKET
+// =============================================================================
+/// Query string of `/ws` (besides the API `token`).
+#[derive(Debug, Deserialize)]
+pub struct WsParams {
+    /// Session to resume after a reconnect
+    pub session: Option<String>,
+
+    /// Last event the client processed; everything after it is replayed
+    #[serde(default)]
+    pub last_seq: u64,
 }
 
 /// WebSocket upgrade handler
-async fn ws_handler(ws: WebSocketUpgrade) -> impl IntoResponse {
-    ws.on_upgrade(handle_socket)
+///
+/// The API key and the per-IP connection cap are checked during the
+/// handshake, so a refused client gets a 401/429 instead of an open socket.
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    principal: Principal,
+    Query(params): Query<WsParams>,
+) -> Response {
+    let slot = match state.connections.acquire(addr.ip()) {
+        Ok(slot) => slot,
+        Err(e) => {
+            info!(api_key = %principal.name, "Connection refused: {}", e);
+            return e.into_response();
+        }
+    };
+
+    let (session, resumed) = state.sessions.open(params.session.as_deref(), &principal.name);
+
+    // The socket is driven by a separate task after the upgrade, so we
+    // create its span here, while the `http_request` span is still current.
+    //
+    // Every log line for this connection (and the research runs it starts)
+    // carries the connection and session ids so a chat can be followed in
+    // the logs across reconnects.
+    let conn_id = Uuid::new_v4().to_string();
+    let span = info_span!(
+        parent: &Span::current(),
+        "ws_connection",
+        conn_id = %conn_id,
+        session_id = %session.id,
+        api_key = %principal.name
+    );
+
+    // Oversized frames fail in the reader, which closes the connection
+    let max_message = state.config.ws_max_message_bytes;
+    ws.max_message_size(max_message)
+        .max_frame_size(max_message)
+        .on_upgrade(move |socket| {
+            let connection = Connection {
+                principal,
+                session,
+                resumed,
+                last_seq: params.last_seq,
+                _slot: slot,
+            };
+            handle_socket(socket, state, connection).instrument(span)
+        })
+}
+
+/// Everything the handshake established for one WebSocket.
+struct Connection {
+    principal: Principal,
+    session: Arc<Session>,
+    resumed: bool,
+    last_seq: u64,
+
+    /// Counts against the client IP's connection cap until dropped
+    _slot: ConnectionSlot,
 }
 
 /// Handle individual WebSocket connections
-async fn handle_socket(socket: WebSocket) {
-    info!("New WebSocket connection established");
+///
+/// The connection only relays: queries run in tasks owned by the session
+/// (see `spawn_query`), and their events reach the client through the
+/// session's buffer, so nothing is lost when the socket drops.
+///
+/// The server pings every `ws_ping_interval_secs`; a connection that sends
+/// nothing - not even a pong - for `ws_idle_timeout_secs` is half-open and
+/// gets dropped. Every connection is also closed (with "going away") after
+/// `ws_max_lifetime_secs`, and the client reconnects to its session.
+async fn handle_socket(socket: WebSocket, state: Arc<AppState>, connection: Connection) {
+    let Connection {
+        principal,
+        session,
+        resumed,
+        last_seq,
+        _slot,
+    } = connection;
+    info!(resumed, last_seq, "New WebSocket connection established");
+    let _gauge = ConnectionGauge::track();
+
+    let config = &state.config;
+    let ping_every = Duration::from_secs(config.ws_ping_interval_secs);
+    let idle_timeout = Duration::from_secs(config.ws_idle_timeout_secs);
+    let max_lifetime = (config.ws_max_lifetime_secs > 0)
+        .then(|| Duration::from_secs(config.ws_max_lifetime_secs));
 
     let (mut sender, mut receiver) = socket.split();
 
-    // Create a channel for streaming responses
-    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    // Connection-level messages, which are not replayed on reconnect
+    let (tx, mut rx) = mpsc::channel::<String>(WS_SEND_BUFFER);
 
-    // Spawn task to send messages to client
+    // Events up to `last_seq` were processed before the disconnect
+    session.ack(last_seq);
+    let attachment = session.attach();
+
+    // Spawn task to send messages to client: first the session id, then
+    // the buffered events the client missed, then everything live
+    let send_session = session.clone();
+    let taken_over = attachment.taken_over.clone();
     let mut send_task = tokio::spawn(async move {
-        while let Some(msg) = rx.recv().await {
-            if sender.send(Message::Text(msg)).await.is_err() {
-                break;
+        let hello = ServerEvent::Session {
+            session_id: send_session.id.clone(),
+            resumed,
+        };
+        if sender.send(Message::Text(hello.to_json())).await.is_err() {
+            return;
+        }
+
+        // Subscribe before the first read so no event slips in between
+        let mut latest = send_session.subscribe();
+        let mut sent = last_seq;
+        let mut notices_open = true;
+
+        let start = tokio::time::Instant::now();
+        let mut ping = tokio::time::interval_at(start + ping_every, ping_every);
+        let lifetime = tokio::time::sleep(max_lifetime.unwrap_or_default());
+        tokio::pin!(lifetime);
+
+        loop {
+            for (seq, json) in send_session.events_after(sent) {
+                if sender.send(Message::Text(json)).await.is_err() {
+                    return;
+                }
+                sent = seq;
+            }
+
+            tokio::select! {
+                _ = taken_over.cancelled() => {
+                    info!("Session taken over by a newer connection");
+                    let _ = sender.send(Message::Close(None)).await;
+                    return;
+                }
+                msg = rx.recv(), if notices_open => match msg {
+                    Some(msg) => {
+                        if sender.send(Message::Text(msg)).await.is_err() {
+                            return;
+                        }
+                    }
+                    // The shutdown notice was sent; keep relaying events
+                    None => notices_open = false,
+                },
+                changed = latest.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                }
+                _ = ping.tick() => {
+                    if sender.send(Message::Ping(Vec::new())).await.is_err() {
+                        return;
+                    }
+                }
+                _ = &mut lifetime, if max_lifetime.is_some() => {
+                    info!("Connection reached its maximum lifetime");
+                    let close = CloseFrame {
+                        code: close_code::AWAY,
+                        reason: "Connection lifetime exceeded, please reconnect".into(),
+                    };
+                    let _ = sender.send(Message::Close(Some(close))).await;
+                    return;
+                }
             }
         }
-    });
+    }.in_current_span());
+
+    // Warn the client when the server starts shutting down
+    let notice_state = state.clone();
+    let notice_session = session.id.clone();
+    let notice_owner = principal.name.clone();
+    let notice_task = tokio::spawn(async move {
+        notice_state.jobs.shutdown_requested().await;
+
+        let job_id = notice_state
+            .jobs
+            .jobs_for_client(&notice_session, &notice_owner)
+            .into_iter()
+            .next();
+        let message = match &job_id {
+            Some(_) => format!(
+                "The server is restarting. Your current query will finish if it can within \
+                 {}s, otherwise it will be resumed after the restart.",
+                notice_state.config.shutdown_grace_secs
+            ),
+            None => "The server is restarting. Please reconnect in a few seconds.".to_string(),
+        };
+        let _ = tx.send(ServerEvent::ShuttingDown { message, job_id }.to_json()).await;
+    }.in_current_span());
 
     // Handle incoming messages
+    let recv_session = session.clone();
     let mut recv_task = tokio::spawn(async move {
-        while let Some(Ok(Message::Text(query))) = receiver.next().await {
-            info!("Received query: {}", query);
-
-            // Load config and create agent
-            let config = match Config::from_env() {
-                Ok(c) => c,
-                Err(e) => {
-                    error!("Failed to load config: {}", e);
-                    let _ = tx.send(format!("Error: Failed to load configuration - {}", e));
+        loop {
+            // Any frame counts as a sign of life, including pongs
+            let frame = match tokio::time::timeout(idle_timeout, receiver.next()).await {
+                Err(_) => {
+                    info!("No traffic for {}s, closing idle connection", idle_timeout.as_secs());
+                    break;
+                }
+                Ok(None) => break,
+                Ok(Some(Err(e))) => {
+                    // Includes messages over ws_max_message_bytes
+                    info!("WebSocket error: {}", e);
+                    break;
+                }
+                Ok(Some(Ok(message))) => message,
+            };
+
+            let frame = match frame {
+                Message::Text(frame) => frame,
+                Message::Close(_) => break,
+                // Pings are answered by axum; pongs only reset the idle timer
+                Message::Ping(_) | Message::Pong(_) | Message::Binary(_) => continue,
+            };
+
+            let (query, options) = match ClientMessage::parse(&frame) {
+                ClientMessage::Ack { seq } => {
+                    recv_session.ack(seq);
+                    continue;
+                }
+                ClientMessage::Cancel => {
+                    info!("Queries cancelled by the client");
+                    recv_session.cancel_queries();
                     continue;
                 }
+                ClientMessage::Query { text, options } => (text, options),
             };
+            info!(query_hash = %crate::agent::query_hash(&query), "Received query");
 
-            if let Err(e) = config.validate() {
+            if let Err(e) = state.config.validate() {
                 error!("Invalid config: {}", e);
-                let _ = tx.send(format!("Error: Invalid configuration - {}", e));
+                recv_session.push(error_event(&e));
                 continue;
             }
 
-            let agent = ResearchAgent::new(config);
-
-            // Perform research
-            match agent.research(&query).await {
-                Ok(response) => {
-                    // Send response back to client
-                    if tx.send(response).is_err() {
-                        error!("Failed to send response to client");
-                        break;
-                    }
-                }
-                Err(e) => {
-                    error!("Research failed: {}", e);
-                    let _ = tx.send(format!("Error: Research failed - {}", e));
-                }
+            // Every query counts against the key's quota
+            let scope = if options.is_quick() { Scope::Search } else { Scope::Research };
+            if let Err(e) = state.auth.authorize(&principal, scope) {
+                info!(code = e.code(), "Query refused: {}", e);
+                recv_session.push(error_event(&e));
+                continue;
             }
+
+            spawn_query(state.clone(), recv_session.clone(), principal.name.clone(), query, options);
         }
-    });
+    }.in_current_span());
 
     // Wait for either task to finish
     tokio::select! {
         _ = (&mut send_task) => recv_task.abort(),
         _ = (&mut recv_task) => send_task.abort(),
     }
+    notice_task.abort();
+    session.detach(&attachment);
 
     info!("WebSocket connection closed");
-}
\ No newline at end of file
+}
+
+/// Run a chat query in the background, reporting to its session.
+///
+/// The task holds its own reference to the session, so the query keeps
+/// running - and its answer stays buffered - while the client is away.
+/// A `cancel` from the client drops it, wherever it is: waiting for its
+/// turn, queued for a slot or talking to the model.
+///
+/// `client` is the API key name the query is recorded under, like queries
+/// over REST; it is queued per session.
+fn spawn_query(
+    state: Arc<AppState>,
+    session: Arc<Session>,
+    client: String,
+    query: String,
+    options: QueryOptions,
+) {
+    // Taken now, so a stop also covers queries still waiting for their turn
+    let cancelled = session.query_token();
+
+    tokio::spawn(
+        async move {
+            tokio::select! {
+                _ = answer_query(&state, &session, &client, &query, &options) => {}
+                _ = cancelled.cancelled() => {
+                    info!("Query cancelled");
+                    session.push(ServerEvent::Cancelled);
+                }
+            }
+        }
+        .in_current_span(),
+    );
+}
+
+/// Run a chat query and push its outcome to the session.
+async fn answer_query(
+    state: &AppState,
+    session: &Arc<Session>,
+    client: &str,
+    query: &str,
+    options: &QueryOptions,
+) {
+    let _turn = session.turn.lock().await;
+
+    // Registered until the answer is pushed, so shutdown waits for it
+    let _job = match state.jobs.start(query, options, client, Some(&session.id)) {
+        Ok(job) => job,
+        Err(e) => {
+            session.push(error_event(&e));
+            return;
+        }
+    };
+
+    let queue_session = session.clone();
+    let on_position = move |position| {
+        queue_session.push(ServerEvent::Queued { position });
+    };
+    let activity_session = session.clone();
+    let activity = ActivitySink::new(move |step| {
+        activity_session.push(ServerEvent::Activity { step });
+    });
+
+    let result = state
+        .run_query(client, Some(&session.id), query, options, on_position, Some(activity))
+        .await;
+    match result {
+        Ok(outcome) => {
+            let sources: Vec<Source> = outcome.sources().into_iter().map(Source::from).collect();
+            if !sources.is_empty() {
+                session.push(ServerEvent::Sources { sources });
+            }
+            let meta = outcome.meta();
+            session.push(ServerEvent::Answer {
+                text: outcome.answer,
+                run_id: outcome.run_id,
+                meta: Some(meta),
+            });
+        }
+        Err(e) => {
+            error!(code = e.code(), "Research failed: {}", e);
+            session.push(error_event(&e));
+        }
+    }
+}
@@ -0,0 +1,337 @@
+// Dimensionless Developments Rust Ai
+// # Sessions Module
+// Server-side chat sessions that outlive a single WebSocket connection.
+//
+// Before sessions, a query ran inside the socket's task: if the browser's
+// connection dropped (Wi-Fi blip, laptop lid, page reload) the answer in
+// progress was lost. Now:
+//
+// - Each chat has a session id, sent to the client in a `session` event.
+// - Queries run in tasks owned by the session, not by the socket.
+// - Every event for the session gets a sequence number and is buffered
+//   until the client acknowledges it (`{"type": "ack", "seq": n}`).
+// - A client reconnecting with `/ws?session=<id>&last_seq=<n>` gets every
+//   buffered event after `n`, then continues live.
+//
+// Sessions nobody is attached to are dropped `session_ttl_secs` after
+// their last activity, once their running queries are done.
+//
+// A `{"type": "cancel"}` from the client stops the session's running query
+// and the ones waiting behind it; each of them reports `cancelled`.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::protocol::{Envelope, ServerEvent};
+
+/// Unacknowledged events kept per session. A client that falls further
+/// behind than this misses the oldest ones.
+const SESSION_EVENT_BUFFER: usize = 256;
+
+// =============================================================================
+// SESSION
+// =============================================================================
+/// Everything behind a session's lock.
+struct SessionState {
+    next_seq: u64,
+
+    /// Sequenced events not acknowledged yet, oldest first, as JSON
+    buffer: VecDeque<(u64, String)>,
+
+    /// The attached connection, if any
+    attachment: Option<Attachment>,
+    next_attachment: u64,
+
+    /// Last event, attach or detach; drives expiry
+    last_activity: Instant,
+
+    /// Watched by the queries started so far; replaced when cancelled,
+    /// so the next query starts afresh
+    queries: CancellationToken,
+}
+
+/// A connection's hold on a session.
+#[derive(Clone)]
+pub struct Attachment {
+    id: u64,
+
+    /// Cancelled when another connection takes the session over
+    pub taken_over: CancellationToken,
+}
+
+/// One chat, shared by its queries and the connection attached to it.
+pub struct Session {
+    pub id: String,
+
+    /// API key name that created the session; only it may resume it
+    owner: String,
+
+    state: Mutex<SessionState>,
+
+    /// Latest sequence number, so the attached connection wakes up on new events
+    latest: watch::Sender<u64>,
+
+    /// Queries of one session run one after the other, like they did on
+    /// a single connection
+    pub turn: tokio::sync::Mutex<()>,
+}
+
+impl Session {
+    fn new(owner: &str) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            owner: owner.to_string(),
+            state: Mutex::new(SessionState {
+                next_seq: 1,
+                buffer: VecDeque::new(),
+                attachment: None,
+                next_attachment: 0,
+                last_activity: Instant::now(),
+                queries: CancellationToken::new(),
+            }),
+            latest: watch::channel(0).0,
+            turn: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Buffer an event for the client and return its sequence number.
+    pub fn push(&self, event: ServerEvent) -> u64 {
+        let mut state = self.lock();
+        let seq = state.next_seq;
+        state.next_seq += 1;
+
+        let json = Envelope::sequenced(seq, event).to_json();
+        state.buffer.push_back((seq, json));
+        if state.buffer.len() > SESSION_EVENT_BUFFER {
+            state.buffer.pop_front();
+        }
+        state.last_activity = Instant::now();
+        drop(state);
+
+        self.latest.send_replace(seq);
+        seq
+    }
+
+    /// The client has processed every event up to `seq`; forget them.
+    pub fn ack(&self, seq: u64) {
+        let mut state = self.lock();
+        while state.buffer.front().is_some_and(|(s, _)| *s <= seq) {
+            state.buffer.pop_front();
+        }
+    }
+
+    /// Buffered events after `seq`, oldest first.
+    pub fn events_after(&self, seq: u64) -> Vec<(u64, String)> {
+        self.lock()
+            .buffer
+            .iter()
+            .filter(|(s, _)| *s > seq)
+            .cloned()
+            .collect()
+    }
+
+    /// Wakes up whenever an event is pushed.
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.latest.subscribe()
+    }
+
+    /// Attach a connection, taking the session over from the previous one.
+    pub fn attach(&self) -> Attachment {
+        let mut state = self.lock();
+        state.next_attachment += 1;
+        let attachment = Attachment {
+            id: state.next_attachment,
+            taken_over: CancellationToken::new(),
+        };
+
+        if let Some(previous) = state.attachment.replace(attachment.clone()) {
+            previous.taken_over.cancel();
+        }
+        state.last_activity = Instant::now();
+        attachment
+    }
+
+    /// Detach a connection, unless another one already took over.
+    pub fn detach(&self, attachment: &Attachment) {
+        let mut state = self.lock();
+        if state.attachment.as_ref().is_some_and(|current| current.id == attachment.id) {
+            state.attachment = None;
+        }
+        state.last_activity = Instant::now();
+    }
+
+    /// Token for a new query; cancelled by `cancel_queries`.
+    pub fn query_token(&self) -> CancellationToken {
+        self.lock().queries.clone()
+    }
+
+    /// Stop every query started so far, running or waiting for its turn.
+    /// Queries started afterwards aren't affected.
+    pub fn cancel_queries(&self) {
+        let cancelled = std::mem::replace(&mut self.lock().queries, CancellationToken::new());
+        cancelled.cancel();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SessionState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// =============================================================================
+// SESSION STORE
+// =============================================================================
+/// All live sessions on the server.
+pub struct SessionStore {
+    sessions: Mutex<HashMap<String, Arc<Session>>>,
+    ttl: Duration,
+}
+
+impl SessionStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    /// Resume session `id` for `owner`, or start a new one when there is
+    /// no such session (expired, server restarted, someone else's).
+    ///
+    /// Returns the session and whether it was resumed.
+    pub fn open(&self, id: Option<&str>, owner: &str) -> (Arc<Session>, bool) {
+        let mut sessions = self.lock();
+        self.prune(&mut sessions);
+
+        let existing = id
+            .and_then(|id| sessions.get(id))
+            .filter(|session| session.owner == owner);
+        if let Some(session) = existing {
+            return (session.clone(), true);
+        }
+
+        let session = Arc::new(Session::new(owner));
+        sessions.insert(session.id.clone(), session.clone());
+        (session, false)
+    }
+
+    /// Whether session `id` is live and belongs to `owner`.
+    pub fn is_owned_by(&self, id: &str, owner: &str) -> bool {
+        self.lock().get(id).is_some_and(|session| session.owner == owner)
+    }
+
+    /// Drop idle sessions.
+    ///
+    /// # Rust Concept: Reference Counting as Liveness
+    ///
+    /// Connections and running queries each hold an `Arc<Session>`. When
+    /// the store's own reference is the only one left, nothing can push
+    /// to the session any more, so it is safe to forget once it expires.
+    fn prune(&self, sessions: &mut HashMap<String, Arc<Session>>) {
+        sessions.retain(|_, session| {
+            Arc::strong_count(session) > 1 || session.lock().last_activity.elapsed() < self.ttl
+        });
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Arc<Session>>> {
+        self.sessions.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// =============================================================================
+// UNIT TESTS
+// =============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(text: &str) -> ServerEvent {
+        ServerEvent::Answer {
+            text: text.to_string(),
+            run_id: None,
+            meta: None,
+        }
+    }
+
+    #[test]
+    fn test_events_are_sequenced_and_acked() {
+        let store = SessionStore::new(Duration::from_secs(60));
+        let (session, resumed) = store.open(None, "anonymous");
+        assert!(!resumed);
+
+        assert_eq!(session.push(answer("a")), 1);
+        assert_eq!(session.push(answer("b")), 2);
+        assert_eq!(session.push(answer("c")), 3);
+
+        session.ack(1);
+        let pending: Vec<u64> = session.events_after(0).iter().map(|(seq, _)| *seq).collect();
+        assert_eq!(pending, [2, 3]);
+        assert_eq!(session.events_after(2).len(), 1);
+        assert!(session.events_after(2)[0].1.contains("\"seq\":3"));
+    }
+
+    #[test]
+    fn test_resume_checks_owner() {
+        let store = SessionStore::new(Duration::from_secs(60));
+        let (session, _) = store.open(None, "team");
+
+        let (same, resumed) = store.open(Some(&session.id), "team");
+        assert!(resumed);
+        assert_eq!(same.id, session.id);
+
+        let (other, resumed) = store.open(Some(&session.id), "ci");
+        assert!(!resumed);
+        assert_ne!(other.id, session.id);
+    }
+
+    #[test]
+    fn test_idle_sessions_expire() {
+        let store = SessionStore::new(Duration::ZERO);
+        let (session, _) = store.open(None, "anonymous");
+        let id = session.id.clone();
+
+        // Still referenced (e.g. by a running query): kept
+        let (_, resumed) = store.open(Some(&id), "anonymous");
+        assert!(resumed);
+
+        drop(session);
+        let (_, resumed) = store.open(Some(&id), "anonymous");
+        assert!(!resumed);
+    }
+
+    #[test]
+    fn test_attach_cancels_previous_connection() {
+        let store = SessionStore::new(Duration::from_secs(60));
+        let (session, _) = store.open(None, "anonymous");
+
+        let first = session.attach();
+        let second = session.attach();
+        assert!(first.taken_over.is_cancelled());
+        assert!(!second.taken_over.is_cancelled());
+
+        // The replaced connection detaching must not detach the new one
+        session.detach(&first);
+        assert!(session.lock().attachment.is_some());
+        session.detach(&second);
+        assert!(session.lock().attachment.is_none());
+    }
+
+    #[test]
+    fn test_cancel_stops_earlier_queries_only() {
+        let store = SessionStore::new(Duration::from_secs(60));
+        let (session, _) = store.open(None, "anonymous");
+
+        let running = session.query_token();
+        let waiting = session.query_token();
+        session.cancel_queries();
+        assert!(running.is_cancelled());
+        assert!(waiting.is_cancelled());
+
+        let next = session.query_token();
+        assert!(!next.is_cancelled());
+    }
+}
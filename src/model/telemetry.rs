@@ -0,0 +1,87 @@
+// Dimensionless Developments Rust Ai
+// # Telemetry Module
+// Exports tracing spans to an OpenTelemetry collector over OTLP (gRPC).
+// Only compiled with `--features otel`.
+//
+// The spans themselves are ordinary `tracing` spans created elsewhere:
+// - `research`        (agent.rs)  - one per research run
+// - `llm_completion`  (agent.rs)  - one per model call inside a run
+// - `web_search`      (tools.rs)  - one per DuckDuckGo request
+// - `http_request`    (server.rs) - one per incoming HTTP request
+//
+// This module just adds a tracing layer that forwards them to OTLP, and
+// reads W3C `traceparent` headers so our spans join the caller's trace.
+
+use anyhow::{Context, Result};
+use axum::http::HeaderMap;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, KeyValue};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use tracing::Span;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Service name reported to the collector.
+const SERVICE_NAME: &str = "ai-research-agent";
+
+/// Flushes and shuts down the exporter when dropped.
+///
+/// # Rust Concept: RAII (Drop)
+///
+/// Spans are exported in batches in the background. Keeping this guard
+/// alive until the end of `main` and letting `Drop` run guarantees the
+/// last batch is sent before the process exits.
+pub struct TelemetryGuard {
+    provider: TracerProvider,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.provider.shutdown() {
+            eprintln!("Failed to flush OpenTelemetry spans: {}", e);
+        }
+    }
+}
+
+/// Build the OTLP tracing layer for `endpoint` (e.g. `http://localhost:4317`).
+pub fn otlp_layer<S>(endpoint: &str) -> Result<(OpenTelemetryLayer<S, Tracer>, TelemetryGuard)>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()
+        .with_context(|| format!("Failed to create OTLP exporter for {}", endpoint))?;
+
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new(vec![
+            KeyValue::new("service.name", SERVICE_NAME),
+            KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+        ]))
+        .build();
+
+    let tracer = provider.tracer(SERVICE_NAME);
+
+    // Understand (and emit) W3C trace context headers
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    global::set_tracer_provider(provider.clone());
+
+    let layer = tracing_opentelemetry::layer().with_tracer(tracer);
+    Ok((layer, TelemetryGuard { provider }))
+}
+
+/// Make `span` a child of the trace described by incoming request headers.
+///
+/// If the request has no `traceparent` header the span starts a new trace.
+pub fn set_remote_parent(span: &Span, headers: &HeaderMap) {
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(headers))
+    });
+    span.set_parent(parent);
+}
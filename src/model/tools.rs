@@ -1,6 +1,8 @@
 // Dimensionless Developments Rust Ai
 // # Tools Module
-// This module implements the web search tool using DuckDuckGo.
+// This module implements the web search tool using DuckDuckGo, a tool
+// that fetches the text of a web page, and one that searches the documents
+// attached to a conversation.
 // It demonstrates several important Rust and async patterns:
 // - Trait implementation (Rig's Tool trait)
 // - Async/await for non-blocking I/O
@@ -10,9 +12,18 @@
 use rig::completion::ToolDefinition;
 use rig::tool::Tool;
 use serde::{Deserialize, Deserializer, Serialize};
-use std::time::Duration;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use tracing::{debug, info, warn};
+use tracing::{debug, field, info, instrument, warn, Span};
+
+use crate::agent::query_hash;
+use crate::documents::{self, Document};
+use crate::monitoring;
+use crate::protocol::{ActivityStep, Source};
 
 // =============================================================================
 // CUSTOM ERROR TYPE
@@ -46,6 +57,27 @@ pub enum SearchError {
     NetworkError(#[from] reqwest::Error),
 }
 
+impl SearchError {
+    /// Short label for metrics (`search_failures_total{reason=...}`).
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SearchError::SearchFailed(_) => "search_failed",
+            SearchError::RateLimited => "rate_limited",
+            SearchError::NoResults(_) => "no_results",
+            SearchError::NetworkError(_) => "network",
+        }
+    }
+}
+
+/// When the last search succeeded, shared by every WebSearchTool.
+///
+/// `/readyz` uses this to report the search provider as healthy without
+/// sending DuckDuckGo an extra request (probes would get us rate limited).
+static LAST_SEARCH_SUCCESS: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Base URL of the DuckDuckGo HTML endpoint.
+pub const DUCKDUCKGO_URL: &str = "https://html.duckduckgo.com/html/";
+
 // =============================================================================
 // SEARCH RESULT STRUCT
 // =============================================================================
@@ -69,6 +101,65 @@ pub struct SearchResult {
     pub snippet: String,
 }
 
+impl From<SearchResult> for Source {
+    fn from(result: SearchResult) -> Self {
+        Source {
+            title: result.title,
+            url: result.url,
+            snippet: result.snippet,
+        }
+    }
+}
+
+/// One call the agent made to a tool, kept for the run's transcript.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCallRecord {
+    /// Tool name, e.g. `web_search`
+    pub tool: String,
+
+    /// The query the model asked for
+    pub query: String,
+
+    /// What the tool found (empty when it failed)
+    pub results: Vec<SearchResult>,
+
+    /// Why the call failed, if it did
+    pub error: Option<String>,
+}
+
+/// Shared list the tool appends its calls to.
+///
+/// Rig clones the tool into the agent, so the list is behind an `Arc`:
+/// the caller keeps one handle and reads it after the run.
+pub type ToolCallLog = Arc<Mutex<Vec<ToolCallRecord>>>;
+
+/// Where tools report what they are doing while they do it, so a chat
+/// client can show "Searching: ..." instead of an empty bubble.
+///
+/// # Rust Concept: Trait Objects
+///
+/// `dyn Fn(ActivityStep)` is "any closure taking a step". The web server
+/// passes one that pushes into the chat session; the tools don't need to
+/// know about sessions or WebSockets at all.
+#[derive(Clone)]
+pub struct ActivitySink(Arc<dyn Fn(ActivityStep) + Send + Sync>);
+
+impl ActivitySink {
+    pub fn new(report: impl Fn(ActivityStep) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(report))
+    }
+
+    pub fn report(&self, step: ActivityStep) {
+        (self.0)(step)
+    }
+}
+
+impl fmt::Debug for ActivitySink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ActivitySink")
+    }
+}
+
 // =============================================================================
 // WEB SEARCH TOOL
 // =============================================================================
@@ -82,6 +173,14 @@ pub struct SearchResult {
 pub struct WebSearchTool {
     /// Maximum results to return per search
     max_results: usize,
+
+    /// Where calls made by the agent are recorded, if anywhere
+    #[serde(skip)]
+    call_log: Option<ToolCallLog>,
+
+    /// Where searches are reported as they happen, if anywhere
+    #[serde(skip)]
+    activity: Option<ActivitySink>,
 }
 
 impl WebSearchTool {
@@ -101,7 +200,71 @@ impl WebSearchTool {
     /// let search_tool = WebSearchTool::new(5);
     /// ```
     pub fn new(max_results: usize) -> Self {
-        Self { max_results }
+        Self {
+            max_results,
+            call_log: None,
+            activity: None,
+        }
+    }
+
+    /// Record every call made through the `Tool` trait into `log`.
+    pub fn with_call_log(mut self, log: ToolCallLog) -> Self {
+        self.call_log = Some(log);
+        self
+    }
+
+    /// Report every search to `activity` as it starts and finishes.
+    pub fn with_activity(mut self, activity: ActivitySink) -> Self {
+        self.activity = Some(activity);
+        self
+    }
+
+    /// Search on behalf of the agent (or a quick search): like `search()`,
+    /// but reported to the activity sink and recorded in the call log.
+    pub async fn search_reported(&self, query: &str) -> Result<Vec<SearchResult>, SearchError> {
+        if let Some(activity) = &self.activity {
+            activity.report(ActivityStep::Searching {
+                query: query.to_string(),
+            });
+        }
+
+        let outcome = self.search(query).await;
+
+        if let Some(activity) = &self.activity {
+            activity.report(ActivityStep::Searched {
+                query: query.to_string(),
+                results: outcome.as_ref().map(Vec::len).unwrap_or(0),
+                error: outcome.as_ref().err().map(ToString::to_string),
+            });
+        }
+        self.log_call(query, &outcome);
+        outcome
+    }
+
+    /// Number of each result among all sources of the run so far: first
+    /// seen first, each URL once (the order of `ResearchOutcome::sources`).
+    ///
+    /// The model sees these numbers and cites them as `[n]`, so a citation
+    /// points at the same card whichever search found the result.
+    fn source_numbers(&self, results: &[SearchResult]) -> Vec<usize> {
+        source_numbers(self.call_log.as_ref(), results)
+    }
+
+    /// Append a call to the log, if there is one.
+    fn log_call(&self, query: &str, outcome: &Result<Vec<SearchResult>, SearchError>) {
+        let Some(log) = &self.call_log else {
+            return;
+        };
+
+        let record = ToolCallRecord {
+            tool: Self::NAME.to_string(),
+            query: query.to_string(),
+            results: outcome.as_ref().map(Vec::clone).unwrap_or_default(),
+            error: outcome.as_ref().err().map(ToString::to_string),
+        };
+        if let Ok(mut log) = log.lock() {
+            log.push(record);
+        }
     }
 
     /// Perform a web search using DuckDuckGo.
@@ -111,24 +274,51 @@ impl WebSearchTool {
     /// `async fn` defines a function that can be paused and resumed.
     /// Inside async functions, you use `.await` to wait for async operations.
     /// This allows efficient handling of I/O without blocking threads.
+    ///
+    /// The call runs in a `web_search` span recording the provider, the
+    /// HTTP status and the number of results, for tracing backends.
+    #[instrument(
+        name = "web_search",
+        skip_all,
+        fields(
+            provider = "duckduckgo",
+            http.status = field::Empty,
+            results = field::Empty,
+        )
+    )]
     pub async fn search(&self, query: &str) -> Result<Vec<SearchResult>, SearchError> {
-        info!(query = %query, "Performing web search");
+        info!(query_hash = %query_hash(query), "Performing web search");
 
         // Rate limiting: wait a bit before making the request
         tokio::time::sleep(Duration::from_millis(500)).await;
 
         // Use DuckDuckGo HTML search
-        let results = self.search_duckduckgo(query).await?;
+        let outcome = self.search_duckduckgo(query).await;
+        monitoring::record_search("duckduckgo", outcome.as_ref().err().map(SearchError::kind));
+        let results = outcome?;
+
+        if let Ok(mut last) = LAST_SEARCH_SUCCESS.lock() {
+            *last = Some(Instant::now());
+        }
+        Span::current().record("results", results.len());
 
         if results.is_empty() {
-            warn!(query = %query, "No search results found");
+            warn!(query_hash = %query_hash(query), "No search results found");
         } else {
-            info!(query = %query, count = results.len(), "Search completed");
+            info!(query_hash = %query_hash(query), count = results.len(), "Search completed");
         }
 
         Ok(results)
     }
 
+    /// How long ago the last successful search (by any tool instance) was.
+    pub fn last_success_age() -> Option<Duration> {
+        LAST_SEARCH_SUCCESS
+            .lock()
+            .ok()
+            .and_then(|last| last.map(|at| at.elapsed()))
+    }
+
     /// Internal method to perform DuckDuckGo search via HTML scraping.
     ///
     /// Note: We use HTML scraping because DuckDuckGo doesn't have a free web search API.
@@ -139,14 +329,13 @@ impl WebSearchTool {
             .user_agent("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
             .build()?;
 
-        let url = format!(
-            "https://html.duckduckgo.com/html/?q={}",
-            urlencoding::encode(query)
-        );
+        let url = format!("{}?q={}", DUCKDUCKGO_URL, urlencoding::encode(query));
 
-        debug!(url = %url, "Fetching search results");
+        // The URL carries the query, so only the endpoint is logged
+        debug!(endpoint = DUCKDUCKGO_URL, "Fetching search results");
 
         let response = client.get(&url).send().await?;
+        Span::current().record("http.status", response.status().as_u16());
 
         if !response.status().is_success() {
             if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
@@ -270,6 +459,27 @@ impl WebSearchTool {
     }
 }
 
+/// Numbers of `results` among all sources recorded in `log` (see
+/// `WebSearchTool::source_numbers`); 1, 2, 3... without a log.
+fn source_numbers(log: Option<&ToolCallLog>, results: &[SearchResult]) -> Vec<usize> {
+    let Some(log) = log else {
+        return (1..=results.len()).collect();
+    };
+
+    let log = log.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut order: Vec<&str> = Vec::new();
+    for result in log.iter().flat_map(|call| call.results.iter()) {
+        if !order.contains(&result.url.as_str()) {
+            order.push(&result.url);
+        }
+    }
+
+    results
+        .iter()
+        .map(|result| order.iter().position(|url| *url == result.url).map_or(0, |i| i + 1))
+        .collect()
+}
+
 /// Extract the domain name from a URL.
 fn extract_domain(url: &str) -> Option<String> {
     url.split("//")
@@ -383,21 +593,22 @@ impl Tool for WebSearchTool {
     ///
     /// Note: In Rig 0.27, call() only takes &self and args (no state parameter).
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        debug!("Web search tool called with query: {}", args.query);
+        debug!(query_hash = %query_hash(&args.query), "Web search tool called");
         
-        let results = self.search(&args.query).await?;
+        let results = self.search_reported(&args.query).await?;
 
         if results.is_empty() {
             return Ok(format!("No results found for: {}", args.query));
         }
 
+        let numbers = self.source_numbers(&results);
         let formatted: String = results
             .iter()
-            .enumerate()
-            .map(|(i, r)| {
+            .zip(numbers)
+            .map(|(r, n)| {
                 format!(
-                    "{}. **{}**\n   URL: {}\n   {}\n",
-                    i + 1,
+                    "[{}] **{}**\n   URL: {}\n   {}\n",
+                    n,
                     r.title,
                     r.url,
                     r.snippet
@@ -413,6 +624,497 @@ impl Tool for WebSearchTool {
     }
 }
 
+// =============================================================================
+// FETCH PAGE TOOL
+// =============================================================================
+/// Redirects followed before a fetch gives up.
+const MAX_REDIRECTS: usize = 5;
+
+/// Most of a page body that is downloaded, in bytes; the rest is dropped
+/// unread, since the text is truncated anyway.
+const MAX_PAGE_BYTES: usize = 2 * 1024 * 1024;
+
+/// Errors from fetching a web page.
+#[derive(Error, Debug)]
+pub enum FetchError {
+    #[error("Not a public http(s) URL: {0}")]
+    InvalidUrl(String),
+
+    #[error("Could not resolve {0}")]
+    Unresolvable(String),
+
+    #[error("Too many redirects")]
+    TooManyRedirects,
+
+    #[error("No more pages may be read for this question; answer with what you have")]
+    PageLimit,
+
+    #[error("The page answered HTTP {0}")]
+    Status(u16),
+
+    #[error("Unsupported content type: {0}")]
+    UnsupportedContent(String),
+
+    #[error("Network error: {0}")]
+    NetworkError(#[from] reqwest::Error),
+}
+
+/// Arguments of the fetch tool: `{"url": "https://..."}`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FetchArgs {
+    pub url: String,
+}
+
+/// A tool that downloads a web page and returns its readable text.
+///
+/// Search results only carry a title and a domain; this lets a caller read
+/// the page behind one. Scripts, styles and tags are stripped and the text
+/// is truncated to `max_chars`, so a large page can't flood a context window.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchPageTool {
+    /// Longest text returned, in characters
+    max_chars: usize,
+
+    /// Where page reads are reported as they happen, if anywhere
+    #[serde(skip)]
+    activity: Option<ActivitySink>,
+
+    /// Page reads left, if limited; clones share the count
+    #[serde(skip)]
+    pages_left: Option<Arc<AtomicUsize>>,
+}
+
+impl FetchPageTool {
+    pub fn new(max_chars: usize) -> Self {
+        Self {
+            max_chars,
+            activity: None,
+            pages_left: None,
+        }
+    }
+
+    /// Allow `pages` reads (failed ones included), then refuse with
+    /// `PageLimit`. A model that keeps reading never gets to answer.
+    pub fn with_page_limit(mut self, pages: usize) -> Self {
+        self.pages_left = Some(Arc::new(AtomicUsize::new(pages)));
+        self
+    }
+
+    /// Report every page read to `activity` as it starts and finishes.
+    pub fn with_activity(mut self, activity: ActivitySink) -> Self {
+        self.activity = Some(activity);
+        self
+    }
+
+    /// Download `url` and return its text content.
+    ///
+    /// Every hop - the URL and each redirect - must resolve to public
+    /// addresses only, and the connection goes to exactly the addresses
+    /// that were checked, so neither a redirect nor a DNS answer that
+    /// changes in between can point the request into the server's network.
+    #[instrument(name = "fetch_page", skip_all, fields(http.status = field::Empty))]
+    pub async fn fetch(&self, url: &str) -> Result<String, FetchError> {
+        let mut current =
+            reqwest::Url::parse(url).map_err(|_| FetchError::InvalidUrl(url.to_string()))?;
+
+        let mut redirects = 0;
+        let mut response = loop {
+            let client = pinned_client(&current).await?;
+            let response = client.get(current.clone()).send().await?;
+            if !response.status().is_redirection() {
+                break response;
+            }
+
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|location| current.join(location).ok());
+            let Some(next) = location else {
+                break response;
+            };
+            redirects += 1;
+            if redirects > MAX_REDIRECTS {
+                return Err(FetchError::TooManyRedirects);
+            }
+            debug!(url = %next, "Following redirect");
+            current = next;
+        };
+        Span::current().record("http.status", response.status().as_u16());
+        if !response.status().is_success() {
+            return Err(FetchError::Status(response.status().as_u16()));
+        }
+
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("text/html")
+            .to_string();
+        if !content_type.starts_with("text/") && !content_type.contains("html") {
+            return Err(FetchError::UnsupportedContent(content_type));
+        }
+
+        // Read at most MAX_PAGE_BYTES, however big the page says it is
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            let room = MAX_PAGE_BYTES - bytes.len();
+            bytes.extend_from_slice(&chunk[..chunk.len().min(room)]);
+            if bytes.len() >= MAX_PAGE_BYTES {
+                break;
+            }
+        }
+        let body = String::from_utf8_lossy(&bytes).into_owned();
+        let text = if content_type.contains("html") {
+            html_to_text(&body)
+        } else {
+            body
+        };
+
+        Ok(truncate_chars(&text, self.max_chars))
+    }
+}
+
+impl Tool for FetchPageTool {
+    const NAME: &'static str = "fetch_page";
+
+    type Args = FetchArgs;
+    type Output = String;
+    type Error = FetchError;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Download a web page and return its readable text (HTML stripped, \
+                          truncated for long pages)."
+                .to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "url": {
+                        "type": "string",
+                        "description": "Absolute http(s) URL of the page"
+                    }
+                },
+                "required": ["url"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        debug!(url = %args.url, "Fetch page tool called");
+        if let Some(left) = &self.pages_left {
+            left.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .map_err(|_| FetchError::PageLimit)?;
+        }
+        if let Some(activity) = &self.activity {
+            activity.report(ActivityStep::Fetching {
+                url: args.url.clone(),
+            });
+        }
+
+        let outcome = self.fetch(&args.url).await;
+
+        if let Some(activity) = &self.activity {
+            activity.report(ActivityStep::Fetched {
+                url: args.url,
+                chars: outcome.as_ref().map(|text| text.chars().count()).unwrap_or(0),
+                error: outcome.as_ref().err().map(ToString::to_string),
+            });
+        }
+        outcome
+    }
+}
+
+// =============================================================================
+// DOCUMENT SEARCH TOOL
+// =============================================================================
+/// Passages returned per document search.
+const DOCUMENT_HITS: usize = 4;
+
+/// Characters of a passage kept as its source card's snippet.
+const DOCUMENT_SNIPPET_CHARS: usize = 240;
+
+/// Errors from searching attached documents. Searching memory can't really
+/// fail, but Rig's `Tool` trait asks for an error type.
+#[derive(Error, Debug)]
+pub enum DocumentSearchError {
+    #[error("No documents are attached")]
+    NoDocuments,
+}
+
+/// A tool that searches the files attached to the conversation.
+///
+/// Matching passages are numbered with the run's web results, so the model
+/// cites them the same way and the chat shows them as source cards.
+#[derive(Debug, Clone)]
+pub struct DocumentSearchTool {
+    documents: Vec<Arc<Document>>,
+
+    /// Where calls made by the agent are recorded, if anywhere
+    call_log: Option<ToolCallLog>,
+
+    /// Where searches are reported as they happen, if anywhere
+    activity: Option<ActivitySink>,
+}
+
+impl DocumentSearchTool {
+    pub fn new(documents: Vec<Arc<Document>>) -> Self {
+        Self {
+            documents,
+            call_log: None,
+            activity: None,
+        }
+    }
+
+    /// Record every call made through the `Tool` trait into `log`.
+    pub fn with_call_log(mut self, log: ToolCallLog) -> Self {
+        self.call_log = Some(log);
+        self
+    }
+
+    /// Report every search to `activity` once it is done.
+    pub fn with_activity(mut self, activity: ActivitySink) -> Self {
+        self.activity = Some(activity);
+        self
+    }
+}
+
+impl Tool for DocumentSearchTool {
+    const NAME: &'static str = "search_documents";
+
+    type Args = SearchArgs;
+    type Output = String;
+    type Error = DocumentSearchError;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        let names: Vec<&str> = self.documents.iter().map(|d| d.name.as_str()).collect();
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: format!(
+                "Search the documents the user attached ({}) and return the passages \
+                 that best match the query.",
+                names.join(", ")
+            ),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "Keywords to look for in the documents"
+                    }
+                },
+                "required": ["query"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        debug!(query_hash = %query_hash(&args.query), "Document search tool called");
+        if self.documents.is_empty() {
+            return Err(DocumentSearchError::NoDocuments);
+        }
+
+        let hits = documents::search(&self.documents, &args.query, DOCUMENT_HITS);
+        let results: Vec<SearchResult> = hits
+            .iter()
+            .map(|hit| SearchResult {
+                title: hit.title.clone(),
+                url: hit.url.clone(),
+                snippet: match hit.text.char_indices().nth(DOCUMENT_SNIPPET_CHARS) {
+                    Some((end, _)) => format!("{}…", hit.text[..end].trim_end()),
+                    None => hit.text.clone(),
+                },
+            })
+            .collect();
+
+        if let Some(activity) = &self.activity {
+            activity.report(ActivityStep::SearchedDocuments {
+                query: args.query.clone(),
+                results: results.len(),
+            });
+        }
+        if let Some(log) = &self.call_log {
+            let record = ToolCallRecord {
+                tool: Self::NAME.to_string(),
+                query: args.query.clone(),
+                results: results.clone(),
+                error: None,
+            };
+            log.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(record);
+        }
+
+        if hits.is_empty() {
+            return Ok(format!("No passages in the attached documents match: {}", args.query));
+        }
+
+        let numbers = source_numbers(self.call_log.as_ref(), &results);
+        let formatted: String = hits
+            .iter()
+            .zip(numbers)
+            .map(|(hit, n)| format!("[{}] **{}**\n{}\n", n, hit.title, hit.text))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Ok(format!(
+            "## Passages from attached documents for: {}\n\n{}",
+            args.query, formatted
+        ))
+    }
+}
+
+/// Only fetch public web pages: no other schemes, and no loopback or
+/// private addresses (the server must not become a proxy into its own
+/// network). Host names are checked after resolving, in `pinned_client`.
+pub fn is_public_http_url(url: &reqwest::Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+
+    // IPv6 literals come back in brackets, e.g. `[::1]`
+    match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) => is_public_ip(ip),
+        Err(_) => {
+            let domain = host.trim_end_matches('.');
+            domain != "localhost" && !domain.ends_with(".localhost") && !domain.ends_with(".local")
+        }
+    }
+}
+
+/// Whether `ip` is on the public internet: not loopback, private,
+/// link-local (cloud metadata lives at 169.254.169.254), carrier-grade NAT,
+/// multicast or reserved - also when wrapped in an IPv6 address like
+/// `::ffff:127.0.0.1`.
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4() {
+            // IPv4-mapped (::ffff:a.b.c.d) and IPv4-compatible (::a.b.c.d)
+            Some(v4) => is_public_ipv4(v4),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || a == 0
+        || (a == 100 && (64..128).contains(&b)) // 100.64.0.0/10, carrier-grade NAT
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || (first & 0xfe00) == 0xfc00 // fc00::/7, unique local
+        || (first & 0xffc0) == 0xfe80) // fe80::/10, link-local
+}
+
+/// A client for one request to `url`, connecting only to the addresses
+/// its host resolved to - after checking every one of them is public.
+/// Redirects are not followed, so the caller can check each hop.
+async fn pinned_client(url: &reqwest::Url) -> Result<reqwest::Client, FetchError> {
+    if !is_public_http_url(url) {
+        return Err(FetchError::InvalidUrl(url.to_string()));
+    }
+    let host = url.host_str().unwrap_or_default();
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let mut builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(20))
+        .user_agent("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
+        .redirect(reqwest::redirect::Policy::none());
+
+    // IP literals were checked above; names are resolved and pinned
+    if host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().is_err() {
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|_| FetchError::Unresolvable(host.to_string()))?
+            .collect();
+        if addrs.is_empty() {
+            return Err(FetchError::Unresolvable(host.to_string()));
+        }
+        if !addrs.iter().all(|addr| is_public_ip(addr.ip())) {
+            return Err(FetchError::InvalidUrl(url.to_string()));
+        }
+        builder = builder.resolve_to_addrs(host, &addrs);
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Readable text of an HTML document.
+///
+/// A deliberately small converter: drops `<script>`/`<style>` blocks and
+/// every tag, decodes the common entities and collapses whitespace, with
+/// a line break for block elements so paragraphs stay apart.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len() / 2);
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let tag_end = rest.find('>').map(|i| i + 1).unwrap_or(rest.len());
+        let tag = rest[..tag_end].to_ascii_lowercase();
+        rest = &rest[tag_end..];
+
+        // Skip the contents of non-text elements entirely
+        for skipped in ["script", "style", "noscript", "svg"] {
+            if tag.starts_with(&format!("<{}", skipped)) {
+                let close = format!("</{}", skipped);
+                let end = rest.to_ascii_lowercase().find(&close).unwrap_or(rest.len());
+                rest = &rest[end..];
+            }
+        }
+
+        let block = ["<p", "</p", "<br", "<div", "</div", "<li", "<h", "</h", "<tr", "</tr"];
+        if block.iter().any(|b| tag.starts_with(b)) {
+            text.push('\n');
+        } else {
+            text.push(' ');
+        }
+    }
+    text.push_str(rest);
+
+    let decoded = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+
+    decoded
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// At most `max` characters of `text`, marking the cut.
+pub fn truncate_chars(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}\n\n[truncated]", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
 // =============================================================================
 // UNIT TESTS
 // =============================================================================
@@ -448,4 +1150,118 @@ mod tests {
         let json = serde_json::to_string(&result).unwrap();
         assert!(json.contains("Test"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_html_to_text() {
+        let html = "<html><head><style>p { color: red }</style><script>alert(1)</script></head>\
+                    <body><h1>Title</h1><p>Fish &amp; chips</p><p>Second   line</p></body></html>";
+        assert_eq!(html_to_text(html), "Title\nFish & chips\nSecond line");
+    }
+
+    #[test]
+    fn test_fetch_rejects_private_urls() {
+        let url = |s: &str| reqwest::Url::parse(s).unwrap();
+        assert!(is_public_http_url(&url("https://www.rust-lang.org/learn")));
+        assert!(!is_public_http_url(&url("file:///etc/passwd")));
+        assert!(!is_public_http_url(&url("http://localhost:11434/api/tags")));
+        assert!(!is_public_http_url(&url("http://192.168.1.1/")));
+        assert!(!is_public_http_url(&url("http://[::1]:8080/")));
+        assert!(!is_public_http_url(&url("http://169.254.169.254/latest/meta-data/")));
+        assert!(!is_public_http_url(&url("http://100.64.0.1/")));
+        assert!(!is_public_http_url(&url("http://[fe80::1]/")));
+
+        // IPv4 wrapped in IPv6
+        assert!(!is_public_http_url(&url("http://[::ffff:7f00:1]/")));
+        assert!(!is_public_http_url(&url("http://[::ffff:169.254.169.254]/")));
+        assert!(is_public_http_url(&url("http://[2606:4700::1111]/")));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_rejects_mapped_private_addresses() {
+        let err = FetchPageTool::new(100).fetch("http://[::ffff:10.0.0.1]/").await;
+        assert!(matches!(err, Err(FetchError::InvalidUrl(_))));
+    }
+
+    #[test]
+    fn test_truncate_chars() {
+        assert_eq!(truncate_chars("héllo", 10), "héllo");
+        assert_eq!(truncate_chars("héllo", 2), "hé\n\n[truncated]");
+    }
+
+    #[test]
+    fn test_source_numbers_follow_the_run() {
+        let result = |url: &str| SearchResult {
+            title: url.to_string(),
+            url: url.to_string(),
+            snippet: String::new(),
+        };
+        let log = ToolCallLog::default();
+        let tool = WebSearchTool::new(5).with_call_log(log.clone());
+
+        for urls in [["https://a.dev", "https://b.dev"], ["https://b.dev", "https://c.dev"]] {
+            log.lock().unwrap().push(ToolCallRecord {
+                tool: "web_search".to_string(),
+                query: "q".to_string(),
+                results: urls.iter().map(|url| result(url)).collect(),
+                error: None,
+            });
+        }
+
+        // The second search's results keep the numbers of the whole run
+        let second = [result("https://b.dev"), result("https://c.dev")];
+        assert_eq!(tool.source_numbers(&second), [2, 3]);
+        assert_eq!(WebSearchTool::new(5).source_numbers(&second), [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_document_search_numbers_passages_with_the_run() {
+        let log = ToolCallLog::default();
+        log.lock().unwrap().push(ToolCallRecord {
+            tool: "web_search".to_string(),
+            query: "q".to_string(),
+            results: vec![SearchResult {
+                title: "Tokio".to_string(),
+                url: "https://tokio.rs".to_string(),
+                snippet: String::new(),
+            }],
+            error: None,
+        });
+
+        let document = Arc::new(Document::new("notes.md", "Tokio uses a work-stealing scheduler."));
+        let tool = DocumentSearchTool::new(vec![document]).with_call_log(log.clone());
+        let output = tool.call(SearchArgs { query: "scheduler".to_string() }).await.unwrap();
+
+        assert!(output.contains("[2] **notes.md (part 1)**"));
+        assert_eq!(log.lock().unwrap()[1].tool, "search_documents");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_reports_activity() {
+        let steps = Arc::new(Mutex::new(Vec::new()));
+        let sink_steps = steps.clone();
+        let tool = FetchPageTool::new(100).with_activity(ActivitySink::new(move |step| {
+            sink_steps.lock().unwrap().push(step);
+        }));
+
+        let url = "http://localhost/admin".to_string();
+        assert!(tool.call(FetchArgs { url: url.clone() }).await.is_err());
+
+        let steps = steps.lock().unwrap();
+        assert_eq!(steps[0], ActivityStep::Fetching { url: url.clone() });
+        assert!(matches!(
+            &steps[1],
+            ActivityStep::Fetched { chars: 0, error: Some(_), .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_stops_at_the_page_limit() {
+        let tool = FetchPageTool::new(100).with_page_limit(1);
+        let args = || FetchArgs { url: "http://localhost/admin".to_string() };
+
+        // The first read is attempted (and refused for its URL)...
+        assert!(matches!(tool.call(args()).await, Err(FetchError::InvalidUrl(_))));
+        // ...the second isn't, by this tool or a clone of it
+        assert!(matches!(tool.clone().call(args()).await, Err(FetchError::PageLimit)));
+    }
+}